@@ -1,18 +1,22 @@
 //! API Service holds the MediaWiki API object.
 
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashMap, future::Future, sync::Arc, time::Duration};
 
 use mediawiki::{api::Api, media_wiki_error::MediaWikiError, title::Title};
 use serde_json::Value;
-use tokio::{sync::{Mutex, RwLock}, task::JoinHandle};
+use tokio::{sync::{Mutex, RwLock}, task::JoinHandle, time::Instant};
 use tracing::{event, Level, span, Instrument, instrument};
 use crate::types::{LoginCredential, SiteProfile};
 
 #[derive(Debug)]
 pub enum APIServiceError {
     NoAPI,
+    /// An edit was refused, as the service is in read-only mode.
+    ReadOnly,
     Client(MediaWikiError),
     Server(Value),
+    /// No response within the per-request timeout.
+    Timeout(Duration),
 }
 
 // impl std::error::Error for APIServiceError {}
@@ -28,20 +32,133 @@ impl core::fmt::Display for APIServiceError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::NoAPI => f.write_str("no API object present in the service"),
+            Self::ReadOnly => f.write_str("the service is in read-only mode, edits are not allowed"),
             Self::Client(e) => e.fmt(f),
             Self::Server(e) => e.fmt(f),
+            Self::Timeout(t) => f.write_fmt(format_args!("no response within {} seconds", t.as_secs())),
         }
     }
 }
 
+/// Maximum number of values in a multi-value parameter (e.g. `titles=`) for ordinary accounts.
+pub const TITLE_BATCH_SIZE_LOW: usize = 50;
+/// Maximum number of values in a multi-value parameter for accounts with the `apihighlimits` right (usually bots and sysops).
+pub const TITLE_BATCH_SIZE_HIGH: usize = 500;
+
+/// Default `maxlag` in seconds, sent with every request.
+/// The `mediawiki` crate waits and retries by itself whenever the server reports a lagging database.
+pub const DEFAULT_MAXLAG: u64 = 5;
+
+/// Default timeout in seconds of a single request to the API.
+/// A call that follows continuation sends one request per continuation, each with its own timeout.
+pub const DEFAULT_REQUEST_TIMEOUT: u64 = 300;
+
+/// How many times a request is retried after logging in again, when the session has expired.
+const RELOGIN_RETRY: usize = 1;
+
+/// Whether the server rejected a request because the login session has expired.
+fn is_session_expired(errobj: &Value) -> bool {
+    matches!(errobj["code"].as_str(), Some("assertuserfailed" | "assertbotfailed" | "assertnameduserfailed" | "notloggedin"))
+}
+
+/// Ask the API which multi-value limit applies to the logged-in account.
+/// Falls back to `TITLE_BATCH_SIZE_LOW` if the rights cannot be determined.
+async fn detect_title_batch_size(api: &Api) -> usize {
+    let params = api.params_into(&[
+        ("action", "query"),
+        ("meta", "userinfo"),
+        ("uiprop", "rights"),
+        ("format", "json"),
+        ("formatversion", "2"),
+    ]);
+    match api.get_query_api_json(&params).await {
+        Ok(resp) => {
+            let high = resp["query"]["userinfo"]["rights"].as_array()
+                .map(|rights| rights.iter().any(|r| r.as_str() == Some("apihighlimits")))
+                .unwrap_or(false);
+            if high { TITLE_BATCH_SIZE_HIGH } else { TITLE_BATCH_SIZE_LOW }
+        },
+        Err(e) => {
+            event!(Level::WARN, error = ?e, "cannot fetch user rights, assume low API limits");
+            TITLE_BATCH_SIZE_LOW
+        },
+    }
+}
+
+/// Merges a continued response into what was gathered so far, the same way the `mediawiki` crate does:
+/// objects are merged key by key, lists are appended, and anything else is replaced.
+pub(crate) fn json_merge(acc: &mut Value, res: Value) {
+    match (acc, res) {
+        (Value::Object(acc), Value::Object(res)) => {
+            for (k, v) in res {
+                json_merge(acc.entry(k).or_insert(Value::Null), v);
+            }
+        },
+        (Value::Array(acc), Value::Array(res)) => acc.extend(res),
+        (acc, res) => *acc = res,
+    }
+}
+
+/// The number of results gathered so far, counted the same way as the `mediawiki` crate does to stop at a limit.
+pub(crate) fn query_result_count(acc: &Value) -> usize {
+    acc["query"].as_object().map(|query| query.values().filter_map(|v| v.as_array()).map(|v| v.len()).sum()).unwrap_or(0)
+}
+
+/// The parameters to send along to continue a query, taken from the `continue` object of its last response.
+/// Empty if the query is complete.
+pub(crate) fn continue_params(res: &Value) -> HashMap<String, String> {
+    res["continue"].as_object()
+        .map(|next| next.iter().map(|(k, v)| (k.clone(), v.as_str().map(|v| v.to_string()).unwrap_or_else(|| v.to_string()))).collect())
+        .unwrap_or_default()
+}
+
+/// Whether `post_edit` lets edits through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EditMode {
+    Allowed,
+    /// Set by the site profile: edits are logged, and reported as skipped.
+    Logged,
+    /// Set by `set_read_only`: edits fail with `APIServiceError::ReadOnly`.
+    Refused,
+}
+
+/// Spaces out requests evenly, so that no more than a given number of requests are sent per second.
+/// Requests wait for their turn in the order they arrive.
+#[derive(Debug)]
+struct RequestPacer {
+    interval: Duration,
+    next: Mutex<Instant>,
+}
+
+impl RequestPacer {
+    fn new(per_second: f64) -> Self {
+        RequestPacer {
+            interval: Duration::from_secs_f64(1.0 / per_second),
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Book the next free slot, and return when it starts.
+    async fn reserve(&self) -> Instant {
+        let mut next = self.next.lock().await;
+        let slot = std::cmp::max(*next, Instant::now());
+        *next = slot + self.interval;
+        slot
+    }
+}
+
 #[derive(Debug)]
 pub struct APIService {
     login: Mutex<Option<LoginCredential>>,
     profile: Mutex<Option<SiteProfile>>,
+    edit_mode: RwLock<EditMode>,
 
     api: RwLock<Option<Api>>,
     network_lock: Arc<Mutex<()>>,
     csrf: RwLock<String>,
+    title_batch_size: RwLock<usize>,
+    pacer: RwLock<Option<RequestPacer>>,
+    request_timeout: RwLock<Option<Duration>>,
 
     keepalivehandle: Mutex<Option<JoinHandle<()>>>,
 }
@@ -53,9 +170,13 @@ impl APIService {
         APIService {
             login: Mutex::new(None),
             profile: Mutex::new(None),
+            edit_mode: RwLock::new(EditMode::Allowed),
             api: RwLock::new(None),
             network_lock: Arc::new(Mutex::new(())),
             csrf: RwLock::new("".to_string()),
+            title_batch_size: RwLock::new(TITLE_BATCH_SIZE_LOW),
+            pacer: RwLock::new(None),
+            request_timeout: RwLock::new(Some(Duration::from_secs(DEFAULT_REQUEST_TIMEOUT))),
             keepalivehandle: Mutex::new(None),
         }
     }
@@ -65,19 +186,74 @@ impl APIService {
             let mut login_lock = self.login.lock().await;
             *login_lock = Some(login);
         }
+        {
+            let mut pacer_lock = self.pacer.write().await;
+            *pacer_lock = profile.ratelimit.filter(|r| *r > 0.0 && r.is_finite()).map(RequestPacer::new);
+        }
+        {
+            let mut timeout_lock = self.request_timeout.write().await;
+            *timeout_lock = Some(profile.requesttimeout.unwrap_or(DEFAULT_REQUEST_TIMEOUT)).filter(|t| *t > 0).map(Duration::from_secs);
+        }
+        if profile.readonly {
+            let mut edit_mode_lock = self.edit_mode.write().await;
+            // a hard read-only mode set before stays
+            if *edit_mode_lock == EditMode::Allowed {
+                *edit_mode_lock = EditMode::Logged;
+            }
+        }
         {
             let mut profile_lock = self.profile.lock().await;
             *profile_lock = Some(profile);
         }
     }
 
+    /// Wait for the rate limit, if the site profile sets one. Every request sent to the API waits here first.
+    async fn pace(&self) {
+        // the slot is booked under the lock, but waited for outside of it, so that a new profile can be set meanwhile
+        let slot = match &*self.pacer.read().await {
+            Some(pacer) => pacer.reserve().await,
+            None => return,
+        };
+        tokio::time::sleep_until(slot).await;
+    }
+
+    /// Wait for `request`, but give up once the per-request timeout of the site profile has passed.
+    /// A stalled connection then fails with `APIServiceError::Timeout`, which the solver retries like other network failures.
+    async fn within_timeout<F: Future<Output = Result<Value, MediaWikiError>>>(&self, request: F) -> Result<Value, APIServiceError> {
+        let timeout = *self.request_timeout.read().await;
+        match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, request).await.map_err(|_| APIServiceError::Timeout(timeout))?.map_err(APIServiceError::from),
+            None => Ok(request.await?),
+        }
+    }
+
+    /// Block or allow all edits. In read-only mode, `post_edit` fails without contacting the API.
+    pub async fn set_read_only(&self, read_only: bool) {
+        let mut edit_mode_lock = self.edit_mode.write().await;
+        *edit_mode_lock = if read_only { EditMode::Refused } else { EditMode::Allowed };
+    }
+
     /// Send a request via GET
     pub async fn get(&self, params: &HashMap<String, String>) -> Result<Value, APIServiceError> {
+        let mut attempt: usize = 0;
+        loop {
+            match self.get_once(params).await {
+                Err(APIServiceError::Server(e)) if attempt < RELOGIN_RETRY && is_session_expired(&e) => {
+                    attempt += 1;
+                    self.relogin().await;
+                },
+                result => return result,
+            }
+        }
+    }
+
+    async fn get_once(&self, params: &HashMap<String, String>) -> Result<Value, APIServiceError> {
+        self.pace().await;
         let api = self.api.read().await;
         if let Some(api) = &*api {
             let mut params = params.clone();
             self.param_decorate(&mut params).await;
-            let resp = api.get_query_api_json(&params).await?;
+            let resp = self.within_timeout(api.get_query_api_json(&params)).await?;
             if let Some(errobj) = resp.get("error") {
                 Err(APIServiceError::Server(errobj.clone()))
             } else {
@@ -88,21 +264,25 @@ impl APIService {
         }
     }
 
-    /// Send a request via GET
+    /// Send a request via GET, and follow its continuation until `max` results are gathered, or every result if `None`.
+    /// Each continued request is sent by itself, so it waits for the rate limit and has its own timeout like any other request.
     pub async fn get_limit(&self, params: &HashMap<String, String>, max: Option<usize>) -> Result<Value, APIServiceError> {
-        let api = self.api.read().await;
-        if let Some(api) = &*api {
-            let mut params = params.clone();
-            self.param_decorate(&mut params).await;
-            let resp = api.get_query_api_json_limit(&params, max).await?;
-            if let Some(errobj) = resp.get("error") {
-                Err(APIServiceError::Server(errobj.clone()))
-            } else {
-                Ok(resp)
+        let mut acc = serde_json::json!({});
+        let mut cont: HashMap<String, String> = HashMap::new();
+        loop {
+            let mut cont_params = params.clone();
+            cont_params.extend(cont.drain());
+            let res = self.get(&cont_params).await?;
+            cont = continue_params(&res);
+            json_merge(&mut acc, res);
+            if cont.is_empty() || max.map(|max| query_result_count(&acc) >= max).unwrap_or(false) {
+                break;
             }
-        } else {
-            Err(APIServiceError::NoAPI)
         }
+        if let Some(acc) = acc.as_object_mut() {
+            acc.remove("continue");
+        }
+        Ok(acc)
     }
 
     /// Send a request via GET
@@ -112,11 +292,25 @@ impl APIService {
 
     /// Send a request via POST
     pub async fn post(&self, params: &HashMap<String, String>) -> Result<Value, APIServiceError> {
+        let mut attempt: usize = 0;
+        loop {
+            match self.post_once(params).await {
+                Err(APIServiceError::Server(e)) if attempt < RELOGIN_RETRY && is_session_expired(&e) => {
+                    attempt += 1;
+                    self.relogin().await;
+                },
+                result => return result,
+            }
+        }
+    }
+
+    async fn post_once(&self, params: &HashMap<String, String>) -> Result<Value, APIServiceError> {
+        self.pace().await;
         let api = self.api.read().await;
         if let Some(api) = &*api {
             let mut params = params.to_owned();
             self.param_decorate(&mut params).await;
-            let resp = api.post_query_api_json(&params).await?;
+            let resp = self.within_timeout(api.post_query_api_json(&params)).await?;
             if let Some(errobj) = resp.get("error") {
                 Err(APIServiceError::Server(errobj.clone()))
             } else {
@@ -128,12 +322,96 @@ impl APIService {
     }
 
     pub async fn post_edit(&self, params: &HashMap<String, String>) -> Result<Value, APIServiceError> {
+        // Never let an edit through in read-only mode
+        let edit_mode = *self.edit_mode.read().await;
+        if edit_mode == EditMode::Refused {
+            return Err(APIServiceError::ReadOnly);
+        }
+        // the profile only asks for the edit to be logged, the caller is told that it was skipped
+        if edit_mode == EditMode::Logged {
+            event!(
+                Level::INFO,
+                title = params.get("title").map(|s| s.as_str()).unwrap_or_default(),
+                summary = params.get("summary").map(|s| s.as_str()).unwrap_or_default(),
+                length = params.get("text").map(|s| s.len()).unwrap_or_default(),
+                "read-only mode, edit not sent"
+            );
+            return Ok(serde_json::json!({"edit": {"result": "Skipped"}}));
+        }
         // Add an bot edit flag to params, if it does not exist
         let mut params = params.to_owned();
         if !params.contains_key("bot") && self.profile.lock().await.as_ref().unwrap().botflag {
             params.insert("bot".to_string(), "1".to_string());
         }
-        self.post(&params).await
+        match self.post(&params).await {
+            // The cached token has expired, try once more with a fresh one
+            Err(APIServiceError::Server(e)) if e["code"].as_str() == Some("badtoken") => {
+                event!(Level::INFO, "csrf token expired, refreshing");
+                params.insert("token".to_string(), self.refresh_csrf().await?);
+                self.post(&params).await
+            },
+            result => result,
+        }
+    }
+
+    /// The `User-Agent` to identify the bot with, as set in the site profile or built from the account and contact
+    async fn user_agent(&self) -> String {
+        let (useragent, contact) = {
+            let lock = self.profile.lock().await;
+            (lock.as_ref().unwrap().useragent.clone(), lock.as_ref().unwrap().contact.clone())
+        };
+        if let Some(useragent) = useragent {
+            return useragent;
+        }
+        let username = {
+            let lock = self.login.lock().await;
+            lock.as_ref().unwrap().username.split('@').next().unwrap().to_string()
+        };
+        let mut about = format!("User:{}", username);
+        if let Some(contact) = contact {
+            about.push_str("; ");
+            about.push_str(&contact);
+        }
+        format!("{}/{} ({})", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"), about)
+    }
+
+    /// Log in again with the stored credential, after the session has expired
+    async fn relogin(&self) {
+        event!(Level::INFO, "session expired, re-login");
+        let mut api = self.api.write().await;
+        if let Some(api) = &mut *api {
+            self.login_api(api).await;
+        }
+    }
+
+    /// Log in on `api`, and refresh everything tied to the session
+    async fn login_api(&self, api: &mut Api) {
+        let (username, password) = {
+            let lock = self.login.lock().await;
+            (lock.as_ref().unwrap().username.clone(), lock.as_ref().unwrap().password.clone())
+        };
+        if let Err(e) = api.login(&username, &password).await {
+            event!(Level::WARN, error = ?e, "cannot log in");
+        }
+        if let Ok(csrf) = api.get_edit_token().await {
+            let mut self_csrf = self.csrf.write().await;
+            *self_csrf = csrf;
+        }
+        let mut self_batch_size = self.title_batch_size.write().await;
+        *self_batch_size = detect_title_batch_size(api).await;
+    }
+
+    /// Fetch a new csrf token, replacing the cached one
+    async fn refresh_csrf(&self) -> Result<String, APIServiceError> {
+        let mut api = self.api.write().await;
+        if let Some(api) = &mut *api {
+            let csrf = api.get_edit_token().await?;
+            let mut self_csrf = self.csrf.write().await;
+            *self_csrf = csrf.clone();
+            Ok(csrf)
+        } else {
+            Err(APIServiceError::NoAPI)
+        }
     }
 
     /// Get csrf token
@@ -142,6 +420,12 @@ impl APIService {
         (*self_csrf).clone()
     }
 
+    /// Get the maximum number of titles that can be sent in one `titles=` batch
+    pub async fn title_batch_size(&self) -> usize {
+        let self_batch_size = self.title_batch_size.read().await;
+        *self_batch_size
+    }
+
     pub fn get_lock(&self) -> Arc<Mutex<()>> {
         self.network_lock.clone()
     }
@@ -171,6 +455,22 @@ impl APIService {
         }
     }
 
+    /// The API endpoint of the site, as given in the site profile
+    pub async fn api_url(&self) -> Option<String> {
+        let profile = self.profile.lock().await;
+        profile.as_ref().map(|p| p.api.to_owned())
+    }
+
+    /// The content language code of the site, such as `en` or `zh`
+    pub async fn content_language(&self) -> Result<Option<String>, APIServiceError> {
+        let api = self.api.read().await;
+        if let Some(api) = &*api {
+            Ok(api.get_site_info_string("general", "lang").ok().map(|s| s.to_owned()))
+        } else {
+            Err(APIServiceError::NoAPI)
+        }
+    }
+
     /// Create a title from full name
     pub async fn title_new_from_full(&self, title: &str) -> Result<Title, APIServiceError> {
         let api = self.api.read().await;
@@ -223,20 +523,28 @@ impl APIService {
             let lock = self.profile.lock().await;
             lock.as_ref().unwrap().api.clone()
         };
+        let maxlag = {
+            let lock = self.profile.lock().await;
+            lock.as_ref().unwrap().maxlag.unwrap_or(DEFAULT_MAXLAG)
+        };
         let (username, password) = {
             let lock = self.login.lock().await;
             (lock.as_ref().unwrap().username.clone(), lock.as_ref().unwrap().password.clone())
         };
         let api_obj = Api::new(&api_url).await;
         if let Ok(mut api_obj) = api_obj {
-            api_obj.set_maxlag(Some(5));
+            api_obj.set_maxlag(Some(maxlag));
             api_obj.set_max_retry_attempts(3);
-            api_obj.set_user_agent(format!("Page List Bot / via User:{}", username.split('@').next().unwrap()));
+            api_obj.set_user_agent(self.user_agent().await);
             let _ = api_obj.login(&username, &password).await;
             if let Ok(csrf) = api_obj.get_edit_token().await {
                 let mut self_csrf = self.csrf.write().await;
                 *self_csrf = csrf;
             }
+            {
+                let mut self_batch_size = self.title_batch_size.write().await;
+                *self_batch_size = detect_title_batch_size(&api_obj).await;
+            }
             let mut api = self.api.write().await;
             *api = Some(api_obj);
         } else {
@@ -276,16 +584,7 @@ impl APIService {
                     if let Ok(response) = response {
                         if response["error"].as_object().is_some() {
                             event!(Level::INFO, "API expired, re-login");
-                            // re-login
-                            let (username, password) = {
-                                let lock = self.login.lock().await;
-                                (lock.as_ref().unwrap().username.clone(), lock.as_ref().unwrap().password.clone())
-                            };
-                            let _ = api.login(&username, &password).await;
-                            if let Ok(csrf) = api.get_edit_token().await {
-                                let mut self_csrf = self.csrf.write().await;
-                                *self_csrf = csrf;
-                            }
+                            self.login_api(api).await;
                         } else {
                             event!(Level::INFO, "API valid");
                         }
@@ -299,20 +598,28 @@ impl APIService {
                         let lock = self.profile.lock().await;
                         lock.as_ref().unwrap().api.clone()
                     };
+                    let maxlag = {
+                        let lock = self.profile.lock().await;
+                        lock.as_ref().unwrap().maxlag.unwrap_or(DEFAULT_MAXLAG)
+                    };
                     let (username, password) = {
                         let lock = self.login.lock().await;
                         (lock.as_ref().unwrap().username.clone(), lock.as_ref().unwrap().password.clone())
                     };
                     let api_obj = Api::new(&api_url).await;
                     if let Ok(mut api_obj) = api_obj {
-                        api_obj.set_maxlag(Some(5));
+                        api_obj.set_maxlag(Some(maxlag));
                         api_obj.set_max_retry_attempts(3);
-                        api_obj.set_user_agent(format!("Page List Bot / via User:{}", username.split('@').next().unwrap()));
+                        api_obj.set_user_agent(self.user_agent().await);
                         let _ = api_obj.login(&username, &password).await;
                         if let Ok(csrf) = api_obj.get_edit_token().await {
                             let mut self_csrf = self.csrf.write().await;
                             *self_csrf = csrf;
                         }
+                        {
+                            let mut self_batch_size = self.title_batch_size.write().await;
+                            *self_batch_size = detect_title_batch_size(&api_obj).await;
+                        }
                         *api = Some(api_obj);
                     } else {
                         event!(Level::WARN, error = ?api_obj.unwrap_err(), "cannot initiate API");
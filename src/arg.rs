@@ -4,6 +4,59 @@ pub fn build_argparse() -> Command<'static> {
     Command::new("Page List Bot")
         .about("Generate a list of wiki pages based on numerous criteria and set operations")
         .version(crate_version!())
+        .subcommand_negates_reqs(true)
+        .subcommand(
+            Command::new("validate")
+                .about("Parse and optimize a query, and print the result without running it")
+                .arg(
+                    Arg::new("query-file")
+                        .long("query-file")
+                        .required(true)
+                        .takes_value(true)
+                        .help("Path to the file with the query")
+                )
+        )
+        .subcommand(
+            Command::new("run")
+                .about("Run a query once on a site, and print the result instead of writing it to the wiki")
+                .args(&[
+                    Arg::new("query-file")
+                        .long("query-file")
+                        .required(true)
+                        .takes_value(true)
+                        .help("Path to the file with the query"),
+                    Arg::new("login")
+                        .long("login")
+                        .required(true)
+                        .takes_value(true)
+                        .help("Path to the JSON file with username and password"),
+                    Arg::new("site")
+                        .long("site")
+                        .required(true)
+                        .takes_value(true)
+                        .help("Path to the JSON file with the website's information"),
+                    Arg::new("profile")
+                        .long("profile")
+                        .required(true)
+                        .takes_value(true)
+                        .help("The specific site profile in site information file to use"),
+                    Arg::new("limit")
+                        .long("limit")
+                        .takes_value(true)
+                        .default_value("-1")
+                        .help("The default limit of each step of the query. A negative value means no limit"),
+                    Arg::new("format")
+                        .long("format")
+                        .takes_value(true)
+                        .possible_values(["text", "json"])
+                        .default_value("text")
+                        .help("Print one title per line, or a JSON object like the JSON output"),
+                    Arg::new("timings")
+                        .long("timings")
+                        .takes_value(false)
+                        .help("Also print the register, kind, result size and time taken of every instruction to stderr"),
+                ])
+        )
         .args(&[
             Arg::new("login")
                 .long("login")
@@ -19,6 +72,51 @@ pub fn build_argparse() -> Command<'static> {
                 .long("profile")
                 .required(true)
                 .takes_value(true)
-                .help("The specific site profile in site information file to use")
+                .multiple_occurrences(true)
+                .help("The specific site profile in site information file to use. Repeat it to run several sites at once"),
+            Arg::new("state-dir")
+                .long("state-dir")
+                .takes_value(true)
+                .help("Directory to save query checkpoints in. If set, interrupted queries resume from the last checkpoint"),
+            Arg::new("cache-ttl")
+                .long("cache-ttl")
+                .takes_value(true)
+                .default_value("60")
+                .validator(|s| s.parse::<u64>())
+                .help("Minutes to keep query results in a cache under the state directory, so that queries run within that time reuse them"),
+            Arg::new("no-cache")
+                .long("no-cache")
+                .takes_value(false)
+                .help("Do not reuse or keep query results across queries. Interrupted queries still resume from their checkpoints"),
+            Arg::new("output-dir")
+                .long("output-dir")
+                .takes_value(true)
+                .help("Directory to write outputs whose target starts with file:// in, under a subdirectory named after the profile. Without it, such outputs are skipped"),
+            Arg::new("read-only")
+                .long("read-only")
+                .takes_value(false)
+                .help("Never edit any page. Queries still run, but every edit is refused"),
+            Arg::new("trigger-file")
+                .long("trigger-file")
+                .takes_value(true)
+                .default_value("plbot.trigger")
+                .help("File with the ids of the tasks to run right away, one per line and optionally prefixed by the profile, read on SIGUSR1"),
+            Arg::new("log-format")
+                .long("log-format")
+                .takes_value(true)
+                .possible_values(["text", "json"])
+                .default_value("text")
+                .help("Write the log file as human-readable lines, or as one JSON object per line"),
+            Arg::new("log-rotation")
+                .long("log-rotation")
+                .takes_value(true)
+                .possible_values(["hourly", "daily"])
+                .default_value("daily")
+                .help("How often to start a new log file"),
+            Arg::new("log-keep")
+                .long("log-keep")
+                .takes_value(true)
+                .validator(|s| s.parse::<usize>())
+                .help("The number of log files to keep. Older ones are removed at startup and at every rotation. Without it, no log file is removed")
         ])
 }
\ No newline at end of file
@@ -0,0 +1,103 @@
+//! Subcommands that work on a single query from the command line, instead of running the bot.
+
+use std::fs;
+
+use clap::ArgMatches;
+
+use crate::apiservice::APIService;
+use crate::parser::{self, DepthMergePolicy};
+
+/// Parse and optimize the query in `--query-file`, and print the instructions, or the error.
+/// Returns whether the query is valid.
+pub fn validate(args: &ArgMatches) -> bool {
+    let query_file = args.value_of("query-file").unwrap();
+    let src = match fs::read_to_string(query_file) {
+        Ok(src) => src,
+        Err(err) => {
+            eprintln!("cannot open query file: {}", err);
+            return false;
+        },
+    };
+    match parser::parse(&src, DepthMergePolicy::default()) {
+        Ok(query) => {
+            for inst in query.0.iter() {
+                println!("{:?}", inst);
+            }
+            println!("result: {:?}", query.1);
+            println!("description: {}", parser::describe(&query));
+            true
+        },
+        Err(err) => {
+            eprintln!("{}", err);
+            false
+        },
+    }
+}
+
+/// Run the query in `--query-file` on the site of `--profile`, and print the titles sorted by namespace and title, and their number.
+/// Nothing is written to the wiki. Returns whether the query succeeds.
+pub async fn run(args: &ArgMatches) -> bool {
+    let query_file = args.value_of("query-file").unwrap();
+    let src = match fs::read_to_string(query_file) {
+        Ok(src) => src,
+        Err(err) => {
+            eprintln!("cannot open query file: {}", err);
+            return false;
+        },
+    };
+    let query = match parser::parse(&src, DepthMergePolicy::default()) {
+        Ok(query) => query,
+        Err(err) => {
+            eprintln!("{}", err);
+            return false;
+        },
+    };
+    let limit: i64 = match args.value_of("limit").unwrap().parse() {
+        Ok(limit) => limit,
+        Err(_) => {
+            eprintln!("invalid limit");
+            return false;
+        },
+    };
+    let (_, profile, login) = match crate::read_profiles(args) {
+        Ok(mut profiles) => profiles.pop().unwrap(),
+        Err(err) => {
+            eprintln!("{}", err);
+            return false;
+        },
+    };
+    let api: &'static APIService = Box::leak(Box::new(APIService::new()));
+    // never edit, whatever the profile says
+    api.setup(login, profile).await;
+    api.set_read_only(true).await;
+    api.try_init().await;
+    let solved = if args.is_present("timings") {
+        crate::solver::solve_api_with_metrics(api, &query, limit, None).await.map(|(titles, timings)| {
+            for t in timings.iter() {
+                eprintln!("{}\t{}\t{}\t{:.3}s", t.dest, t.kind, t.size, t.elapsed.as_secs_f64());
+            }
+            titles
+        })
+    } else {
+        crate::solver::solve_api(api, &query, limit, None).await
+    };
+    let mut titles = match solved {
+        Ok(titles) => Vec::from_iter(titles),
+        Err(err) => {
+            eprintln!("solve failure: {:?}", err);
+            return false;
+        },
+    };
+    titles.sort_by(|a, b| a.namespace_id().cmp(&b.namespace_id()).then_with(|| a.pretty().cmp(b.pretty())));
+    if args.value_of("format") == Some("json") {
+        println!("{}", crate::routine::make_json_value(api, &titles, parser::is_count(&query)).await);
+    } else {
+        if !parser::is_count(&query) {
+            for t in titles.iter() {
+                println!("{}", crate::routine::full_title(api, t).await);
+            }
+        }
+        println!("total: {}", titles.len());
+    }
+    true
+}
@@ -0,0 +1,60 @@
+//! Loading the site information file and the login file.
+
+use std::fs;
+
+use serde_json::Value;
+
+use crate::types::{LoginCredential, SiteProfile};
+
+/// Everything that can be wrong with the config files, with enough detail to fix it.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// A config file cannot be read.
+    Read { file: String, error: std::io::Error },
+    /// A config file is not valid JSON.
+    Parse { file: String, error: serde_json::Error },
+    /// The site information file has no such profile.
+    NoProfile { file: String, profile: String },
+    /// A profile is missing a required field, has a field of the wrong type, or has an unknown field.
+    BadProfile { file: String, profile: String, error: serde_json::Error },
+    /// The login file has no credential named by the profile.
+    NoLogin { file: String, profile: String, login: String },
+    /// A login credential is missing a required field, or has an unknown field.
+    BadLogin { file: String, login: String, error: serde_json::Error },
+}
+
+impl std::error::Error for ConfigError {}
+
+impl core::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Read { file, error } => write!(f, "cannot read \"{}\": {}", file, error),
+            Self::Parse { file, error } => write!(f, "\"{}\" is not valid JSON: {}", file, error),
+            Self::NoProfile { file, profile } => write!(f, "site profile \"{}\" is not found in \"{}\"", profile, file),
+            Self::BadProfile { file, profile, error } => write!(f, "site profile \"{}\" in \"{}\" is invalid: {}", profile, file, error),
+            Self::NoLogin { file, profile, login } => write!(f, "login credential \"{}\", used by site profile \"{}\", is not found in \"{}\"", login, profile, file),
+            Self::BadLogin { file, login, error } => write!(f, "login credential \"{}\" in \"{}\" is invalid: {}", login, file, error),
+        }
+    }
+}
+
+/// Read a JSON file.
+fn read_json(file: &str) -> Result<Value, ConfigError> {
+    let content = fs::read_to_string(file).map_err(|error| ConfigError::Read { file: file.to_owned(), error })?;
+    serde_json::from_str(&content).map_err(|error| ConfigError::Parse { file: file.to_owned(), error })
+}
+
+/// Read the site profiles `profiles` from the site information file `site_file`, along with their login credentials from `login_file`.
+pub fn load_profiles<'a>(site_file: &str, login_file: &str, profiles: impl IntoIterator<Item = &'a str>) -> Result<Vec<(String, SiteProfile, LoginCredential)>, ConfigError> {
+    let sites = read_json(site_file)?;
+    let logins = read_json(login_file)?;
+    profiles.into_iter().map(|name| {
+        let profile = sites.get(name).ok_or_else(|| ConfigError::NoProfile { file: site_file.to_owned(), profile: name.to_owned() })?;
+        let profile: SiteProfile = serde_json::from_value(profile.clone())
+            .map_err(|error| ConfigError::BadProfile { file: site_file.to_owned(), profile: name.to_owned(), error })?;
+        let login = logins.get(&profile.login).ok_or_else(|| ConfigError::NoLogin { file: login_file.to_owned(), profile: name.to_owned(), login: profile.login.clone() })?;
+        let login: LoginCredential = serde_json::from_value(login.clone())
+            .map_err(|error| ConfigError::BadLogin { file: login_file.to_owned(), login: profile.login.clone(), error })?;
+        Ok((name.to_owned(), profile, login))
+    }).collect()
+}
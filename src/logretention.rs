@@ -0,0 +1,41 @@
+//! `tracing-appender` never removes old log files, so they are pruned here.
+
+use std::{fs, path::Path, time::Duration};
+
+use tracing::{event, Level};
+
+/// Remove all but the newest `keep` files in `dir` named `prefix.<date>`.
+/// The date suffixes written by `tracing-appender` sort by time, so the newest files sort last.
+pub fn prune(dir: &Path, prefix: &str, keep: usize) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) => {
+            event!(Level::WARN, dir = %dir.display(), "cannot list log directory: {}", err);
+            return;
+        },
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|e| e.ok())
+        .filter_map(|e| e.file_name().into_string().ok())
+        .filter(|n| n.strip_prefix(prefix).map(|s| s.starts_with('.')).unwrap_or(false))
+        .collect();
+    names.sort_unstable();
+    let remove = names.len().saturating_sub(keep);
+    for name in names.iter().take(remove) {
+        match fs::remove_file(dir.join(name)) {
+            Ok(()) => event!(Level::INFO, file = name.as_str(), "old log file removed"),
+            Err(err) => event!(Level::WARN, file = name.as_str(), "cannot remove old log file: {}", err),
+        }
+    }
+}
+
+/// Prune the log files once right away, and then every `period`, which should be the rotation period.
+pub fn start(dir: String, prefix: &'static str, keep: usize, period: Duration) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            prune(Path::new(&dir), prefix, keep);
+        }
+    });
+}
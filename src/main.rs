@@ -1,9 +1,10 @@
 use std::fs;
-use lazy_static::lazy_static;
+use clap::ArgMatches;
+use futures::future::join_all;
 use apiservice::APIService;
 use routine::TaskFinder;
-use serde_json::Value;
-use tracing::{span, event, Level};
+use tokio::signal::unix::{signal, SignalKind};
+use tracing::{span, event, Level, Instrument};
 use tracing_subscriber::{fmt::format::FmtSpan, filter, prelude::*};
 
 mod parser;
@@ -11,21 +12,32 @@ mod solver;
 mod routine;
 
 mod arg;
+mod cli;
+mod config;
+mod logretention;
 mod apiservice;
 mod types;
 
-lazy_static! {
-    static ref API_SERVICE: APIService = APIService::new();
-}
-
 /// The main function parses command line arguments, and extracts important information from config files.
-/// API_SERVICE is initialized, and TASK_FINDER is fired
+/// An API service and a task finder are started for every site profile given, unless a subcommand is given
 #[tokio::main]
 async fn main() {
     let args = arg::build_argparse().get_matches();
 
+    // subcommands need neither the log file nor the wiki
+    match args.subcommand() {
+        Some(("validate", sub_args)) => std::process::exit(if cli::validate(sub_args) { 0 } else { 1 }),
+        Some(("run", sub_args)) => std::process::exit(if cli::run(sub_args).await { 0 } else { 1 }),
+        _ => (),
+    }
+
     // set up subscriber
-    let file_appender = tracing_appender::rolling::daily(format!("logs/{}", args.value_of("profile").unwrap()), "plbot.log");
+    let log_dir = format!("logs/{}", args.values_of("profile").unwrap().collect::<Vec<_>>().join("+"));
+    let (file_appender, rotation_period) = if args.value_of("log-rotation") == Some("hourly") {
+        (tracing_appender::rolling::hourly(&log_dir, "plbot.log"), std::time::Duration::from_secs(60 * 60))
+    } else {
+        (tracing_appender::rolling::daily(&log_dir, "plbot.log"), std::time::Duration::from_secs(24 * 60 * 60))
+    };
     let (non_blocking, _guard) = tracing_appender::non_blocking(file_appender);
     tracing_subscriber::registry()
     /*
@@ -36,53 +48,140 @@ async fn main() {
         )
     */
         .with(
-            tracing_subscriber::fmt::layer()
+            (args.value_of("log-format") != Some("json")).then(|| tracing_subscriber::fmt::layer()
+                .with_writer(non_blocking.clone())
+                .with_ansi(false)
+                .with_span_events(FmtSpan::NONE)
+                .with_filter(filter::LevelFilter::DEBUG)
+            )
+        )
+        .with(
+            (args.value_of("log-format") == Some("json")).then(|| tracing_subscriber::fmt::layer()
                 .with_writer(non_blocking)
                 .with_ansi(false)
                 .with_span_events(FmtSpan::NONE)
+                .json()
+                .with_current_span(true)
+                .with_span_list(true)
                 .with_filter(filter::LevelFilter::DEBUG)
+            )
         )
         .init();
+    if let Some(keep) = args.value_of("log-keep") {
+        // checked by clap already
+        let keep: usize = keep.parse().unwrap();
+        logretention::start(log_dir, "plbot.log", keep.max(1), rotation_period);
+    }
 
-    let (profile, login) = span!(target: "main", Level::INFO, "bootstrap").in_scope(|| {
-        event!(Level::INFO, "reading config files");
-        event!(Level::DEBUG, "reading site information file");
-        let sites = fs::read_to_string(args.value_of("site").unwrap()).expect("cannot open site information file");
-        event!(Level::DEBUG, "parsing site information file");
-        let sites: Value = serde_json::from_str(&sites).expect("cannot parse site information file");
-
-        let profile = args.value_of("profile").unwrap();
-        event!(Level::DEBUG, "fetching profile \"{}\"", profile);
-        let profile: types::SiteProfile = serde_json::from_value(sites[profile].clone()).expect("cannot find specified site profile");
+    let profiles = match span!(target: "main", Level::INFO, "bootstrap").in_scope(|| read_profiles(&args)) {
+        Ok(profiles) => profiles,
+        Err(err) => {
+            event!(target: "main", Level::ERROR, "{}", err);
+            eprintln!("{}", err);
+            std::process::exit(1);
+        },
+    };
 
-        event!(Level::DEBUG, "reading login file");
-        let login = fs::read_to_string(args.value_of("login").unwrap()).expect("cannot open login file");
-        event!(Level::DEBUG, "parsing login file");
-        let login: Value = serde_json::from_str(&login).expect("cannot parse login file.");
-        event!(Level::DEBUG, "fetching login credential \"{}\"", &profile.login);
-        let login: types::LoginCredential = serde_json::from_value(login[&profile.login].clone()).expect("cannot find specified site profile");
+    let result_cache_ttl = if args.is_present("no-cache") {
+        None
+    } else {
+        // checked by clap already
+        let minutes: u64 = args.value_of("cache-ttl").unwrap().parse().unwrap();
+        Some(std::time::Duration::from_secs(minutes.saturating_mul(60)))
+    };
 
-        event!(Level::INFO, "read config files successful");
-        (profile, login)
-    });
+    // every site gets its own API service and task finder, which live until the process exits
+    let mut task_finders: Vec<(String, &'static TaskFinder)> = Vec::new();
+    for (name, profile, login) in profiles {
+        let config_loc = profile.config.to_owned();
+        let api_service: &'static APIService = Box::leak(Box::new(APIService::new()));
+        let task_finder: &'static TaskFinder = Box::leak(Box::new(TaskFinder::new(api_service)));
+        async {
+            api_service.setup(login, profile).await;
+            if args.is_present("read-only") {
+                event!(target: "main", Level::WARN, "running in read-only mode, all edits will be refused");
+                api_service.set_read_only(true).await;
+            }
+            api_service.try_init().await;
+            api_service.start().await;
 
-    let config_loc = profile.config.to_owned();
+            task_finder.set_config_location(&config_loc).await;
+            task_finder.set_state_dir(args.value_of("state-dir")).await;
+            task_finder.set_result_cache_ttl(result_cache_ttl).await;
+            // each site writes its files in a directory of its own, so that tasks on different sites cannot overwrite each other's files
+            let output_dir = args.value_of("output-dir").map(|dir| std::path::Path::new(dir).join(&name).to_string_lossy().into_owned());
+            task_finder.set_output_dir(output_dir.as_deref()).await;
+            task_finder.start().await;
+        }.instrument(span!(target: "main", Level::INFO, "site", profile = name.as_str())).await;
+        task_finders.push((name, task_finder));
+    }
 
-    lazy_static! {
-        static ref TASK_FINDER: TaskFinder = TaskFinder::new();
+    let mut usr1 = signal(SignalKind::user_defined1()).expect("cannot listen for SIGUSR1");
+    let mut hup = signal(SignalKind::hangup()).expect("cannot listen for SIGHUP");
+    loop {
+        tokio::select! {
+            ctrl_c_res = tokio::signal::ctrl_c() => {
+                match ctrl_c_res {
+                    Ok(()) => event!(Level::INFO, "ctrl-c detected"),
+                    Err(err) => event!(Level::ERROR, "unable to listen for shutdown signal: {}", err),
+                }
+                break;
+            },
+            _ = usr1.recv() => {
+                event!(Level::INFO, "SIGUSR1 detected");
+                trigger_tasks(args.value_of("trigger-file").unwrap(), &task_finders).await;
+            },
+            _ = hup.recv() => {
+                event!(Level::INFO, "SIGHUP detected, reloading configuration");
+                for (_, task_finder) in task_finders.iter() {
+                    task_finder.reload();
+                }
+            },
+        }
     }
+    // let running queries stop at a safe point instead of killing them with the runtime
+    join_all(task_finders.iter().map(|(_, task_finder)| task_finder.shutdown())).await;
+    event!(Level::INFO, "all tasks stopped");
 
-    API_SERVICE.setup(login, profile).await;
-    API_SERVICE.try_init().await;
-    API_SERVICE.start().await;
+}
 
-    TASK_FINDER.set_config_location(&config_loc).await;
-    TASK_FINDER.start().await;
+/// Read the site profiles named by `--profile`, along with their login credentials.
+fn read_profiles(args: &ArgMatches) -> Result<Vec<(String, types::SiteProfile, types::LoginCredential)>, config::ConfigError> {
+    event!(Level::INFO, "reading config files");
+    let profiles = config::load_profiles(args.value_of("site").unwrap(), args.value_of("login").unwrap(), args.values_of("profile").unwrap())?;
+    event!(Level::INFO, "read config files successful");
+    Ok(profiles)
+}
 
-    let ctrl_c_res = tokio::signal::ctrl_c().await;
-    match ctrl_c_res {
-        Ok(()) => event!(Level::INFO, "ctrl-c detected"),
-        Err(err) => event!(Level::ERROR, "unable to listen for shutdown signal: {}", err),
+/// Run the tasks listed in the trigger file right away, and remove the file so that they are not run again on the next signal.
+/// Each line is a task id, optionally prefixed by the site profile and a space. Without the profile, the task is looked up in every site.
+async fn trigger_tasks(trigger_file: &str, task_finders: &[(String, &'static TaskFinder)]) {
+    let content = match fs::read_to_string(trigger_file) {
+        Ok(content) => content,
+        Err(err) => {
+            event!(Level::WARN, file = trigger_file, "cannot read trigger file: {}", err);
+            return;
+        },
+    };
+    _ = fs::remove_file(trigger_file);
+    for line in content.lines().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+        let (profile, id) = match line.rsplit_once(' ') {
+            Some((profile, id)) => (Some(profile.trim()), id),
+            None => (None, line),
+        };
+        let id = match id.parse::<i64>() {
+            Ok(id) => id,
+            Err(_) => {
+                event!(Level::WARN, line, "cannot parse task id in trigger file");
+                continue;
+            },
+        };
+        let mut found = false;
+        for (name, task_finder) in task_finders.iter().filter(|(name, _)| profile.map(|p| p == name).unwrap_or(true)) {
+            found |= task_finder.trigger(id).instrument(span!(target: "main", Level::INFO, "site", profile = name.as_str())).await;
+        }
+        if !found {
+            event!(Level::WARN, line, "no such task to trigger");
+        }
     }
-
 }
@@ -0,0 +1,73 @@
+//! This module runs static analysis on the IR,
+//! to catch queries that are almost certainly unintended.
+
+use std::collections::HashMap;
+
+use super::Query;
+use super::ir::{Instruction, RegID};
+
+/// Whether a title given to `Prefix` only names a namespace, e.g. `""` or `"Category:"`.
+/// Such a prefix matches every page in the namespace.
+fn is_namespace_only(title: &str) -> bool {
+    let title = title.trim();
+    title.is_empty() || title.ends_with(':')
+}
+
+fn lookup(unbounded: &HashMap<RegID, bool>, reg: &RegID) -> bool {
+    unbounded.get(reg).copied().unwrap_or(false)
+}
+
+/// Estimates whether the result of a query is unbounded, i.e. not narrowed down by any selective operation,
+/// so that the query effectively returns every page in a namespace or even the whole wiki.
+///
+/// The estimation is conservative: it only flags a query if its result provably or very likely covers a whole namespace.
+/// - A `Prefix` without a limit, whose operand only names a namespace, is unbounded. So are an `AllPages` and a `Complement` without a limit.
+/// - Generators and `Toggle` are unbounded if their operand is. `Limit` is always bounded.
+/// - `And` is bounded if either side is. `Or` and `Xor` are unbounded if either side is. `Exclude` follows its left side.
+pub fn is_likely_unbounded(query: &Query) -> bool {
+    let mut unbounded: HashMap<RegID, bool> = HashMap::new();
+    let mut titles: HashMap<RegID, &Vec<String>> = HashMap::new();
+    for inst in query.0.iter() {
+        let this = match inst {
+            Instruction::And { op1, op2, .. } => lookup(&unbounded, op1) && lookup(&unbounded, op2),
+            Instruction::Or { op1, op2, .. } |
+            Instruction::Xor { op1, op2, .. } => lookup(&unbounded, op1) || lookup(&unbounded, op2),
+            Instruction::Exclude { op1, .. } => lookup(&unbounded, op1),
+            Instruction::Link { op, .. } |
+            Instruction::LinkTo { op, .. } |
+            Instruction::EmbeddedIn { op, .. } |
+            Instruction::RedirectsTo { op, .. } |
+            Instruction::FileUsage { op, .. } |
+            Instruction::TemplatesOn { op, .. } |
+            Instruction::InCat { op, .. } |
+            Instruction::Toggle { op, .. } |
+            Instruction::ResolveRedirect { op, .. } |
+            Instruction::LangLinks { op, .. } |
+            Instruction::Count { op, .. } |
+            Instruction::Nop { op, .. } => lookup(&unbounded, op),
+            Instruction::Prefix { op, cs, .. } => {
+                let limited = matches!(cs.limit, Some(l) if l >= 0);
+                let whole_ns = titles.get(op).map(|t| t.iter().any(|t| is_namespace_only(t))).unwrap_or(false);
+                lookup(&unbounded, op) || (whole_ns && !limited)
+            },
+            Instruction::AllPages { cs, .. } |
+            Instruction::Complement { cs, .. } => !matches!(cs.limit, Some(l) if l >= 0),
+            Instruction::Limit { .. } |
+            Instruction::Search { .. } |
+            Instruction::WithProp { .. } |
+            Instruction::UserContribs { .. } |
+            Instruction::RecentChanges { .. } |
+            Instruction::DoubleRedirects { .. } |
+            Instruction::BrokenRedirects { .. } |
+            Instruction::ProtectedTitles { .. } |
+            Instruction::ExtLink { .. } |
+            Instruction::MissingParam { .. } => false,
+            Instruction::Set { dest, titles: t, .. } => {
+                titles.insert(*dest, t);
+                false
+            },
+        };
+        unbounded.insert(inst.get_dest(), this);
+    }
+    unbounded.get(&query.1).copied().unwrap_or(false)
+}
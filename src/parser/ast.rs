@@ -14,6 +14,28 @@ pub(crate) enum Expr {
     Constrained(Box<Expr>, Vec<Constraint>),
     // Set arithmetics
     Binary(Box<Expr>, BinaryOpcode, Box<Expr>),
+    // Truncation
+    Limit(Box<Expr>, i64),
+    // Search, with an optional search type
+    Search(String, Option<String>),
+    // Pages with a page property
+    WithProp(String),
+    // Every page in a namespace
+    AllPages(i64),
+    // Pages edited by a user, with an optional kind of contribution
+    UserContribs(String, Option<String>),
+    // Pages changed in the last hours, with an optional type of change
+    RecentChanges(i64, Option<String>),
+    // Redirects to redirects
+    DoubleRedirects,
+    // Redirects to missing pages
+    BrokenRedirects,
+    // Pages linking to an external URL
+    ExtLink(String),
+    // Pages embedding a template without a value for one of its parameters
+    MissingParam(String, String),
+    // Titles protected from creation, optionally at a certain level
+    ProtectedTitles(Option<String>),
 }
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
@@ -21,9 +43,16 @@ pub(crate) enum UnaryOpcode {
     Link,
     LinkTo,
     EmbeddedIn,
+    TemplatesOn,
+    FileUsage,
+    RedirectsTo,
     InCategory,
     Toggle,
+    ResolveRedirect,
+    LangLinks,
     Prefix,
+    Count,
+    Complement,
 }
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
@@ -42,4 +71,23 @@ pub(crate) enum Constraint {
     DirectLink(bool),
     ResolveRedir(bool),
     Limit(i64),
+    Protect(String, Option<String>),
+    AddedAfter(String),
+    AddedBefore(String),
+    Sort(String, Option<String>),
+    SizeLt(i64),
+    SizeGt(i64),
+    TouchedAfter(String),
+    TouchedBefore(String),
+    // protection type, level, whether the protection must be indefinite
+    Protected(String, Option<String>, bool),
+    Unprotected(String),
+    // media or MIME types, whether to keep pages that are not files
+    MediaType(Vec<String>, bool),
+    // whether to keep only redirects, or only pages other than redirects
+    IsRedirect(bool),
+    // regular expression, flags, whether to keep the pages that do not match instead
+    Content(String, Option<String>, bool),
+    KeepForeign(bool),
+    KeepFragment(bool),
 }
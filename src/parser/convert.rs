@@ -3,15 +3,25 @@
 //! defined in `plbot_base`
 
 use std::collections::HashSet;
+use mediawiki::api::NamespaceID;
 
 use super::{ast::Expr, ast::UnaryOpcode, ast::BinaryOpcode, PLBotParseResult, optim::merge_constraints, optim::construct_constraints_from_vec, error::PLBotParserError};
-use super::ir::{Instruction, SetConstraint, RegID, RedirectFilterStrategy};
+use super::ir::{Instruction, SetConstraint, RegID, RedirectFilterStrategy, DepthMergePolicy, SearchWhat, CategorySortKey};
 
-pub(crate) fn to_ir(ast: &Expr) -> PLBotParseResult {
-    ir_helper(ast, 0)
+pub(crate) fn to_ir(ast: &Expr, depth_merge: DepthMergePolicy) -> PLBotParseResult {
+    let (inst, fin) = ir_helper(ast, 0, depth_merge)?;
+    // `Count` produces a number rather than a list of pages, so it can only wrap the whole query
+    if inst.iter().any(|i| matches!(i, Instruction::Count { dest, .. } if *dest != fin)) {
+        return Err(PLBotParserError::Semantic(String::from("count must wrap the whole query")));
+    }
+    // `Complement` enumerates whole namespaces, so it must be told which ones rather than enumerating the whole wiki
+    if inst.iter().any(|i| matches!(i, Instruction::Complement { cs, .. } if cs.ns.as_ref().map(|ns| ns.is_empty()).unwrap_or(true))) {
+        return Err(PLBotParserError::Semantic(String::from("not needs a namespace constraint")));
+    }
+    Ok((inst, fin))
 }
 
-fn ir_helper(ast: &Expr, mut reg_id: RegID) -> PLBotParseResult {
+fn ir_helper(ast: &Expr, mut reg_id: RegID, depth_merge: DepthMergePolicy) -> PLBotParseResult {
     // do a postorder dfs to the tree
     // find any semantic error
     let mut stack: Vec<&Expr> = Vec::new();
@@ -24,7 +34,18 @@ fn ir_helper(ast: &Expr, mut reg_id: RegID) -> PLBotParseResult {
             Expr::Binary(..) => root = None,
             Expr::Unary(_, c) => root = Some(c),
             Expr::Constrained(c, _) => root = Some(c),
+            Expr::Limit(c, _) => root = Some(c),
             Expr::Page(..) => root = None,
+            Expr::Search(..) => root = None,
+            Expr::WithProp(..) => root = None,
+            Expr::AllPages(..) => root = None,
+            Expr::UserContribs(..) => root = None,
+            Expr::RecentChanges(..) => root = None,
+            Expr::DoubleRedirects => root = None,
+            Expr::BrokenRedirects => root = None,
+            Expr::ProtectedTitles(..) => root = None,
+            Expr::ExtLink(..) => root = None,
+            Expr::MissingParam(..) => root = None,
         };
     }
 
@@ -37,25 +58,112 @@ fn ir_helper(ast: &Expr, mut reg_id: RegID) -> PLBotParseResult {
                 inst.push(instruct);
                 reg_id += 1;
             },
+            Expr::Search(q, w) => {
+                let what = match w {
+                    Some(w) => w.parse().map_err(|_| PLBotParserError::Semantic(format!("invalid search type \"{}\"", w)))?,
+                    None => SearchWhat::Text,
+                };
+                instruct = Instruction::Search{ dest: reg_id, query: q.to_owned(), what, cs: SetConstraint::new() };
+                inst.push(instruct);
+                reg_id += 1;
+            },
+            Expr::WithProp(p) => {
+                instruct = Instruction::WithProp{ dest: reg_id, prop: p.to_owned(), cs: SetConstraint::new() };
+                inst.push(instruct);
+                reg_id += 1;
+            },
+            Expr::AllPages(ns) => {
+                // virtual namespaces such as `Special:` hold no pages to list
+                if *ns < 0 {
+                    return Err(PLBotParserError::Semantic(format!("invalid namespace {}", ns)));
+                }
+                instruct = Instruction::AllPages{ dest: reg_id, ns: *ns as NamespaceID, cs: SetConstraint::new() };
+                inst.push(instruct);
+                reg_id += 1;
+            },
+            Expr::UserContribs(u, s) => {
+                // only page creations can be told apart, other kinds of edits are all the same page
+                let created = match s.as_deref() {
+                    None => false,
+                    Some("new") => true,
+                    Some(s) => return Err(PLBotParserError::Semantic(format!("invalid contribution type \"{}\"", s))),
+                };
+                instruct = Instruction::UserContribs{ dest: reg_id, user: u.to_owned(), created, cs: SetConstraint::new() };
+                inst.push(instruct);
+                reg_id += 1;
+            },
+            Expr::RecentChanges(h, t) => {
+                if *h <= 0 {
+                    return Err(PLBotParserError::Semantic(format!("invalid number of hours {}", h)));
+                }
+                let kind = match t {
+                    Some(t) => Some(t.parse().map_err(|_| PLBotParserError::Semantic(format!("invalid change type \"{}\"", t)))?),
+                    None => None,
+                };
+                instruct = Instruction::RecentChanges{ dest: reg_id, hours: *h as u64, kind, cs: SetConstraint::new() };
+                inst.push(instruct);
+                reg_id += 1;
+            },
+            Expr::DoubleRedirects => {
+                instruct = Instruction::DoubleRedirects{ dest: reg_id, cs: SetConstraint::new() };
+                inst.push(instruct);
+                reg_id += 1;
+            },
+            Expr::BrokenRedirects => {
+                instruct = Instruction::BrokenRedirects{ dest: reg_id, cs: SetConstraint::new() };
+                inst.push(instruct);
+                reg_id += 1;
+            },
+            Expr::ProtectedTitles(level) => {
+                instruct = Instruction::ProtectedTitles{ dest: reg_id, level: level.to_owned(), cs: SetConstraint::new() };
+                inst.push(instruct);
+                reg_id += 1;
+            },
+            Expr::ExtLink(url) => {
+                instruct = Instruction::ExtLink{ dest: reg_id, url: url.to_owned(), cs: SetConstraint::new() };
+                inst.push(instruct);
+                reg_id += 1;
+            },
+            Expr::MissingParam(template, param) => {
+                instruct = Instruction::MissingParam{ dest: reg_id, template: template.to_owned(), param: param.to_owned(), cs: SetConstraint::new() };
+                inst.push(instruct);
+                reg_id += 1;
+            },
             Expr::Unary(op, _) => {
                 instruct = match *op {
                     UnaryOpcode::Link => Instruction::Link{ dest: reg_id, op: reg_id - 1, cs: SetConstraint::new() },
                     UnaryOpcode::LinkTo => Instruction::LinkTo{ dest: reg_id, op: reg_id - 1, cs: SetConstraint::new() },
                     UnaryOpcode::EmbeddedIn => Instruction::EmbeddedIn{ dest: reg_id, op: reg_id - 1, cs: SetConstraint::new() },
+                    UnaryOpcode::TemplatesOn => Instruction::TemplatesOn{ dest: reg_id, op: reg_id - 1, cs: SetConstraint::new() },
+                    UnaryOpcode::FileUsage => Instruction::FileUsage{ dest: reg_id, op: reg_id - 1, cs: SetConstraint::new() },
+                    UnaryOpcode::RedirectsTo => Instruction::RedirectsTo{ dest: reg_id, op: reg_id - 1, cs: SetConstraint::new() },
                     UnaryOpcode::InCategory => Instruction::InCat{ dest: reg_id, op: reg_id - 1, cs: SetConstraint::new() },
-                    UnaryOpcode::Toggle => Instruction::Toggle{ dest: reg_id, op: reg_id - 1 },
+                    UnaryOpcode::Toggle => Instruction::Toggle{ dest: reg_id, op: reg_id - 1, cs: SetConstraint::new() },
+                    UnaryOpcode::ResolveRedirect => Instruction::ResolveRedirect{ dest: reg_id, op: reg_id - 1 },
+                    UnaryOpcode::LangLinks => Instruction::LangLinks{ dest: reg_id, op: reg_id - 1, cs: SetConstraint::new() },
                     UnaryOpcode::Prefix => Instruction::Prefix{ dest: reg_id, op: reg_id - 1, cs: SetConstraint::new() },
+                    UnaryOpcode::Count => Instruction::Count{ dest: reg_id, op: reg_id - 1 },
+                    UnaryOpcode::Complement => Instruction::Complement{ dest: reg_id, op: reg_id - 1, cs: SetConstraint::new() },
                 };
                 inst.push(instruct);
                 reg_id += 1;
             },
+            Expr::Limit(_, count) => {
+                // rejects negative counts
+                if *count < 0 {
+                    return Err(PLBotParserError::Semantic(String::from("invalid limit count")));
+                }
+                instruct = Instruction::Limit{ dest: reg_id, op: reg_id - 1, count: *count as usize };
+                inst.push(instruct);
+                reg_id += 1;
+            },
             Expr::Binary(l, op, r) => {
-                let mut lop = ir_helper(l, reg_id)?;
+                let mut lop = ir_helper(l, reg_id, depth_merge)?;
                 let left_dest = lop.1;
                 reg_id = left_dest + 1;
                 inst.append(&mut lop.0);
                 
-                let mut rop = ir_helper(r, reg_id)?;
+                let mut rop = ir_helper(r, reg_id, depth_merge)?;
                 let right_dest = rop.1;
                 reg_id = right_dest + 1;
                 inst.append(&mut rop.0);
@@ -73,12 +181,30 @@ fn ir_helper(ast: &Expr, mut reg_id: RegID) -> PLBotParseResult {
                 // apply the constraint to the corresponding instruction
                 // the tree formulation ensures that this would always be the last element of `inst`, aka `reg_id - 1`
                 // the instruction construction process ensures that `inst` is sorted by `dest` field in ascending order
-                let constraint_struct = construct_constraints_from_vec(c)?;
+                let constraint_struct = construct_constraints_from_vec(c, depth_merge)?;
                 // rejects if ns has some negative number
                 let mut stack: Vec<(RegID, SetConstraint)> = vec![(reg_id - 1, constraint_struct)];
                 while let Some((target, con)) = stack.pop() {
                     let ires = inst.binary_search_by(|probe| probe.get_dest().cmp(&target));
                     if let Ok(idx) = ires {
+                        // the time range is about when a page is added to a category, so only `InCat` takes it,
+                        // though it passes through set operations and `Toggle` like any other constraint
+                        // the same goes for the sort order of category members
+                        let incat_only = matches!(inst[idx],
+                            Instruction::InCat { .. } | Instruction::And { .. } | Instruction::Or { .. } | Instruction::Exclude { .. } |
+                            Instruction::Xor { .. } | Instruction::Toggle { .. } | Instruction::Nop { .. });
+                        if (con.added_after.is_some() || con.added_before.is_some()) && !incat_only {
+                            return Err(PLBotParserError::Semantic(String::from("invalid time range constraint")));
+                        }
+                        if con.sort.is_some() && !incat_only {
+                            return Err(PLBotParserError::Semantic(String::from("invalid sort constraint")));
+                        }
+                        // likewise, fragments only exist in titles written in the query
+                        if con.keep_fragment.is_some() && !matches!(inst[idx],
+                            Instruction::Set { .. } | Instruction::And { .. } | Instruction::Or { .. } | Instruction::Exclude { .. } |
+                            Instruction::Xor { .. } | Instruction::Toggle { .. } | Instruction::Nop { .. }) {
+                            return Err(PLBotParserError::Semantic(String::from("invalid keepfragment constraint")));
+                        }
                         match &mut inst[idx] {
                             Instruction::And { dest: _, op1, op2 } |
                             Instruction::Or { dest: _, op1, op2 } |
@@ -89,15 +215,15 @@ fn ir_helper(ast: &Expr, mut reg_id: RegID) -> PLBotParseResult {
                                 stack.push((*op1, con.clone()));
                             },
                             Instruction::Link { dest, op, cs } => {
-                                // rejects if constraint has a depth or directlink field, else merge
-                                if con.depth.is_some() || con.directlink.is_some() {
+                                // rejects if constraint has a depth, directlink or protect field, else merge
+                                if con.depth.is_some() || con.directlink.is_some() || con.protect.is_some() {
                                     return Err(PLBotParserError::Semantic(String::from("invalid constraint")));
                                 }
                                 // also rejects if constraint has a redirect constraint other than `All`
                                 if con.redir.is_some() && con.redir.unwrap() != RedirectFilterStrategy::All {
                                     return Err(PLBotParserError::Semantic(String::from("invalid redirect strategy")));
                                 }
-                                let new_constraint = merge_constraints(cs, &con)?;
+                                let new_constraint = merge_constraints(cs, &con, depth_merge)?;
                                 let new_inst = Instruction::Link { dest: *dest, op: *op, cs: new_constraint };
                                 inst[idx] = new_inst;
                             },
@@ -106,19 +232,54 @@ fn ir_helper(ast: &Expr, mut reg_id: RegID) -> PLBotParseResult {
                                 if con.depth.is_some() {
                                     return Err(PLBotParserError::Semantic(String::from("invalid depth constraint")));
                                 }
-                                let new_constraint = merge_constraints(cs, &con)?;
+                                if con.protect.is_some() {
+                                    return Err(PLBotParserError::Semantic(String::from("invalid protect constraint")));
+                                }
+                                let new_constraint = merge_constraints(cs, &con, depth_merge)?;
                                 let new_inst = Instruction::LinkTo { dest: *dest, op: *op, cs: new_constraint };
                                 inst[idx] = new_inst;
                             },
                             Instruction::EmbeddedIn { dest, op, cs } => {
-                                // rejects if constraint has a depth or directlink field, else merge
-                                if con.depth.is_some() || con.directlink.is_some() {
+                                // rejects if constraint has a depth, directlink or protect field, else merge
+                                if con.depth.is_some() || con.directlink.is_some() || con.protect.is_some() {
                                     return Err(PLBotParserError::Semantic(String::from("invalid constraint")));
                                 }
-                                let new_constraint = merge_constraints(cs, &con)?;
+                                let new_constraint = merge_constraints(cs, &con, depth_merge)?;
                                 let new_inst = Instruction::EmbeddedIn { dest: *dest, op: *op, cs: new_constraint };
                                 inst[idx] = new_inst;
                             }
+                            Instruction::TemplatesOn { dest, op, cs } => {
+                                // rejects if constraint has a depth, directlink or protect field, else merge
+                                if con.depth.is_some() || con.directlink.is_some() || con.protect.is_some() {
+                                    return Err(PLBotParserError::Semantic(String::from("invalid constraint")));
+                                }
+                                // also rejects if constraint has a redirect constraint other than `All`
+                                if con.redir.is_some() && con.redir.unwrap() != RedirectFilterStrategy::All {
+                                    return Err(PLBotParserError::Semantic(String::from("invalid redirect strategy")));
+                                }
+                                let new_constraint = merge_constraints(cs, &con, depth_merge)?;
+                                let new_inst = Instruction::TemplatesOn { dest: *dest, op: *op, cs: new_constraint };
+                                inst[idx] = new_inst;
+                            }
+                            Instruction::FileUsage { dest, op, cs } => {
+                                // rejects if constraint has a depth, directlink or protect field, else merge
+                                if con.depth.is_some() || con.directlink.is_some() || con.protect.is_some() {
+                                    return Err(PLBotParserError::Semantic(String::from("invalid constraint")));
+                                }
+                                let new_constraint = merge_constraints(cs, &con, depth_merge)?;
+                                let new_inst = Instruction::FileUsage { dest: *dest, op: *op, cs: new_constraint };
+                                inst[idx] = new_inst;
+                            }
+                            Instruction::RedirectsTo { dest, op, cs } => {
+                                // rejects if constraint has a depth, redir, directlink, resolveredir or protect field, else merge
+                                // the results are all redirects to the operand, so resolving them leads back to it
+                                if con.depth.is_some() || con.redir.is_some() || con.directlink.is_some() || con.resolveredir.is_some() || con.protect.is_some() {
+                                    return Err(PLBotParserError::Semantic(String::from("invalid constraint")));
+                                }
+                                let new_constraint = merge_constraints(cs, &con, depth_merge)?;
+                                let new_inst = Instruction::RedirectsTo { dest: *dest, op: *op, cs: new_constraint };
+                                inst[idx] = new_inst;
+                            }
                             Instruction::InCat { dest, op, cs } => {
                                 // rejects if constraint has a redirect constraint other than `All`, or constraint has a directlink constraint. Otherwise merge the constraints
                                 if con.redir.is_some() && con.redir.unwrap() != RedirectFilterStrategy::All {
@@ -127,23 +288,35 @@ fn ir_helper(ast: &Expr, mut reg_id: RegID) -> PLBotParseResult {
                                 if con.directlink.is_some() {
                                     return Err(PLBotParserError::Semantic(String::from("invalid directlink constraint")));
                                 }
-                                let new_constraint = merge_constraints(cs, &con)?;
+                                if con.protect.is_some() {
+                                    return Err(PLBotParserError::Semantic(String::from("invalid protect constraint")));
+                                }
+                                let new_constraint = merge_constraints(cs, &con, depth_merge)?;
+                                // the API only takes a time range when sorting by timestamp
+                                let timed = new_constraint.added_after.is_some() || new_constraint.added_before.is_some();
+                                if timed && matches!(new_constraint.sort, Some(s) if s.key != CategorySortKey::Timestamp) {
+                                    return Err(PLBotParserError::Semantic(String::from("conflict sort constraint")));
+                                }
                                 let new_inst = Instruction::InCat { dest: *dest, op: *op, cs: new_constraint };
                                 inst[idx] = new_inst;
                             }
-                            Instruction::Toggle { dest: _, op } => {
-                                // switch every ns constraint, then pass through this instruction
+                            Instruction::Toggle { dest: _, op, cs } => {
+                                // post filters check the pages this instruction outputs, so they stay here
+                                // switch every ns constraint of the rest, then pass it through this instruction
+                                let (con, filters) = con.split_post_filters();
+                                *cs = merge_constraints(cs, &filters, depth_merge)?;
                                 let ns = con.ns.clone();
                                 
                                 if let Some(ns_set) = ns {
                                     let mut ns_vec = Vec::from_iter(ns_set);
-                                    for i in ns_vec.iter_mut() {
+                                    // special namespaces and the interwiki pseudo namespace have no talk pages, so they stay as they are
+                                    for i in ns_vec.iter_mut().filter(|i| **i >= 0) {
                                         *i ^= 0b1;
                                     }
-                                    let new_con = SetConstraint { ns: Some(HashSet::from_iter(ns_vec.into_iter())), depth: con.depth, redir: con.redir, directlink: con.directlink, resolveredir: con.resolveredir, limit: con.limit };
+                                    let new_con = SetConstraint { ns: Some(HashSet::from_iter(ns_vec.into_iter())), ..con };
                                     stack.push((*op, new_con));
                                 } else {
-                                    stack.push((*op, con.clone()));
+                                    stack.push((*op, con));
                                 }
                             }
                             Instruction::Prefix { dest, op, cs } => {
@@ -152,23 +325,145 @@ fn ir_helper(ast: &Expr, mut reg_id: RegID) -> PLBotParseResult {
                                 if con.depth.is_some() || con.directlink.is_some() || con.resolveredir.is_some() {
                                     return Err(PLBotParserError::Semantic(String::from("invalid constraint")));
                                 }
-                                let new_constraint = merge_constraints(cs, &con)?;
+                                let new_constraint = merge_constraints(cs, &con, depth_merge)?;
                                 let new_inst = Instruction::Prefix { dest: *dest, op: *op, cs: new_constraint };
                                 inst[idx] = new_inst;
                             },
+                            Instruction::Complement { dest, op, cs } => {
+                                // rejects if constraint has a depth, directlink or resolveredir field, else merge
+                                // the constraint narrows down the pages enumerated, not the operand
+                                if con.depth.is_some() || con.directlink.is_some() || con.resolveredir.is_some() {
+                                    return Err(PLBotParserError::Semantic(String::from("invalid constraint")));
+                                }
+                                let new_constraint = merge_constraints(cs, &con, depth_merge)?;
+                                let new_inst = Instruction::Complement { dest: *dest, op: *op, cs: new_constraint };
+                                inst[idx] = new_inst;
+                            },
+                            Instruction::ResolveRedirect { .. } => {
+                                // rejects any constraint, as the targets may not satisfy the constraint the redirects satisfy
+                                return Err(PLBotParserError::Semantic(String::from("invalid constraint")));
+                            },
+                            Instruction::LangLinks { cs, .. } => {
+                                // rejects any constraint, as the resulting pages are on other wikis, and nothing is known about them here
+                                // the only exception is keepforeign, without which the result is dropped like any other foreign page
+                                let mut rest = con.clone();
+                                rest.keep_foreign = None;
+                                if rest != SetConstraint::new() {
+                                    return Err(PLBotParserError::Semantic(String::from("invalid constraint")));
+                                }
+                                *cs = merge_constraints(cs, &con, depth_merge)?;
+                            },
+                            Instruction::Limit { .. } |
+                            Instruction::Count { .. } => {
+                                // rejects any constraint, since filtering before or after truncation gives different results
+                                // and there is nothing to filter after counting
+                                return Err(PLBotParserError::Semantic(String::from("invalid constraint")));
+                            },
                             Instruction::Nop { dest: _, op } => {
                                 // pass through this instruction
                                 stack.push((*op, con.clone()));
                             }
                             Instruction::Set { dest, titles, cs } => {
-                                // rejects if constraint has a depth, redir, resolveredir, or directlink field, else merge
-                                if con.depth.is_some() || con.redir.is_some() || con.directlink.is_some() || con.resolveredir.is_some() {
+                                // rejects if constraint has a depth, redir, resolveredir, directlink, or protect field, else merge
+                                if con.depth.is_some() || con.redir.is_some() || con.directlink.is_some() || con.resolveredir.is_some() || con.protect.is_some() {
                                     return Err(PLBotParserError::Semantic(String::from("invalid constraint")));
                                 }
-                                let new_constraint = merge_constraints(cs, &con)?;
+                                let new_constraint = merge_constraints(cs, &con, depth_merge)?;
                                 let new_inst = Instruction::Set { dest: *dest, titles: (*titles).clone(), cs: new_constraint };
                                 inst[idx] = new_inst;
                             },
+                            Instruction::Search { dest, query, what, cs } => {
+                                // rejects if constraint has a depth, redir, directlink, or protect field, else merge
+                                if con.depth.is_some() || con.redir.is_some() || con.directlink.is_some() || con.protect.is_some() {
+                                    return Err(PLBotParserError::Semantic(String::from("invalid constraint")));
+                                }
+                                let new_constraint = merge_constraints(cs, &con, depth_merge)?;
+                                let new_inst = Instruction::Search { dest: *dest, query: (*query).clone(), what: *what, cs: new_constraint };
+                                inst[idx] = new_inst;
+                            },
+                            Instruction::WithProp { dest, prop, cs } => {
+                                // rejects if constraint has a depth, redir, directlink, or protect field, else merge
+                                if con.depth.is_some() || con.redir.is_some() || con.directlink.is_some() || con.protect.is_some() {
+                                    return Err(PLBotParserError::Semantic(String::from("invalid constraint")));
+                                }
+                                let new_constraint = merge_constraints(cs, &con, depth_merge)?;
+                                let new_inst = Instruction::WithProp { dest: *dest, prop: (*prop).clone(), cs: new_constraint };
+                                inst[idx] = new_inst;
+                            },
+                            Instruction::UserContribs { dest, user, created, cs } => {
+                                // rejects if constraint has a depth, redir, directlink, or protect field, else merge
+                                if con.depth.is_some() || con.redir.is_some() || con.directlink.is_some() || con.protect.is_some() {
+                                    return Err(PLBotParserError::Semantic(String::from("invalid constraint")));
+                                }
+                                let new_constraint = merge_constraints(cs, &con, depth_merge)?;
+                                let new_inst = Instruction::UserContribs { dest: *dest, user: (*user).clone(), created: *created, cs: new_constraint };
+                                inst[idx] = new_inst;
+                            },
+                            Instruction::RecentChanges { dest, hours, kind, cs } => {
+                                // rejects if constraint has a depth, directlink, or protect field, else merge
+                                if con.depth.is_some() || con.directlink.is_some() || con.protect.is_some() {
+                                    return Err(PLBotParserError::Semantic(String::from("invalid constraint")));
+                                }
+                                let new_constraint = merge_constraints(cs, &con, depth_merge)?;
+                                let new_inst = Instruction::RecentChanges { dest: *dest, hours: *hours, kind: *kind, cs: new_constraint };
+                                inst[idx] = new_inst;
+                            },
+                            Instruction::DoubleRedirects { dest, cs } => {
+                                // rejects if constraint has a depth, redir, directlink, or protect field, else merge
+                                if con.depth.is_some() || con.redir.is_some() || con.directlink.is_some() || con.protect.is_some() {
+                                    return Err(PLBotParserError::Semantic(String::from("invalid constraint")));
+                                }
+                                let new_constraint = merge_constraints(cs, &con, depth_merge)?;
+                                let new_inst = Instruction::DoubleRedirects { dest: *dest, cs: new_constraint };
+                                inst[idx] = new_inst;
+                            },
+                            Instruction::BrokenRedirects { dest, cs } => {
+                                // rejects if constraint has a depth, redir, directlink, or protect field, else merge
+                                if con.depth.is_some() || con.redir.is_some() || con.directlink.is_some() || con.protect.is_some() {
+                                    return Err(PLBotParserError::Semantic(String::from("invalid constraint")));
+                                }
+                                let new_constraint = merge_constraints(cs, &con, depth_merge)?;
+                                let new_inst = Instruction::BrokenRedirects { dest: *dest, cs: new_constraint };
+                                inst[idx] = new_inst;
+                            },
+                            Instruction::ExtLink { dest, url, cs } => {
+                                // rejects if constraint has a depth, redir, directlink, or protect field, else merge
+                                if con.depth.is_some() || con.redir.is_some() || con.directlink.is_some() || con.protect.is_some() {
+                                    return Err(PLBotParserError::Semantic(String::from("invalid constraint")));
+                                }
+                                let new_constraint = merge_constraints(cs, &con, depth_merge)?;
+                                let new_inst = Instruction::ExtLink { dest: *dest, url: (*url).clone(), cs: new_constraint };
+                                inst[idx] = new_inst;
+                            },
+                            Instruction::MissingParam { dest, template, param, cs } => {
+                                // rejects if constraint has a depth, redir, directlink, resolveredir or protect field, else merge
+                                // the wikitext of a redirect holds no template calls, so redirects are never listed
+                                if con.depth.is_some() || con.redir.is_some() || con.directlink.is_some() || con.resolveredir.is_some() || con.protect.is_some() {
+                                    return Err(PLBotParserError::Semantic(String::from("invalid constraint")));
+                                }
+                                let new_constraint = merge_constraints(cs, &con, depth_merge)?;
+                                let new_inst = Instruction::MissingParam { dest: *dest, template: (*template).clone(), param: (*param).clone(), cs: new_constraint };
+                                inst[idx] = new_inst;
+                            },
+                            Instruction::ProtectedTitles { dest, level, cs } => {
+                                // rejects if constraint has a depth, redir, directlink, resolveredir or protect field, else merge
+                                // the titles do not exist, so they are never redirects
+                                if con.depth.is_some() || con.redir.is_some() || con.directlink.is_some() || con.resolveredir.is_some() || con.protect.is_some() {
+                                    return Err(PLBotParserError::Semantic(String::from("invalid constraint")));
+                                }
+                                let new_constraint = merge_constraints(cs, &con, depth_merge)?;
+                                let new_inst = Instruction::ProtectedTitles { dest: *dest, level: level.to_owned(), cs: new_constraint };
+                                inst[idx] = new_inst;
+                            },
+                            Instruction::AllPages { dest, ns, cs } => {
+                                // rejects if constraint has a depth or directlink field, else merge
+                                if con.depth.is_some() || con.directlink.is_some() {
+                                    return Err(PLBotParserError::Semantic(String::from("invalid constraint")));
+                                }
+                                let new_constraint = merge_constraints(cs, &con, depth_merge)?;
+                                let new_inst = Instruction::AllPages { dest: *dest, ns: *ns, cs: new_constraint };
+                                inst[idx] = new_inst;
+                            },
                         }
                     } else {
                         return Err(PLBotParserError::Semantic(String::from("internal instruction not found while generating")));
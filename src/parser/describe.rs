@@ -0,0 +1,188 @@
+//! This module turns a compiled query into a plain-language
+//! description, so that editors reading an auto-generated
+//! report can tell what the query actually does.
+
+use std::collections::HashMap;
+
+use super::Query;
+use super::ir::{Instruction, SetConstraint, RegID, RedirectFilterStrategy, ProtectionStatus, SortDirection, RecentChangeType};
+
+/// Describe the constraint attached to an instruction, e.g. " (namespace 0, 14; depth 2)".
+/// Returns an empty string if there is nothing worth mentioning.
+fn describe_constraint(cs: &SetConstraint) -> String {
+    let mut parts: Vec<String> = Vec::new();
+    if let Some(ns) = &cs.ns {
+        let mut ns_vec: Vec<_> = ns.iter().copied().collect();
+        ns_vec.sort_unstable();
+        let ns_str = ns_vec.iter().map(|n| n.to_string()).collect::<Vec<String>>().join(", ");
+        if ns_vec.len() == 1 {
+            parts.push(format!("namespace {}", ns_str));
+        } else {
+            parts.push(format!("namespaces {}", ns_str));
+        }
+    }
+    if let Some(depth) = cs.depth {
+        if depth < 0 {
+            parts.push(String::from("unlimited depth"));
+        } else {
+            parts.push(format!("depth {}", depth));
+        }
+    }
+    if let Some(redir) = cs.redir {
+        match redir {
+            RedirectFilterStrategy::NoRedirect => parts.push(String::from("no redirects")),
+            RedirectFilterStrategy::OnlyRedirect => parts.push(String::from("redirects only")),
+            RedirectFilterStrategy::All => (),
+        }
+    }
+    if cs.directlink == Some(true) {
+        parts.push(String::from("direct links only"));
+    }
+    if cs.resolveredir == Some(true) {
+        parts.push(String::from("redirects resolved"));
+    }
+    if cs.keep_foreign == Some(true) {
+        parts.push(String::from("special and interwiki pages kept"));
+    }
+    if cs.keep_fragment == Some(true) {
+        parts.push(String::from("section links kept"));
+    }
+    if let Some(protect) = &cs.protect {
+        if let Some(level) = &protect.prlevel {
+            parts.push(format!("{}-protected at level {}", protect.prtype.to_string(), level));
+        } else {
+            parts.push(format!("{}-protected", protect.prtype.to_string()));
+        }
+    }
+    match (cs.added_after, cs.added_before) {
+        (Some(after), Some(before)) => parts.push(format!("added between {} and {}", after.to_rfc3339(), before.to_rfc3339())),
+        (Some(after), None) => parts.push(format!("added after {}", after.to_rfc3339())),
+        (None, Some(before)) => parts.push(format!("added before {}", before.to_rfc3339())),
+        (None, None) => (),
+    }
+    if let Some(sort) = cs.sort {
+        parts.push(format!("sorted by {}, {}", sort.key, if sort.dir == SortDirection::Desc { "descending" } else { "ascending" }));
+    }
+    match (cs.size_gt, cs.size_lt) {
+        (Some(gt), Some(lt)) => parts.push(format!("between {} and {} bytes", gt, lt)),
+        (Some(gt), None) => parts.push(format!("larger than {} bytes", gt)),
+        (None, Some(lt)) => parts.push(format!("smaller than {} bytes", lt)),
+        (None, None) => (),
+    }
+    match (cs.touched_after, cs.touched_before) {
+        (Some(after), Some(before)) => parts.push(format!("last touched between {} and {}", after.to_rfc3339(), before.to_rfc3339())),
+        (Some(after), None) => parts.push(format!("last touched after {}", after.to_rfc3339())),
+        (None, Some(before)) => parts.push(format!("last touched before {}", before.to_rfc3339())),
+        (None, None) => (),
+    }
+    match &cs.protected {
+        Some(ProtectionStatus::Unprotected(prtype)) => parts.push(format!("not {}-protected", prtype.to_string())),
+        Some(ProtectionStatus::Protected { filter, indefinite }) => {
+            let mut part = format!("{}-protected", filter.prtype.to_string());
+            if let Some(level) = &filter.prlevel {
+                part.push_str(&format!(" at level {}", level));
+            }
+            if *indefinite {
+                part.push_str(" indefinitely");
+            }
+            parts.push(part);
+        },
+        None => (),
+    }
+    if let Some(media_type) = &cs.media_type {
+        let mut part = format!("files of type {}", media_type.types.join(" or "));
+        if media_type.keep_non_file {
+            part.push_str(", or pages other than files");
+        }
+        parts.push(part);
+    }
+    match cs.is_redirect {
+        Some(true) => parts.push(String::from("redirects only")),
+        Some(false) => parts.push(String::from("no redirects")),
+        None => (),
+    }
+    for content in cs.content.iter() {
+        let verb = if content.invert { "not matching" } else { "matching" };
+        parts.push(format!("wikitext {} /{}/{}", verb, content.pattern, content.flags));
+    }
+    if let Some(limit) = cs.limit {
+        if limit >= 0 {
+            parts.push(format!("at most {} per query", limit));
+        }
+    }
+    if parts.is_empty() {
+        String::new()
+    } else {
+        format!(" ({})", parts.join("; "))
+    }
+}
+
+/// Wrap a sub-description in parentheses, unless it is a plain page list.
+fn operand(desc: &HashMap<RegID, (String, bool)>, reg: &RegID) -> String {
+    match desc.get(reg) {
+        Some((s, true)) => s.clone(),
+        Some((s, false)) => format!("({})", s),
+        None => String::from("(?)"),
+    }
+}
+
+/// Produce a human-readable summary of a query.
+///
+/// Walks the IR in order (which is guaranteed to be topologically sorted),
+/// and describes each register in terms of its operands.
+pub fn describe(query: &Query) -> String {
+    // register -> (description, is it a plain page list that needs no parentheses)
+    let mut desc: HashMap<RegID, (String, bool)> = HashMap::new();
+    for inst in query.0.iter() {
+        let entry = match inst {
+            Instruction::And { op1, op2, .. } => (format!("{} and {}", operand(&desc, op1), operand(&desc, op2)), false),
+            Instruction::Or { op1, op2, .. } => (format!("{} or {}", operand(&desc, op1), operand(&desc, op2)), false),
+            Instruction::Exclude { op1, op2, .. } => (format!("{}, excluding {}", operand(&desc, op1), operand(&desc, op2)), false),
+            Instruction::Xor { op1, op2, .. } => (format!("either {} or {} but not both", operand(&desc, op1), operand(&desc, op2)), false),
+            Instruction::Link { op, cs, .. } => (format!("pages linked from {}{}", operand(&desc, op), describe_constraint(cs)), false),
+            Instruction::LinkTo { op, cs, .. } => (format!("pages that link to {}{}", operand(&desc, op), describe_constraint(cs)), false),
+            Instruction::EmbeddedIn { op, cs, .. } => (format!("pages that transclude {}{}", operand(&desc, op), describe_constraint(cs)), false),
+            Instruction::TemplatesOn { op, cs, .. } => (format!("pages transcluded by {}{}", operand(&desc, op), describe_constraint(cs)), false),
+            Instruction::FileUsage { op, cs, .. } => (format!("pages that use the file {}{}", operand(&desc, op), describe_constraint(cs)), false),
+            Instruction::RedirectsTo { op, cs, .. } => (format!("redirects to {}{}", operand(&desc, op), describe_constraint(cs)), false),
+            Instruction::InCat { op, cs, .. } => (format!("pages in {}{}", operand(&desc, op), describe_constraint(cs)), false),
+            Instruction::Toggle { op, cs, .. } => (format!("talk or subject pages of {}{}", operand(&desc, op), describe_constraint(cs)), false),
+            Instruction::LangLinks { op, cs, .. } => (format!("pages in other languages linked from {}{}", operand(&desc, op), describe_constraint(cs)), false),
+            Instruction::ResolveRedirect { op, .. } => (format!("{}, with redirects replaced by their targets", operand(&desc, op)), false),
+            Instruction::Prefix { op, cs, .. } => (format!("pages with prefix {}{}", operand(&desc, op), describe_constraint(cs)), false),
+            Instruction::Complement { op, cs, .. } => (format!("pages other than {}{}", operand(&desc, op), describe_constraint(cs)), false),
+            Instruction::Limit { op, count, .. } => (format!("the first {} of {}", count, operand(&desc, op)), false),
+            Instruction::Count { op, .. } => (format!("the number of {}", operand(&desc, op)), false),
+            Instruction::Set { titles, cs, .. } => {
+                if titles.is_empty() {
+                    (String::from("nothing"), true)
+                } else {
+                    let list = titles.join(", ");
+                    let cons = describe_constraint(cs);
+                    (format!("{}{}", list, cons), titles.len() == 1 && cons.is_empty())
+                }
+            },
+            Instruction::WithProp { prop, cs, .. } => (format!("pages with page property \"{}\"{}", prop, describe_constraint(cs)), false),
+            Instruction::AllPages { ns, cs, .. } => (format!("all pages in namespace {}{}", ns, describe_constraint(cs)), false),
+            Instruction::DoubleRedirects { cs, .. } => (format!("double redirects{}", describe_constraint(cs)), false),
+            Instruction::BrokenRedirects { cs, .. } => (format!("broken redirects{}", describe_constraint(cs)), false),
+            Instruction::ExtLink { url, cs, .. } => (format!("pages linking to the URL \"{}\"{}", url, describe_constraint(cs)), false),
+            Instruction::MissingParam { template, param, cs, .. } => (format!("pages using {} without a value for \"{}\"{}", template, param, describe_constraint(cs)), false),
+            Instruction::ProtectedTitles { level, cs, .. } => match level {
+                Some(level) => (format!("titles protected from creation at level {}{}", level, describe_constraint(cs)), false),
+                None => (format!("titles protected from creation{}", describe_constraint(cs)), false),
+            },
+            Instruction::RecentChanges { hours, kind, cs, .. } => (format!("pages with {} in the last {} hours{}", match kind {
+                Some(RecentChangeType::Edit) => "edits",
+                Some(RecentChangeType::New) => "creations",
+                Some(RecentChangeType::Log) => "log entries",
+                None => "changes",
+            }, hours, describe_constraint(cs)), false),
+            Instruction::UserContribs { user, created, cs, .. } => (format!("pages {} by {}{}", if *created { "created" } else { "edited" }, user, describe_constraint(cs)), false),
+            Instruction::Search { query, what, cs, .. } => (format!("pages whose {} matches the search \"{}\"{}", what, query, describe_constraint(cs)), false),
+            Instruction::Nop { op, .. } => desc.get(op).cloned().unwrap_or_else(|| (String::from("?"), true)),
+        };
+        desc.insert(inst.get_dest(), entry);
+    }
+    desc.remove(&query.1).map(|(text, _)| text).unwrap_or_default()
+}
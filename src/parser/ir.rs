@@ -11,9 +11,28 @@
 
 use mediawiki::api::NamespaceID;
 use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
 
 pub type RegID = u64;
 pub type DepthNum = i64;
+pub type Timestamp = chrono::DateTime<chrono::Utc>;
+
+/// `DepthMergePolicy` controls how two different depth constraints on the same instruction are merged.
+/// A negative depth means unlimited depth, so it is deeper than any non-negative one.
+/// 
+/// `Conflict`: reject the query.
+/// 
+/// `Max`: keep the deeper one.
+/// 
+/// `Min`: keep the shallower one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DepthMergePolicy {
+    #[default]
+    Conflict,
+    Max,
+    Min,
+}
 
 /// `RedirectFilterStrategy` controls whether the query result should include redirect pages.
 /// Intended for `LinkTo` and `EmbeddedIn` instructions.
@@ -23,7 +42,7 @@ pub type DepthNum = i64;
 /// `OnlyRedirect`: explicitly query for redirects.
 /// 
 /// `All`: query for both redirects and non-redirects.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum RedirectFilterStrategy {
     NoRedirect,
     OnlyRedirect,
@@ -40,20 +59,300 @@ impl ToString for RedirectFilterStrategy {
     }
 }
 
+/// `SearchWhat` selects what a `Search` instruction searches in.
+/// 
+/// `Text`: the page text.
+/// 
+/// `Title`: the page title.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SearchWhat {
+    Text,
+    Title,
+}
+
+impl std::fmt::Display for SearchWhat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Text => f.write_str("text"),
+            Self::Title => f.write_str("title"),
+        }
+    }
+}
+
+impl std::str::FromStr for SearchWhat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "title" => Ok(Self::Title),
+            _ => Err(()),
+        }
+    }
+}
+
+/// `RecentChangeType` selects which changes a `RecentChanges` instruction looks at.
+/// 
+/// `Edit`: edits to existing pages.
+/// 
+/// `New`: page creations.
+/// 
+/// `Log`: log entries, such as moves and deletions. The page is the target of the log entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RecentChangeType {
+    Edit,
+    New,
+    Log,
+}
+
+impl std::fmt::Display for RecentChangeType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Edit => f.write_str("edit"),
+            Self::New => f.write_str("new"),
+            Self::Log => f.write_str("log"),
+        }
+    }
+}
+
+impl std::str::FromStr for RecentChangeType {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "edit" => Ok(Self::Edit),
+            "new" => Ok(Self::New),
+            "log" => Ok(Self::Log),
+            _ => Err(()),
+        }
+    }
+}
+
+/// `CategorySortKey` selects how members of a category are ordered.
+/// 
+/// `Sortkey`: by the sort key, i.e. the order shown on the category page.
+/// 
+/// `Timestamp`: by when the page was added to the category.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CategorySortKey {
+    Sortkey,
+    Timestamp,
+}
+
+impl std::fmt::Display for CategorySortKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Sortkey => f.write_str("sortkey"),
+            Self::Timestamp => f.write_str("timestamp"),
+        }
+    }
+}
+
+impl std::str::FromStr for CategorySortKey {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sortkey" => Ok(Self::Sortkey),
+            "timestamp" => Ok(Self::Timestamp),
+            _ => Err(()),
+        }
+    }
+}
+
+/// `SortDirection` selects whether a sort goes ascending or descending.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum SortDirection {
+    #[default]
+    Asc,
+    Desc,
+}
+
+impl std::fmt::Display for SortDirection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Asc => f.write_str("asc"),
+            Self::Desc => f.write_str("desc"),
+        }
+    }
+}
+
+impl std::str::FromStr for SortDirection {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "asc" => Ok(Self::Asc),
+            "desc" => Ok(Self::Desc),
+            _ => Err(()),
+        }
+    }
+}
+
+/// `CategorySort` is the order in which members of a category are fetched.
+/// 
+/// The result of a query is a set, so the order only makes a difference together with a `limit` constraint,
+/// which keeps the first pages the API returns, e.g. the most recently added ones.
+/// The `Limit` instruction, in contrast, always sorts by full title before truncating.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CategorySort {
+    pub key: CategorySortKey,
+    pub dir: SortDirection,
+}
+
+/// `ProtectionType` selects which kind of protection a `ProtectionFilter` looks at.
+/// 
+/// `Edit`: edit protection.
+/// 
+/// `Move`: move protection.
+/// 
+/// `Upload`: upload protection. Only meaningful for files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ProtectionType {
+    Edit,
+    Move,
+    Upload,
+}
+
+impl ToString for ProtectionType {
+    fn to_string(&self) -> String {
+        match self {
+            Self::Edit => String::from("edit"),
+            Self::Move => String::from("move"),
+            Self::Upload => String::from("upload"),
+        }
+    }
+}
+
+impl std::str::FromStr for ProtectionType {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "edit" => Ok(Self::Edit),
+            "move" => Ok(Self::Move),
+            "upload" => Ok(Self::Upload),
+            _ => Err(()),
+        }
+    }
+}
+
+/// `ProtectionFilter` keeps only pages with a certain protection.
+/// Intended for `Prefix` and `AllPages` instructions, where it is applied server-side.
+/// 
+/// `prtype`: which protection to look at.
+/// 
+/// `prlevel`: the protection level, e.g. `autoconfirmed` or `sysop`. Levels are site-specific. If set to `None`, any level matches.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ProtectionFilter {
+    pub prtype: ProtectionType,
+    pub prlevel: Option<String>,
+}
+
+/// `ProtectionStatus` keeps only pages in a certain protection state.
+/// Unlike `ProtectionFilter`, it is checked after the result is generated, so it works on any instruction.
+/// Expired protections are not counted.
+/// 
+/// `Unprotected`: keep pages without any protection of the given type.
+/// 
+/// `Protected`: keep pages with a protection matching `filter`. If `indefinite` is set, the protection must never expire.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ProtectionStatus {
+    Unprotected(ProtectionType),
+    Protected { filter: ProtectionFilter, indefinite: bool },
+}
+
+/// `MediaTypeFilter` keeps only files of certain types.
+/// 
+/// `types`: media types as MediaWiki names them, e.g. `BITMAP`, `DRAWING`, `AUDIO`, `VIDEO` or `OFFICE`, or MIME types, e.g. `application/pdf` or `image/*`.
+/// A file matches if it matches any of them. Media types are case-insensitive.
+/// 
+/// `keep_non_file`: whether to keep pages outside the File namespace. They are dropped otherwise.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MediaTypeFilter {
+    pub types: Vec<String>,
+    pub keep_non_file: bool,
+}
+
+impl MediaTypeFilter {
+    /// Whether a file of `media_type` and `mime` matches the filter.
+    pub fn matches(&self, media_type: &str, mime: &str) -> bool {
+        self.types.iter().any(|t| match t.strip_suffix("/*") {
+            Some(major) => mime.split_once('/').map(|(m, _)| m.eq_ignore_ascii_case(major)).unwrap_or(false),
+            None if t.contains('/') => mime.eq_ignore_ascii_case(t),
+            None => media_type.eq_ignore_ascii_case(t),
+        })
+    }
+}
+
+/// `ContentFilter` keeps only pages whose wikitext matches a regular expression, in the syntax of the `regex` crate.
+/// Checking it downloads the wikitext of every page, so it is applied after every other post filter.
+/// 
+/// `flags`: any of `i` (case-insensitive), `m` (`^` and `$` match at line breaks), `s` (`.` matches line breaks)
+/// and `a` (anchored, i.e. the pattern must match the whole wikitext rather than any part of it).
+/// 
+/// `invert`: keep the pages that do not match instead.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ContentFilter {
+    pub pattern: String,
+    pub flags: String,
+    pub invert: bool,
+}
+
+/// The flags a `ContentFilter` accepts.
+pub const CONTENT_FILTER_FLAGS: &str = "imsa";
+
+impl ContentFilter {
+    /// Compile the pattern with the flags.
+    pub fn regex(&self) -> Result<regex::Regex, regex::Error> {
+        let pattern = if self.flags.contains('a') { format!(r"\A(?:{})\z", self.pattern) } else { self.pattern.clone() };
+        regex::RegexBuilder::new(&pattern)
+            .case_insensitive(self.flags.contains('i'))
+            .multi_line(self.flags.contains('m'))
+            .dot_matches_new_line(self.flags.contains('s'))
+            .build()
+    }
+}
+
 /// `SetConstraint` are modifier to some instructions.
-/// They are intended for `Link`, `LinkTo`, `InCat`, `Prefix`, `EmbeddedIn` and `Set` instructions.
-/// They are not effective to `Toggle` and and all binary instructions.
+/// They are intended for `Link`, `LinkTo`, `InCat`, `Prefix`, `Complement`, `AllPages`, `EmbeddedIn`, `TemplatesOn`, `FileUsage`, `RedirectsTo`, `Search`, `WithProp`, `UserContribs`, `RecentChanges`, `DoubleRedirects`, `BrokenRedirects`, `ProtectedTitles`, `ExtLink`, `MissingParam` and `Set` instructions.
+/// They are not effective to all binary instructions. `Toggle` only keeps post filters, the other constraints pass through it to its operand.
 /// 
 /// `ns`: the namespace(s) to filter on
 /// 
 /// `depth`: query depth into the category tree. Only to be used with `InCat`.
 /// 
-/// `redir`: how to deal with redirect pages. Refer to `RedirectStrategy` for more information. Only to be used with `LinkTo`, `Prefix` and `EmbeddedIn`.
+/// `redir`: how to deal with redirect pages. Refer to `RedirectStrategy` for more information. Only to be used with `LinkTo`, `Prefix`, `Complement`, `AllPages`, `RecentChanges`, `EmbeddedIn` and `FileUsage`.
 /// 
 /// `directlink`: how to deal with linking via redirects. Only to be used with `LinkTo`.
 /// 
 /// `resolveredir`: If a page is a redirect, how to deal with it.
-#[derive(Debug, Clone)]
+/// 
+/// `protect`: only keep pages with the given protection. Refer to `ProtectionFilter` for more information. Only to be used with `Prefix`, `Complement` and `AllPages`.
+/// 
+/// `added_after`, `added_before`: only keep pages added to the category within the time range. Only to be used with `InCat`.
+/// 
+/// `sort`: the order to fetch category members in. Refer to `CategorySort` for more information. Only to be used with `InCat`.
+/// 
+/// `size_lt`, `size_gt`: only keep pages smaller or larger than the given size in bytes.
+/// 
+/// `touched_after`, `touched_before`: only keep pages last touched within the time range.
+/// 
+/// `protected`: only keep pages in the given protection state. Refer to `ProtectionStatus` for more information.
+/// 
+/// `media_type`: only keep files of the given types. Refer to `MediaTypeFilter` for more information.
+/// 
+/// `content`: only keep pages whose wikitext matches, or does not match, regular expressions. A page must pass every filter in the list. Refer to `ContentFilter` for more information.
+/// 
+/// `is_redirect`: only keep redirects if `true`, or only pages other than redirects if `false`.
+/// Unlike `redir`, which not every generator can apply, it checks each page of the result, so it works the same everywhere.
+/// These are post filters, i.e. they are applied to the result after it is generated, so they can be used with any instruction that takes constraints.
+/// 
+/// `keep_foreign`: whether to keep special pages and pages on other wikis in the result of a generator, `LangLinks` included.
+/// They are dropped by default, as they cannot be queried further.
+/// 
+/// `keep_fragment`: whether to keep the `#fragment` of titles written in a query. They are stripped by default, so that `Foo#bar` and `Foo` are the same page. Only to be used with `Set`.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct SetConstraint {
     pub ns: Option<HashSet<NamespaceID>>,
     pub depth: Option<DepthNum>,
@@ -61,6 +360,20 @@ pub struct SetConstraint {
     pub directlink: Option<bool>,
     pub resolveredir: Option<bool>,
     pub limit: Option<i64>,
+    pub protect: Option<ProtectionFilter>,
+    pub added_after: Option<Timestamp>,
+    pub added_before: Option<Timestamp>,
+    pub sort: Option<CategorySort>,
+    pub size_lt: Option<u64>,
+    pub size_gt: Option<u64>,
+    pub touched_after: Option<Timestamp>,
+    pub touched_before: Option<Timestamp>,
+    pub protected: Option<ProtectionStatus>,
+    pub media_type: Option<MediaTypeFilter>,
+    pub is_redirect: Option<bool>,
+    pub content: Vec<ContentFilter>,
+    pub keep_foreign: Option<bool>,
+    pub keep_fragment: Option<bool>,
 }
 
 impl SetConstraint {
@@ -72,8 +385,56 @@ impl SetConstraint {
             directlink: None,
             resolveredir: None,
             limit: None,
+            protect: None,
+            added_after: None,
+            added_before: None,
+            sort: None,
+            size_lt: None,
+            size_gt: None,
+            touched_after: None,
+            touched_before: None,
+            protected: None,
+            media_type: None,
+            is_redirect: None,
+            content: Vec::new(),
+            keep_foreign: None,
+            keep_fragment: None,
         }
     }
+
+    /// Whether any post filter is set. Refer to the documentation of `SetConstraint` for which constraints are post filters.
+    pub fn has_post_filter(&self) -> bool {
+        self.size_lt.is_some() || self.size_gt.is_some() || self.touched_after.is_some() || self.touched_before.is_some() || self.protected.is_some() || self.media_type.is_some() || self.is_redirect.is_some() || !self.content.is_empty()
+    }
+
+    /// Split the constraint into the part about how pages are generated, and the post filters.
+    /// `keep_foreign` goes into both, as it applies to every step.
+    pub fn split_post_filters(&self) -> (SetConstraint, SetConstraint) {
+        let filters = SetConstraint {
+            size_lt: self.size_lt,
+            size_gt: self.size_gt,
+            touched_after: self.touched_after,
+            touched_before: self.touched_before,
+            protected: self.protected.clone(),
+            media_type: self.media_type.clone(),
+            is_redirect: self.is_redirect,
+            content: self.content.clone(),
+            keep_foreign: self.keep_foreign,
+            ..SetConstraint::new()
+        };
+        let rest = SetConstraint {
+            size_lt: None,
+            size_gt: None,
+            touched_after: None,
+            touched_before: None,
+            protected: None,
+            media_type: None,
+            is_redirect: None,
+            content: Vec::new(),
+            ..self.clone()
+        };
+        (rest, filters)
+    }
 }
 
 impl Default for SetConstraint {
@@ -82,7 +443,38 @@ impl Default for SetConstraint {
     }
 }
 
-#[derive(Debug, Clone)]
+impl Hash for SetConstraint {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // `HashSet` does not implement `Hash`, so hash the namespaces in sorted order instead
+        let ns = self.ns.as_ref().map(|ns| {
+            let mut ns_vec: Vec<NamespaceID> = ns.iter().copied().collect();
+            ns_vec.sort_unstable();
+            ns_vec
+        });
+        ns.hash(state);
+        self.depth.hash(state);
+        self.redir.hash(state);
+        self.directlink.hash(state);
+        self.resolveredir.hash(state);
+        self.limit.hash(state);
+        self.protect.hash(state);
+        self.added_after.hash(state);
+        self.added_before.hash(state);
+        self.sort.hash(state);
+        self.size_lt.hash(state);
+        self.size_gt.hash(state);
+        self.touched_after.hash(state);
+        self.touched_before.hash(state);
+        self.protected.hash(state);
+        self.media_type.hash(state);
+        self.is_redirect.hash(state);
+        self.content.hash(state);
+        self.keep_foreign.hash(state);
+        self.keep_fragment.hash(state);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Instruction {
     // Binary
     And { dest: RegID, op1: RegID, op2: RegID },
@@ -93,11 +485,30 @@ pub enum Instruction {
     Link { dest: RegID, op: RegID, cs: SetConstraint },
     LinkTo { dest: RegID, op: RegID, cs: SetConstraint },
     EmbeddedIn { dest: RegID, op: RegID, cs: SetConstraint },
+    RedirectsTo { dest: RegID, op: RegID, cs: SetConstraint },
+    FileUsage { dest: RegID, op: RegID, cs: SetConstraint },
+    TemplatesOn { dest: RegID, op: RegID, cs: SetConstraint },
     InCat { dest: RegID, op: RegID, cs: SetConstraint },
-    Toggle { dest: RegID, op: RegID },
+    Toggle { dest: RegID, op: RegID, cs: SetConstraint },
+    ResolveRedirect { dest: RegID, op: RegID },
+    LangLinks { dest: RegID, op: RegID, cs: SetConstraint },
     Prefix { dest: RegID, op: RegID, cs: SetConstraint },
+    Limit { dest: RegID, op: RegID, count: usize },
+    Complement { dest: RegID, op: RegID, cs: SetConstraint },
+    // Terminal
+    Count { dest: RegID, op: RegID },
     // Primitive
     Set { dest: RegID, titles: Vec<String>, cs: SetConstraint },
+    Search { dest: RegID, query: String, what: SearchWhat, cs: SetConstraint },
+    WithProp { dest: RegID, prop: String, cs: SetConstraint },
+    AllPages { dest: RegID, ns: NamespaceID, cs: SetConstraint },
+    UserContribs { dest: RegID, user: String, created: bool, cs: SetConstraint },
+    RecentChanges { dest: RegID, hours: u64, kind: Option<RecentChangeType>, cs: SetConstraint },
+    DoubleRedirects { dest: RegID, cs: SetConstraint },
+    BrokenRedirects { dest: RegID, cs: SetConstraint },
+    ProtectedTitles { dest: RegID, level: Option<String>, cs: SetConstraint },
+    ExtLink { dest: RegID, url: String, cs: SetConstraint },
+    MissingParam { dest: RegID, template: String, param: String, cs: SetConstraint },
     // Null
     Nop { dest: RegID, op: RegID },
 }
@@ -109,17 +520,58 @@ impl Instruction {
     }
 
     pub fn is_unary_op(&self) -> bool {
-        matches!(*self, Self::Link {..} | Self::LinkTo {..} | Self::EmbeddedIn {..} | Self::InCat {..} | Self::Toggle {..} | Self::Prefix {..})
+        matches!(*self, Self::Link {..} | Self::LinkTo {..} | Self::EmbeddedIn {..} | Self::RedirectsTo {..} | Self::FileUsage {..} | Self::TemplatesOn {..} | Self::InCat {..} | Self::Toggle {..} | Self::ResolveRedirect {..} | Self::LangLinks {..} | Self::Prefix {..} | Self::Limit {..} | Self::Complement {..} | Self::Count {..})
     }
 
     pub fn is_primitive_op(&self) -> bool {
-        matches!(*self, Self::Set {..})
+        matches!(*self, Self::Set {..} | Self::Search {..} | Self::WithProp {..} | Self::AllPages {..} | Self::UserContribs {..} | Self::RecentChanges {..} | Self::DoubleRedirects {..} | Self::BrokenRedirects {..} | Self::ProtectedTitles {..} | Self::ExtLink {..} | Self::MissingParam {..})
     }
 
     pub fn is_nop(&self) -> bool {
         matches!(*self, Self::Nop {..})
     }
 
+    /// Whether the instruction fetches pages from the wiki, rather than only working on the pages it is given.
+    pub fn is_generator(&self) -> bool {
+        !(self.is_binary_op() || self.is_nop() || matches!(*self, Self::Toggle {..} | Self::Limit {..} | Self::Count {..}))
+    }
+
+    /// The name of the variant, e.g. `LinkTo`, for logs.
+    pub fn kind(&self) -> &'static str {
+        match *self {
+            Self::And { .. } => "And",
+            Self::Or { .. } => "Or",
+            Self::Exclude { .. } => "Exclude",
+            Self::Xor { .. } => "Xor",
+            Self::Link { .. } => "Link",
+            Self::LinkTo { .. } => "LinkTo",
+            Self::EmbeddedIn { .. } => "EmbeddedIn",
+            Self::RedirectsTo { .. } => "RedirectsTo",
+            Self::FileUsage { .. } => "FileUsage",
+            Self::TemplatesOn { .. } => "TemplatesOn",
+            Self::InCat { .. } => "InCat",
+            Self::Toggle { .. } => "Toggle",
+            Self::ResolveRedirect { .. } => "ResolveRedirect",
+            Self::LangLinks { .. } => "LangLinks",
+            Self::Prefix { .. } => "Prefix",
+            Self::Limit { .. } => "Limit",
+            Self::Complement { .. } => "Complement",
+            Self::Count { .. } => "Count",
+            Self::Set { .. } => "Set",
+            Self::Search { .. } => "Search",
+            Self::WithProp { .. } => "WithProp",
+            Self::AllPages { .. } => "AllPages",
+            Self::UserContribs { .. } => "UserContribs",
+            Self::RecentChanges { .. } => "RecentChanges",
+            Self::DoubleRedirects { .. } => "DoubleRedirects",
+            Self::BrokenRedirects { .. } => "BrokenRedirects",
+            Self::ProtectedTitles { .. } => "ProtectedTitles",
+            Self::ExtLink { .. } => "ExtLink",
+            Self::MissingParam { .. } => "MissingParam",
+            Self::Nop { .. } => "Nop",
+        }
+    }
+
     pub fn get_dest(&self) -> RegID {
         match *self {
             Self::And { dest, .. } => dest,
@@ -129,14 +581,67 @@ impl Instruction {
             Self::Link { dest, .. } => dest,
             Self::LinkTo { dest, .. } => dest,
             Self::EmbeddedIn { dest, .. } => dest,
+            Self::RedirectsTo { dest, .. } => dest,
+            Self::FileUsage { dest, .. } => dest,
+            Self::TemplatesOn { dest, .. } => dest,
             Self::InCat { dest, .. } => dest,
             Self::Toggle { dest, ..} => dest,
+            Self::ResolveRedirect { dest, ..} => dest,
+            Self::LangLinks { dest, ..} => dest,
             Self::Prefix { dest, .. } => dest,
+            Self::Limit { dest, .. } => dest,
+            Self::Complement { dest, .. } => dest,
+            Self::Count { dest, .. } => dest,
             Self::Set { dest, .. } => dest,
+            Self::Search { dest, .. } => dest,
+            Self::WithProp { dest, .. } => dest,
+            Self::AllPages { dest, .. } => dest,
+            Self::UserContribs { dest, .. } => dest,
+            Self::RecentChanges { dest, .. } => dest,
+            Self::DoubleRedirects { dest, .. } => dest,
+            Self::BrokenRedirects { dest, .. } => dest,
+            Self::ProtectedTitles { dest, .. } => dest,
+            Self::ExtLink { dest, .. } => dest,
+            Self::MissingParam { dest, .. } => dest,
             Self::Nop { dest, .. } => dest,
         }
     }
 
+    pub fn get_operands(&self) -> Vec<RegID> {
+        match *self {
+            Self::And { op1, op2, .. } |
+            Self::Or { op1, op2, .. } |
+            Self::Exclude { op1, op2, .. } |
+            Self::Xor { op1, op2, .. } => vec![op1, op2],
+            Self::Link { op, .. } |
+            Self::LinkTo { op, .. } |
+            Self::EmbeddedIn { op, .. } |
+            Self::RedirectsTo { op, .. } |
+            Self::FileUsage { op, .. } |
+            Self::TemplatesOn { op, .. } |
+            Self::InCat { op, .. } |
+            Self::Toggle { op, .. } |
+            Self::ResolveRedirect { op, .. } |
+            Self::LangLinks { op, .. } |
+            Self::Prefix { op, .. } |
+            Self::Limit { op, .. } |
+            Self::Complement { op, .. } |
+            Self::Count { op, .. } |
+            Self::Nop { op, .. } => vec![op],
+            Self::Set { .. } |
+            Self::Search { .. } |
+            Self::WithProp { .. } |
+            Self::AllPages { .. } |
+            Self::UserContribs { .. } |
+            Self::RecentChanges { .. } |
+            Self::DoubleRedirects { .. } |
+            Self::BrokenRedirects { .. } |
+            Self::ProtectedTitles { .. } |
+            Self::ExtLink { .. } => vec![],
+            Self::MissingParam { .. } => vec![],
+        }
+    }
+
     pub fn set_dest(&mut self, new_dest: RegID) {
         match self {
             Self::And { dest, .. } => *dest = new_dest,
@@ -146,28 +651,128 @@ impl Instruction {
             Self::Link { dest, .. } => *dest = new_dest,
             Self::LinkTo { dest, .. } => *dest = new_dest,
             Self::EmbeddedIn { dest, .. } => *dest = new_dest,
+            Self::RedirectsTo { dest, .. } => *dest = new_dest,
+            Self::FileUsage { dest, .. } => *dest = new_dest,
+            Self::TemplatesOn { dest, .. } => *dest = new_dest,
             Self::InCat { dest, .. } => *dest = new_dest,
             Self::Toggle { dest, ..} => *dest = new_dest,
+            Self::ResolveRedirect { dest, ..} => *dest = new_dest,
+            Self::LangLinks { dest, ..} => *dest = new_dest,
             Self::Prefix { dest, .. } => *dest = new_dest,
+            Self::Limit { dest, .. } => *dest = new_dest,
+            Self::Complement { dest, .. } => *dest = new_dest,
+            Self::Count { dest, .. } => *dest = new_dest,
             Self::Set { dest, .. } => *dest = new_dest,
+            Self::Search { dest, .. } => *dest = new_dest,
+            Self::WithProp { dest, .. } => *dest = new_dest,
+            Self::AllPages { dest, .. } => *dest = new_dest,
+            Self::UserContribs { dest, .. } => *dest = new_dest,
+            Self::RecentChanges { dest, .. } => *dest = new_dest,
+            Self::DoubleRedirects { dest, .. } => *dest = new_dest,
+            Self::BrokenRedirects { dest, .. } => *dest = new_dest,
+            Self::ProtectedTitles { dest, .. } => *dest = new_dest,
+            Self::ExtLink { dest, .. } => *dest = new_dest,
+            Self::MissingParam { dest, .. } => *dest = new_dest,
             Self::Nop { dest, .. } => *dest = new_dest,
         };
     }
 
+    /// A copy of the instruction with `dest` and every operand set to 0.
+    /// Two instructions doing the same thing compare equal this way, wherever they are in a query.
+    pub fn without_registers(&self) -> Self {
+        let mut inst = self.clone();
+        inst.set_dest(0);
+        match &mut inst {
+            Self::And { op1, op2, .. } |
+            Self::Or { op1, op2, .. } |
+            Self::Exclude { op1, op2, .. } |
+            Self::Xor { op1, op2, .. } => {
+                *op1 = 0;
+                *op2 = 0;
+            },
+            Self::Link { op, .. } |
+            Self::LinkTo { op, .. } |
+            Self::EmbeddedIn { op, .. } |
+            Self::RedirectsTo { op, .. } |
+            Self::FileUsage { op, .. } |
+            Self::TemplatesOn { op, .. } |
+            Self::InCat { op, .. } |
+            Self::Toggle { op, .. } |
+            Self::ResolveRedirect { op, .. } |
+            Self::LangLinks { op, .. } |
+            Self::Prefix { op, .. } |
+            Self::Limit { op, .. } |
+            Self::Complement { op, .. } |
+            Self::Count { op, .. } |
+            Self::Nop { op, .. } => *op = 0,
+            _ => (),
+        }
+        inst
+    }
+
+    pub fn get_constraint(&self) -> Option<&SetConstraint> {
+        match self {
+            Self::Link { cs, .. } |
+            Self::LinkTo { cs, .. } |
+            Self::EmbeddedIn { cs, .. } |
+            Self::FileUsage { cs, .. } |
+            Self::TemplatesOn { cs, .. } |
+            Self::RedirectsTo { cs, .. } |
+            Self::InCat { cs, .. } |
+            Self::Prefix { cs, .. } |
+            Self::Complement { cs, .. } |
+            Self::Set { cs, .. } |
+            Self::Search { cs, .. } |
+            Self::WithProp { cs, .. } |
+            Self::AllPages { cs, .. } |
+            Self::UserContribs { cs, .. } |
+            Self::RecentChanges { cs, .. } |
+            Self::DoubleRedirects { cs, .. } |
+            Self::BrokenRedirects { cs, .. } |
+            Self::ProtectedTitles { cs, .. } |
+            Self::ExtLink { cs, .. } => Some(cs),
+            Self::MissingParam { cs, .. } => Some(cs),
+            Self::Toggle { cs, .. } => Some(cs),
+            Self::LangLinks { cs, .. } => Some(cs),
+            _ => None,
+        }
+    }
+
     pub fn ns_empty(&self) -> bool {
         match self {
             Self::Link { cs, .. } |
             Self::LinkTo { cs, .. } |
             Self::EmbeddedIn { cs, .. } |
+            Self::RedirectsTo { cs, .. } |
+            Self::FileUsage { cs, .. } |
+            Self::TemplatesOn { cs, .. } |
             Self::InCat { cs, .. } |
             Self::Prefix { cs, .. } |
-            Self::Set { cs, .. } => {
+            Self::Complement { cs, .. } |
+            Self::Set { cs, .. } |
+            Self::Search { cs, .. } |
+            Self::WithProp { cs, .. } |
+            Self::UserContribs { cs, .. } |
+            Self::RecentChanges { cs, .. } |
+            Self::DoubleRedirects { cs, .. } |
+            Self::BrokenRedirects { cs, .. } |
+            Self::ProtectedTitles { cs, .. } |
+            Self::ExtLink { cs, .. } |
+            Self::MissingParam { cs, .. } => {
                 if let Some(ns) = &cs.ns {
                     ns.is_empty()
                 } else {
                     false
                 }
             },
+            // the namespace of `AllPages` is fixed, so any namespace constraint that leaves it out empties the result
+            Self::AllPages { ns, cs, .. } => {
+                if let Some(nss) = &cs.ns {
+                    !nss.contains(ns)
+                } else {
+                    false
+                }
+            },
             _ => false,
         }
     }
@@ -10,15 +10,26 @@ mod grammar;
 mod optim;
 mod convert;
 mod error;
+mod describe;
+mod analyze;
 pub(crate) mod ir;
 
 pub use error::PLBotParserError;
+pub use describe::describe;
+pub use analyze::is_likely_unbounded;
+pub use ir::DepthMergePolicy;
 
 pub type Query = (Vec<ir::Instruction>, ir::RegID);
 
 type PLBotParseResult = Result<Query, PLBotParserError>;
 
-pub fn parse(src: &str) -> PLBotParseResult {
+/// Whether the query only asks for the number of pages, i.e. it is wrapped in `count`.
+pub fn is_count(query: &Query) -> bool {
+    query.0.iter().any(|i| matches!(i, ir::Instruction::Count { dest, .. } if *dest == query.1))
+}
+
+/// Parse a query. `depth_merge` decides what happens when two different depth constraints meet.
+pub fn parse(src: &str, depth_merge: DepthMergePolicy) -> PLBotParseResult {
     let ast_res = grammar::ExprParser::new().parse(src);
     let ast = match ast_res {
         Ok(e) => {
@@ -28,10 +39,15 @@ pub fn parse(src: &str) -> PLBotParseResult {
             return Err(PLBotParserError::Parse);
         },
     };
-    let (mut ir_ls, ir_fin) = convert::to_ir(&ast)?;
+    let (mut ir_ls, ir_fin) = convert::to_ir(&ast, depth_merge)?;
     optim::remove_redundent_talk(&mut ir_ls);
     optim::remove_empty_ns(&mut ir_ls);
+    optim::remove_idempotent(&mut ir_ls);
+    optim::remove_empty_and(&mut ir_ls);
+    optim::factor_common_and(&mut ir_ls);
 
-    optim::remove_nop(&mut ir_ls);
+    optim::remove_nop(&mut ir_ls)?;
+    optim::eliminate_common_subexpr(&mut ir_ls);
+    optim::remove_unreachable(&mut ir_ls, ir_fin);
     Ok((ir_ls, ir_fin))
 }
@@ -2,29 +2,101 @@
 //! on an Abstract Syntax Tree (AST).
 //! 
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
-use super::ir::{Instruction, SetConstraint, RegID, DepthNum, RedirectFilterStrategy};
+use super::ir::{Instruction, SetConstraint, ContentFilter, CONTENT_FILTER_FLAGS, RegID, DepthNum, DepthMergePolicy, RedirectFilterStrategy, ProtectionFilter, ProtectionStatus, MediaTypeFilter, CategorySort, Timestamp};
+use chrono::TimeZone;
 use mediawiki::api::NamespaceID;
 
 use super::{ast::*, error::PLBotParserError};
 
+/// Merge two different depths according to `policy`
+/// Two negative depths both mean unlimited depth, so they never conflict
+fn merge_depth(d1: DepthNum, d2: DepthNum, policy: DepthMergePolicy) -> Result<DepthNum, PLBotParserError> {
+    if d1 == d2 || (d1 < 0 && d2 < 0) {
+        return Ok(d1);
+    }
+    match policy {
+        DepthMergePolicy::Conflict => Err(PLBotParserError::Semantic(String::from("conflict depth"))),
+        DepthMergePolicy::Max => Ok(if d1 < 0 || d2 < 0 { -1 } else { DepthNum::max(d1, d2) }),
+        DepthMergePolicy::Min => Ok(if d1 < 0 { d2 } else if d2 < 0 { d1 } else { DepthNum::min(d1, d2) }),
+    }
+}
+
+/// Parse a timestamp written in a query, either a full ISO 8601 timestamp like `2022-01-31T12:00:00Z`, or a date like `2022-01-31` meaning its midnight in UTC
+fn parse_timestamp(s: &str) -> Result<Timestamp, PLBotParserError> {
+    if let Ok(t) = chrono::DateTime::parse_from_rfc3339(s) {
+        Ok(t.with_timezone(&chrono::Utc))
+    } else if let Some(t) = chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d").ok().and_then(|d| d.and_hms_opt(0, 0, 0)) {
+        Ok(chrono::Utc.from_utc_datetime(&t))
+    } else {
+        Err(PLBotParserError::Semantic(format!("invalid timestamp \"{}\"", s)))
+    }
+}
+
+/// Merge two optional time bounds, keeping the later one if `later` is set, otherwise the earlier one
+fn merge_time_bound(t1: Option<Timestamp>, t2: Option<Timestamp>, later: bool) -> Option<Timestamp> {
+    match (t1, t2) {
+        (None, t) | (t, None) => t,
+        (Some(t1), Some(t2)) => Some(if later { t1.max(t2) } else { t1.min(t2) }),
+    }
+}
+
+/// Reject a time window that cannot hold anything, i.e. one that does not end after it starts
+fn check_time_window(after: Option<Timestamp>, before: Option<Timestamp>, what: &str) -> Result<(), PLBotParserError> {
+    match (after, before) {
+        (Some(after), Some(before)) if after >= before => Err(PLBotParserError::Semantic(format!("empty {} time window, {} is not before {}", what, after.to_rfc3339(), before.to_rfc3339()))),
+        _ => Ok(()),
+    }
+}
+
+/// Merge two optional size bounds, keeping the larger one if `larger` is set, otherwise the smaller one
+fn merge_size_bound(s1: Option<u64>, s2: Option<u64>, larger: bool) -> Option<u64> {
+    match (s1, s2) {
+        (None, s) | (s, None) => s,
+        (Some(s1), Some(s2)) => Some(if larger { s1.max(s2) } else { s1.min(s2) }),
+    }
+}
+
 /// Convert a `Vec` of `Constraint`s into a `SetConstraint`
-/// Merge all `Ns` constraints (using intersection), set all `Limit` constraints to the minimum, and reject any other duplicate-and-confilcting constraints
-pub(crate) fn construct_constraints_from_vec(orig: &[Constraint]) -> Result<SetConstraint, PLBotParserError> {
+/// Merge all `Ns` constraints (using intersection), set all `Limit` constraints to the minimum, merge `Depth` constraints by `depth_merge`, and reject any other duplicate-and-confilcting constraints
+/// Two non-empty `Ns` constraints that share no namespace are also rejected, since they can only be a mistake
+/// Time ranges and size ranges are merged by intersection
+pub(crate) fn construct_constraints_from_vec(orig: &[Constraint], depth_merge: DepthMergePolicy) -> Result<SetConstraint, PLBotParserError> {
     let mut depth: Option<DepthNum> = None;
     let mut ns: Option<HashSet<NamespaceID>> = None;
     let mut redir: Option<RedirectFilterStrategy> = None;
     let mut directlink: Option<bool> = None;
     let mut resolveredir: Option<bool> = None;
+    let mut keep_foreign: Option<bool> = None;
+    let mut keep_fragment: Option<bool> = None;
     let mut limit: Option<i64> = None;
+    let mut protect: Option<ProtectionFilter> = None;
+    let mut added_after: Option<Timestamp> = None;
+    let mut added_before: Option<Timestamp> = None;
+    let mut sort: Option<CategorySort> = None;
+    let mut size_lt: Option<u64> = None;
+    let mut size_gt: Option<u64> = None;
+    let mut touched_after: Option<Timestamp> = None;
+    let mut touched_before: Option<Timestamp> = None;
+    let mut protected: Option<ProtectionStatus> = None;
+    let mut media_type: Option<MediaTypeFilter> = None;
+    let mut is_redirect: Option<bool> = None;
+    let mut content: Vec<ContentFilter> = Vec::new();
 
     for c in orig {
         match c {
             Constraint::Ns(n) => {
                 if let Some(old_set) = ns {
-                    let new_set = n.iter().copied().collect();
-                    let intersect_set = old_set.intersection(&new_set).copied().collect();
+                    let new_set: HashSet<NamespaceID> = n.iter().copied().collect();
+                    let intersect_set: HashSet<NamespaceID> = old_set.intersection(&new_set).copied().collect();
+                    if intersect_set.is_empty() && !old_set.is_empty() && !new_set.is_empty() {
+                        let mut old_vec = Vec::from_iter(old_set);
+                        let mut new_vec = Vec::from_iter(new_set);
+                        old_vec.sort_unstable();
+                        new_vec.sort_unstable();
+                        return Err(PLBotParserError::Semantic(format!("conflict namespace constraint {:?} and {:?}", old_vec, new_vec)));
+                    }
                     ns = Some(intersect_set);
                 } else {
                     ns = Some(n.iter().copied().collect());
@@ -32,9 +104,7 @@ pub(crate) fn construct_constraints_from_vec(orig: &[Constraint]) -> Result<SetC
             },
             Constraint::Depth(d) => {
                 if let Some(n) = depth {
-                    if n != *d && (n >= 0 || *d >= 0) { // Disallow different depth constraints, except they are both negative
-                        return Err(PLBotParserError::Semantic("conflict depth".to_string()));
-                    }
+                    depth = Some(merge_depth(n, *d, depth_merge)?);
                 } else {
                     depth = Some(*d);
                 }
@@ -66,6 +136,24 @@ pub(crate) fn construct_constraints_from_vec(orig: &[Constraint]) -> Result<SetC
                     resolveredir = Some(*s);
                 }
             },
+            Constraint::KeepForeign(s) => {
+                if let Some(ss) = keep_foreign {
+                    if ss != *s {
+                        return Err(PLBotParserError::Semantic("conflict keepforeign constraint".to_string()));
+                    }
+                } else {
+                    keep_foreign = Some(*s);
+                }
+            },
+            Constraint::KeepFragment(s) => {
+                if let Some(ss) = keep_fragment {
+                    if ss != *s {
+                        return Err(PLBotParserError::Semantic("conflict keepfragment constraint".to_string()));
+                    }
+                } else {
+                    keep_fragment = Some(*s);
+                }
+            },
             Constraint::Limit(l) => {
                 if let Some(ll) = limit {
                     if ll < 0 {
@@ -76,15 +164,110 @@ pub(crate) fn construct_constraints_from_vec(orig: &[Constraint]) -> Result<SetC
                 } else {
                     limit = Some(*l);
                 }
-            }
+            },
+            Constraint::Protect(t, l) => {
+                let prtype = t.parse().map_err(|_| PLBotParserError::Semantic(format!("invalid protection type \"{}\"", t)))?;
+                let new_protect = ProtectionFilter { prtype, prlevel: l.clone() };
+                if let Some(p) = &protect {
+                    if *p != new_protect {
+                        return Err(PLBotParserError::Semantic("conflict protection constraint".to_string()));
+                    }
+                } else {
+                    protect = Some(new_protect);
+                }
+            },
+            Constraint::AddedAfter(t) => {
+                added_after = merge_time_bound(added_after, Some(parse_timestamp(t)?), true);
+            },
+            Constraint::AddedBefore(t) => {
+                added_before = merge_time_bound(added_before, Some(parse_timestamp(t)?), false);
+            },
+            Constraint::Sort(k, d) => {
+                let key = k.parse().map_err(|_| PLBotParserError::Semantic(format!("invalid sort key \"{}\"", k)))?;
+                let dir = match d {
+                    Some(d) => d.parse().map_err(|_| PLBotParserError::Semantic(format!("invalid sort direction \"{}\"", d)))?,
+                    None => Default::default(),
+                };
+                let new_sort = CategorySort { key, dir };
+                if sort.is_some() && sort != Some(new_sort) {
+                    return Err(PLBotParserError::Semantic("conflict sort constraint".to_string()));
+                }
+                sort = Some(new_sort);
+            },
+            Constraint::SizeLt(s) | Constraint::SizeGt(s) => {
+                if *s < 0 {
+                    return Err(PLBotParserError::Semantic("invalid size constraint".to_string()));
+                }
+                if matches!(c, Constraint::SizeLt(_)) {
+                    size_lt = merge_size_bound(size_lt, Some(*s as u64), false);
+                } else {
+                    size_gt = merge_size_bound(size_gt, Some(*s as u64), true);
+                }
+            },
+            Constraint::TouchedAfter(t) => {
+                touched_after = merge_time_bound(touched_after, Some(parse_timestamp(t)?), true);
+            },
+            Constraint::TouchedBefore(t) => {
+                touched_before = merge_time_bound(touched_before, Some(parse_timestamp(t)?), false);
+            },
+            Constraint::Protected(t, _, _) | Constraint::Unprotected(t) => {
+                let prtype = t.parse().map_err(|_| PLBotParserError::Semantic(format!("invalid protection type \"{}\"", t)))?;
+                let new_protected = match c {
+                    Constraint::Protected(_, l, indefinite) => ProtectionStatus::Protected { filter: ProtectionFilter { prtype, prlevel: l.clone() }, indefinite: *indefinite },
+                    _ => ProtectionStatus::Unprotected(prtype),
+                };
+                if let Some(p) = &protected {
+                    if *p != new_protected {
+                        return Err(PLBotParserError::Semantic("conflict protection constraint".to_string()));
+                    }
+                } else {
+                    protected = Some(new_protected);
+                }
+            },
+            Constraint::MediaType(types, keep_non_file) => {
+                if types.is_empty() {
+                    return Err(PLBotParserError::Semantic("invalid media type constraint".to_string()));
+                }
+                let mut types = types.clone();
+                types.sort_unstable();
+                types.dedup();
+                let new_media_type = MediaTypeFilter { types, keep_non_file: *keep_non_file };
+                if media_type.is_some() && media_type.as_ref() != Some(&new_media_type) {
+                    return Err(PLBotParserError::Semantic("conflict media type constraint".to_string()));
+                }
+                media_type = Some(new_media_type);
+            },
+            Constraint::IsRedirect(r) => {
+                if is_redirect.is_some() && is_redirect != Some(*r) {
+                    return Err(PLBotParserError::Semantic("conflict redirect filter".to_string()));
+                }
+                is_redirect = Some(*r);
+            },
+            Constraint::Content(pattern, flags, invert) => {
+                let mut flags: Vec<char> = flags.as_deref().unwrap_or_default().chars().collect();
+                if flags.iter().any(|f| !CONTENT_FILTER_FLAGS.contains(*f)) {
+                    return Err(PLBotParserError::Semantic("invalid regular expression flags".to_string()));
+                }
+                flags.sort_unstable();
+                flags.dedup();
+                let new_content = ContentFilter { pattern: pattern.clone(), flags: flags.into_iter().collect(), invert: *invert };
+                if let Err(e) = new_content.regex() {
+                    return Err(PLBotParserError::Semantic(format!("invalid regular expression: {}", e)));
+                }
+                content.push(new_content);
+            },
         }
     }
-    Ok( SetConstraint { ns, depth, redir, directlink, resolveredir, limit } )
+    check_time_window(added_after, added_before, "added")?;
+    check_time_window(touched_after, touched_before, "touched")?;
+    content.sort_unstable();
+    content.dedup();
+    Ok( SetConstraint { ns, depth, redir, directlink, resolveredir, limit, protect, added_after, added_before, sort, size_lt, size_gt, touched_after, touched_before, protected, media_type, is_redirect, content, keep_foreign, keep_fragment } )
 }
 
 /// Merge two `SetConstraint`s into one
-/// `Ns` will be merged by intersection, `Limit` will get the minimum number, for other constraints, return error if they conflict.
-pub(crate) fn merge_constraints(orig: &SetConstraint, other: &SetConstraint) -> Result<SetConstraint, PLBotParserError> {
+/// `Ns`, time ranges and size ranges will be merged by intersection, `Limit` will get the minimum number, `Depth` will be merged by `depth_merge`, content filters are all kept, for other constraints, return error if they conflict.
+pub(crate) fn merge_constraints(orig: &SetConstraint, other: &SetConstraint, depth_merge: DepthMergePolicy) -> Result<SetConstraint, PLBotParserError> {
     let ns = if orig.ns.is_none() {
         other.ns.clone()
     } else if other.ns.is_none() {
@@ -92,12 +275,9 @@ pub(crate) fn merge_constraints(orig: &SetConstraint, other: &SetConstraint) ->
     } else {
         Some(orig.ns.as_ref().unwrap().intersection(other.ns.as_ref().unwrap()).copied().collect())
     };
-    let depth = if orig.depth.is_none() {
-        other.depth
-    } else if other.depth.is_none() || (orig.depth.unwrap() == other.depth.unwrap()) || (orig.depth.unwrap() < 0 && other.depth.unwrap() < 0) {
-        orig.depth
-    } else {
-        return Err(PLBotParserError::Semantic(String::from("conflict depth")));
+    let depth = match (orig.depth, other.depth) {
+        (None, d) | (d, None) => d,
+        (Some(d1), Some(d2)) => Some(merge_depth(d1, d2, depth_merge)?),
     };
     let redir = if orig.redir.is_none() {
         other.redir
@@ -120,6 +300,16 @@ pub(crate) fn merge_constraints(orig: &SetConstraint, other: &SetConstraint) ->
     } else {
         return Err(PLBotParserError::Semantic(String::from("conflict resolveredir constraint")));
     };
+    let keep_foreign = match (orig.keep_foreign, other.keep_foreign) {
+        (None, k) | (k, None) => k,
+        (Some(k1), Some(k2)) if k1 == k2 => Some(k1),
+        _ => return Err(PLBotParserError::Semantic(String::from("conflict keepforeign constraint"))),
+    };
+    let keep_fragment = match (orig.keep_fragment, other.keep_fragment) {
+        (None, k) | (k, None) => k,
+        (Some(k1), Some(k2)) if k1 == k2 => Some(k1),
+        _ => return Err(PLBotParserError::Semantic(String::from("conflict keepfragment constraint"))),
+    };
     let limit = if orig.limit.is_none() || orig.limit.unwrap() < 0 {
         other.limit
     } else if other.limit.is_none() || other.limit.unwrap() < 0 {
@@ -127,20 +317,64 @@ pub(crate) fn merge_constraints(orig: &SetConstraint, other: &SetConstraint) ->
     } else {
         Some(i64::min(orig.limit.unwrap(), other.limit.unwrap()))
     };
+    let protect = match (&orig.protect, &other.protect) {
+        (None, p) | (p, None) => p.clone(),
+        (Some(p1), Some(p2)) if p1 == p2 => Some(p1.clone()),
+        _ => return Err(PLBotParserError::Semantic(String::from("conflict protection constraint"))),
+    };
 
-    Ok(SetConstraint { ns, depth, redir, directlink, resolveredir, limit })
+    let added_after = merge_time_bound(orig.added_after, other.added_after, true);
+    let added_before = merge_time_bound(orig.added_before, other.added_before, false);
+    let sort = match (orig.sort, other.sort) {
+        (None, s) | (s, None) => s,
+        (Some(s1), Some(s2)) if s1 == s2 => Some(s1),
+        _ => return Err(PLBotParserError::Semantic(String::from("conflict sort constraint"))),
+    };
+    let size_lt = merge_size_bound(orig.size_lt, other.size_lt, false);
+    let size_gt = merge_size_bound(orig.size_gt, other.size_gt, true);
+    let touched_after = merge_time_bound(orig.touched_after, other.touched_after, true);
+    let touched_before = merge_time_bound(orig.touched_before, other.touched_before, false);
+    let protected = match (&orig.protected, &other.protected) {
+        (None, p) | (p, None) => p.clone(),
+        (Some(p1), Some(p2)) if p1 == p2 => Some(p1.clone()),
+        _ => return Err(PLBotParserError::Semantic(String::from("conflict protection constraint"))),
+    };
+    let media_type = match (&orig.media_type, &other.media_type) {
+        (None, m) | (m, None) => m.clone(),
+        (Some(m1), Some(m2)) if m1 == m2 => Some(m1.clone()),
+        _ => return Err(PLBotParserError::Semantic(String::from("conflict media type constraint"))),
+    };
+    let is_redirect = match (orig.is_redirect, other.is_redirect) {
+        (None, r) | (r, None) => r,
+        (Some(r1), Some(r2)) if r1 == r2 => Some(r1),
+        _ => return Err(PLBotParserError::Semantic(String::from("conflict redirect filter"))),
+    };
+    // every content filter must hold, so merging keeps all of them
+    let mut content: Vec<ContentFilter> = orig.content.iter().chain(other.content.iter()).cloned().collect();
+    content.sort_unstable();
+    content.dedup();
+    check_time_window(added_after, added_before, "added")?;
+    check_time_window(touched_after, touched_before, "touched")?;
+
+    Ok(SetConstraint { ns, depth, redir, directlink, resolveredir, limit, protect, added_after, added_before, sort, size_lt, size_gt, touched_after, touched_before, protected, media_type, is_redirect, content, keep_foreign, keep_fragment })
 }
 
-/// Removes consecutive `Toggle` instructions
+/// Removes consecutive `Toggle` instructions, unless either has post filters
 pub(crate) fn remove_redundent_talk(ir: &mut Vec<Instruction>) {
     // iterate through every instruction
     // if we encounter a `Toggle { dest, op }`, check the corresponding instruction whose `dest` is the aforementioned `Toggle` instruction's op
     // if that instruction is also a `Toggle { dest2, op2 }` i.e. `dest2 == op`
     // change the two instructions into `Nop { dest, op }` instructions
     for idx in 0..ir.len() {
-        if let Instruction::Toggle { dest, op } = ir[idx] {
+        if let Instruction::Toggle { dest, op, ref cs } = ir[idx] {
+            if cs.has_post_filter() {
+                continue;
+            }
             if let Ok(idx2) = ir.binary_search_by(|probe| probe.get_dest().cmp(&op)) {
-                if let Instruction::Toggle { dest: dest2, op: op2 } = ir[idx2] {
+                if let Instruction::Toggle { dest: dest2, op: op2, cs: ref cs2 } = ir[idx2] {
+                    if cs2.has_post_filter() {
+                        continue;
+                    }
                     // change instructions
                     let inst1 = Instruction::Nop { dest, op };
                     let inst2 = Instruction::Nop { dest: dest2, op: op2 };
@@ -182,9 +416,17 @@ pub(crate) fn remove_empty_ns(ir: &mut Vec<Instruction>) {
                         Instruction::Link { dest, op, .. } |
                         Instruction::LinkTo { dest, op, .. } |
                         Instruction::EmbeddedIn { dest, op, .. } |
+                        Instruction::RedirectsTo { dest, op, .. } |
+                        Instruction::FileUsage { dest, op, .. } |
+                        Instruction::TemplatesOn { dest, op, .. } |
                         Instruction::InCat { dest, op, .. } |
-                        Instruction::Toggle { dest, op } |
-                        Instruction::Prefix { dest, op, .. } => {
+                        Instruction::Toggle { dest, op, .. } |
+                        Instruction::ResolveRedirect { dest, op } |
+                        Instruction::LangLinks { dest, op, .. } |
+                        Instruction::Prefix { dest, op, .. } |
+                        Instruction::Limit { dest, op, .. } |
+                        Instruction::Complement { dest, op, .. } |
+                        Instruction::Count { dest, op } => {
                             let emptyinst = Instruction::Nop { dest: *dest, op: *op };
                             stack.push(*op);
                             ir[idx] = emptyinst;
@@ -193,6 +435,19 @@ pub(crate) fn remove_empty_ns(ir: &mut Vec<Instruction>) {
                             titles.clear();
                             *cs = SetConstraint::new();
                         },
+                        Instruction::Search { dest, .. } |
+                        Instruction::WithProp { dest, .. } |
+                        Instruction::AllPages { dest, .. } |
+                        Instruction::UserContribs { dest, .. } |
+                        Instruction::RecentChanges { dest, .. } |
+                        Instruction::DoubleRedirects { dest, .. } |
+                        Instruction::BrokenRedirects { dest, .. } |
+                        Instruction::ProtectedTitles { dest, .. } |
+                        Instruction::ExtLink { dest, .. } |
+                        Instruction::MissingParam { dest, .. } => {
+                            let emptyinst = Instruction::Set { dest: *dest, titles: Vec::new(), cs: SetConstraint::new() };
+                            ir[idx] = emptyinst;
+                        },
                         Instruction::Nop { dest: _, op } => {
                             stack.push(*op);
                         },
@@ -203,14 +458,241 @@ pub(crate) fn remove_empty_ns(ir: &mut Vec<Instruction>) {
     }
 }
 
+fn find(ir: &[Instruction], reg: RegID) -> Option<&Instruction> {
+    ir.binary_search_by(|probe| probe.get_dest().cmp(&reg)).ok().map(|idx| &ir[idx])
+}
+
+/// Collects the registers of every instruction in the subtree rooted at `reg`, including `reg` itself
+fn subtree(ir: &[Instruction], reg: RegID) -> Vec<RegID> {
+    let mut regs: Vec<RegID> = Vec::new();
+    let mut stack: Vec<RegID> = vec![reg];
+    while let Some(opdest) = stack.pop() {
+        regs.push(opdest);
+        if let Some(inst) = find(ir, opdest) {
+            stack.extend(inst.get_operands());
+        }
+    }
+    regs
+}
+
+/// Whether the subtrees rooted at `reg1` and `reg2` are structurally identical, and thus yield the same set
+/// 
+/// `Nop` instructions are looked through. The comparison is conservative, e.g. page lists are only equal if written in the same order.
+fn same_subtree(ir: &[Instruction], reg1: RegID, reg2: RegID) -> bool {
+    let mut stack: Vec<(RegID, RegID)> = vec![(reg1, reg2)];
+    while let Some((mut r1, mut r2)) = stack.pop() {
+        while let Some(Instruction::Nop { op, .. }) = find(ir, r1) {
+            r1 = *op;
+        }
+        while let Some(Instruction::Nop { op, .. }) = find(ir, r2) {
+            r2 = *op;
+        }
+        if r1 == r2 {
+            continue;
+        }
+        let (inst1, inst2) = match (find(ir, r1), find(ir, r2)) {
+            (Some(i1), Some(i2)) => (i1, i2),
+            _ => return false,
+        };
+        let same_node = match (inst1, inst2) {
+            (Instruction::And { .. }, Instruction::And { .. }) |
+            (Instruction::Or { .. }, Instruction::Or { .. }) |
+            (Instruction::Exclude { .. }, Instruction::Exclude { .. }) |
+            (Instruction::Xor { .. }, Instruction::Xor { .. }) |
+            (Instruction::ResolveRedirect { .. }, Instruction::ResolveRedirect { .. }) |
+            (Instruction::Count { .. }, Instruction::Count { .. }) => true,
+            (Instruction::Link { cs: cs1, .. }, Instruction::Link { cs: cs2, .. }) |
+            (Instruction::LinkTo { cs: cs1, .. }, Instruction::LinkTo { cs: cs2, .. }) |
+            (Instruction::EmbeddedIn { cs: cs1, .. }, Instruction::EmbeddedIn { cs: cs2, .. }) |
+            (Instruction::RedirectsTo { cs: cs1, .. }, Instruction::RedirectsTo { cs: cs2, .. }) |
+            (Instruction::FileUsage { cs: cs1, .. }, Instruction::FileUsage { cs: cs2, .. }) |
+            (Instruction::TemplatesOn { cs: cs1, .. }, Instruction::TemplatesOn { cs: cs2, .. }) |
+            (Instruction::InCat { cs: cs1, .. }, Instruction::InCat { cs: cs2, .. }) |
+            (Instruction::Toggle { cs: cs1, .. }, Instruction::Toggle { cs: cs2, .. }) |
+            (Instruction::LangLinks { cs: cs1, .. }, Instruction::LangLinks { cs: cs2, .. }) |
+            (Instruction::Prefix { cs: cs1, .. }, Instruction::Prefix { cs: cs2, .. }) |
+            (Instruction::Complement { cs: cs1, .. }, Instruction::Complement { cs: cs2, .. }) => cs1 == cs2,
+            (Instruction::Limit { count: c1, .. }, Instruction::Limit { count: c2, .. }) => c1 == c2,
+            (Instruction::Set { titles: t1, cs: cs1, .. }, Instruction::Set { titles: t2, cs: cs2, .. }) => t1 == t2 && cs1 == cs2,
+            (Instruction::Search { query: q1, what: w1, cs: cs1, .. }, Instruction::Search { query: q2, what: w2, cs: cs2, .. }) => q1 == q2 && w1 == w2 && cs1 == cs2,
+            (Instruction::WithProp { prop: p1, cs: cs1, .. }, Instruction::WithProp { prop: p2, cs: cs2, .. }) => p1 == p2 && cs1 == cs2,
+            (Instruction::AllPages { ns: ns1, cs: cs1, .. }, Instruction::AllPages { ns: ns2, cs: cs2, .. }) => ns1 == ns2 && cs1 == cs2,
+            (Instruction::UserContribs { user: u1, created: c1, cs: cs1, .. }, Instruction::UserContribs { user: u2, created: c2, cs: cs2, .. }) => u1 == u2 && c1 == c2 && cs1 == cs2,
+            (Instruction::RecentChanges { hours: h1, kind: k1, cs: cs1, .. }, Instruction::RecentChanges { hours: h2, kind: k2, cs: cs2, .. }) => h1 == h2 && k1 == k2 && cs1 == cs2,
+            (Instruction::DoubleRedirects { cs: cs1, .. }, Instruction::DoubleRedirects { cs: cs2, .. }) => cs1 == cs2,
+            (Instruction::BrokenRedirects { cs: cs1, .. }, Instruction::BrokenRedirects { cs: cs2, .. }) => cs1 == cs2,
+            (Instruction::ProtectedTitles { level: l1, cs: cs1, .. }, Instruction::ProtectedTitles { level: l2, cs: cs2, .. }) => l1 == l2 && cs1 == cs2,
+            (Instruction::ExtLink { url: u1, cs: cs1, .. }, Instruction::ExtLink { url: u2, cs: cs2, .. }) => u1 == u2 && cs1 == cs2,
+            (Instruction::MissingParam { template: t1, param: p1, cs: cs1, .. }, Instruction::MissingParam { template: t2, param: p2, cs: cs2, .. }) => t1 == t2 && p1 == p2 && cs1 == cs2,
+            _ => false,
+        };
+        if !same_node {
+            return false;
+        }
+        stack.extend(inst1.get_operands().into_iter().zip(inst2.get_operands()));
+    }
+    true
+}
+
+/// Simplifies set operations whose two operands yield the same set
+/// 
+/// `And(x, x)` and `Or(x, x)` become `x`, while `Exclude(x, x)` and `Xor(x, x)` become an empty `Set`.
+/// The subtrees no longer needed are removed.
+pub(crate) fn remove_idempotent(ir: &mut Vec<Instruction>) {
+    let mut dead: HashSet<RegID> = HashSet::new();
+    for idx in 0..ir.len() {
+        let (dest, op1, op2, keep) = match ir[idx] {
+            Instruction::And { dest, op1, op2 } |
+            Instruction::Or { dest, op1, op2 } => (dest, op1, op2, true),
+            Instruction::Exclude { dest, op1, op2 } |
+            Instruction::Xor { dest, op1, op2 } => (dest, op1, op2, false),
+            _ => continue,
+        };
+        if !same_subtree(ir, op1, op2) {
+            continue;
+        }
+        dead.extend(subtree(ir, op2));
+        if keep {
+            ir[idx] = Instruction::Nop { dest, op: op1 };
+        } else {
+            dead.extend(subtree(ir, op1));
+            ir[idx] = Instruction::Set { dest, titles: Vec::new(), cs: SetConstraint::new() };
+        }
+    }
+    ir.retain(|inst| !dead.contains(&inst.get_dest()));
+}
+
+/// Factors a common operand out of an `Or` of two `And`s
+/// 
+/// `Or(And(a, c), And(b, c))` becomes `And(Or(a, b), c)`, so that `c` is solved and intersected once instead of twice.
+/// `c` may be either operand of either `And`, as both `And` and `Or` are commutative.
+/// `Nop` instructions between the `Or` and the `And`s are looked through. The second copy of `c` and the subtrees no longer needed are removed.
+pub(crate) fn factor_common_and(ir: &mut Vec<Instruction>) {
+    let mut dead: HashSet<RegID> = HashSet::new();
+    for idx in 0..ir.len() {
+        let (dest, or1, or2) = match ir[idx] {
+            Instruction::Or { dest, op1, op2 } => (dest, op1, op2),
+            _ => continue,
+        };
+        let skip_nop = |mut reg: RegID| {
+            while let Some(Instruction::Nop { op, .. }) = find(ir, reg) {
+                reg = *op;
+            }
+            reg
+        };
+        let (and1, and2) = (skip_nop(or1), skip_nop(or2));
+        let (a1, b1, a2, b2) = match (find(ir, and1), find(ir, and2)) {
+            (Some(&Instruction::And { op1: a1, op2: b1, .. }), Some(&Instruction::And { op1: a2, op2: b2, .. })) => (a1, b1, a2, b2),
+            _ => continue,
+        };
+        // (common operand, the other operand of the first `And`, copy of the common operand, the other operand of the second `And`)
+        let factored = [(a1, b1, a2, b2), (a1, b1, b2, a2), (b1, a1, a2, b2), (b1, a1, b2, a2)]
+            .into_iter()
+            .find(|&(common, _, copy, _)| same_subtree(ir, common, copy));
+        let (common, rest1, copy, rest2) = match factored {
+            Some(factored) => factored,
+            None => continue,
+        };
+        // the new `Or` reuses the later `And`, so that it still comes after both of its operands
+        let (inner, outer) = if and1 > and2 { (and1, and2) } else { (and2, and1) };
+        for mut reg in [or1, or2] {
+            while let Some(Instruction::Nop { op, .. }) = find(ir, reg) {
+                dead.insert(reg);
+                reg = *op;
+            }
+        }
+        dead.insert(outer);
+        dead.extend(subtree(ir, copy));
+        let inner_idx = ir.binary_search_by(|probe| probe.get_dest().cmp(&inner)).unwrap();
+        ir[inner_idx] = Instruction::Or { dest: inner, op1: rest1, op2: rest2 };
+        ir[idx] = Instruction::And { dest, op1: inner, op2: common };
+    }
+    ir.retain(|inst| !dead.contains(&inst.get_dest()));
+}
+
+/// Replaces `And` instructions that are destined to yield an empty set with an empty `Set` instruction
+/// 
+/// An `And` yields an empty set if either operand does. An operand provably yields an empty set if it is
+/// an empty `Set` (such as the ones left by `remove_empty_ns`), or a set operation or generator on such sets.
+/// The whole subtree under such an `And` is removed, so none of its generators are ever run.
+pub(crate) fn remove_empty_and(ir: &mut Vec<Instruction>) {
+    // `ir` is sorted by `dest`, and an operand always has a smaller `dest` than the instruction using it,
+    // so a single forward pass is enough to find every register that is provably empty
+    let mut empty: HashSet<RegID> = HashSet::new();
+    let mut dead: HashSet<RegID> = HashSet::new();
+    for idx in 0..ir.len() {
+        let yields_empty = match &ir[idx] {
+            Instruction::And { op1, op2, .. } => empty.contains(op1) || empty.contains(op2),
+            Instruction::Or { op1, op2, .. } |
+            Instruction::Xor { op1, op2, .. } => empty.contains(op1) && empty.contains(op2),
+            Instruction::Exclude { op1, .. } => empty.contains(op1),
+            Instruction::Link { op, .. } |
+            Instruction::LinkTo { op, .. } |
+            Instruction::EmbeddedIn { op, .. } |
+            Instruction::RedirectsTo { op, .. } |
+            Instruction::FileUsage { op, .. } |
+            Instruction::TemplatesOn { op, .. } |
+            Instruction::InCat { op, .. } |
+            Instruction::Toggle { op, .. } |
+            Instruction::ResolveRedirect { op, .. } |
+            Instruction::LangLinks { op, .. } |
+            Instruction::Prefix { op, .. } |
+            Instruction::Limit { op, .. } |
+            Instruction::Count { op, .. } |
+            Instruction::Nop { op, .. } => empty.contains(op),
+            // the complement of nothing is the whole namespace
+            Instruction::Complement { .. } => false,
+            Instruction::Set { titles, .. } => titles.is_empty(),
+            Instruction::Search { .. } |
+            Instruction::WithProp { .. } |
+            Instruction::AllPages { .. } |
+            Instruction::UserContribs { .. } |
+            Instruction::RecentChanges { .. } |
+            Instruction::DoubleRedirects { .. } |
+            Instruction::BrokenRedirects { .. } |
+            Instruction::ProtectedTitles { .. } |
+            Instruction::ExtLink { .. } |
+            Instruction::MissingParam { .. } => false,
+        };
+        if !yields_empty {
+            continue;
+        }
+        let dest = ir[idx].get_dest();
+        empty.insert(dest);
+        if let Instruction::And { op1, op2, .. } = ir[idx] {
+            // the whole subtree is dead
+            dead.extend(subtree(ir, op1));
+            dead.extend(subtree(ir, op2));
+            ir[idx] = Instruction::Set { dest, titles: Vec::new(), cs: SetConstraint::new() };
+        }
+    }
+    ir.retain(|inst| !dead.contains(&inst.get_dest()));
+}
+
 /// Removes all Nop instructions
-pub(crate) fn remove_nop(ir: &mut Vec<Instruction>) {
+/// 
+/// A `Nop` that refers to itself, directly or through a cycle of `Nop`s, can only come from a malformed IR.
+/// Such a `Nop` is rejected instead of being silently dropped.
+pub(crate) fn remove_nop(ir: &mut Vec<Instruction>) -> Result<(), PLBotParserError> {
     // iterate through every instruction
     let mut idx = 0;
     while idx < ir.len() {
         let mut deleted = false;
         if let Instruction::Nop { dest, op } = ir[idx] {
-            while let Ok(idx2) = ir.binary_search_by(|probe| probe.get_dest().cmp(&op)) {
+            // follow the chain of `Nop`s starting from here, visiting a register twice means a cycle
+            let mut visited: HashSet<RegID> = HashSet::from([dest]);
+            let mut next = op;
+            while let Ok(idx2) = ir.binary_search_by(|probe| probe.get_dest().cmp(&next)) {
+                if !visited.insert(next) {
+                    return Err(PLBotParserError::Semantic(String::from("cyclic nop instruction")));
+                }
+                if let Instruction::Nop { op: op2, .. } = ir[idx2] {
+                    next = op2;
+                } else {
+                    break;
+                }
+            }
+            if let Ok(idx2) = ir.binary_search_by(|probe| probe.get_dest().cmp(&op)) {
                 ir[idx2].set_dest(dest);
                 ir.remove(idx);
                 deleted = true;
@@ -220,4 +702,91 @@ pub(crate) fn remove_nop(ir: &mut Vec<Instruction>) {
             idx += 1;
         }
     }
+    Ok(())
+}
+
+/// Eliminates common subexpressions
+/// 
+/// If two subtrees are structurally identical, the later one is replaced with a `Nop` that copies the result of the earlier one,
+/// so that the subtree is only solved once.
+/// 
+/// This turns the IR from a tree into a DAG, where a register may be used more than once.
+/// Other passes assume a tree, so this one must run last, after `remove_nop`.
+/// The rest of each replaced subtree is left in place, run `remove_unreachable` afterwards to drop it.
+pub(crate) fn eliminate_common_subexpr(ir: &mut [Instruction]) {
+    // value numbering: map each register to the first register holding the same value
+    // an instruction is keyed by itself, with `dest` erased and operands replaced by their canonical registers
+    let mut canonical: HashMap<RegID, RegID> = HashMap::new();
+    let mut seen: HashMap<Instruction, RegID> = HashMap::new();
+    for inst in ir.iter_mut() {
+        let dest = inst.get_dest();
+        if let Instruction::Nop { op, .. } = inst {
+            let canon = canonical.get(op).copied().unwrap_or(*op);
+            canonical.insert(dest, canon);
+            continue;
+        }
+        let mut key = inst.clone();
+        key.set_dest(0);
+        let canon = |r: &mut RegID| *r = canonical.get(r).copied().unwrap_or(*r);
+        match &mut key {
+            Instruction::And { op1, op2, .. } |
+            Instruction::Or { op1, op2, .. } |
+            Instruction::Exclude { op1, op2, .. } |
+            Instruction::Xor { op1, op2, .. } => {
+                canon(op1);
+                canon(op2);
+            },
+            Instruction::Link { op, .. } |
+            Instruction::LinkTo { op, .. } |
+            Instruction::EmbeddedIn { op, .. } |
+            Instruction::RedirectsTo { op, .. } |
+            Instruction::FileUsage { op, .. } |
+            Instruction::TemplatesOn { op, .. } |
+            Instruction::InCat { op, .. } |
+            Instruction::Toggle { op, .. } |
+            Instruction::ResolveRedirect { op, .. } |
+            Instruction::LangLinks { op, .. } |
+            Instruction::Prefix { op, .. } |
+            Instruction::Limit { op, .. } |
+            Instruction::Complement { op, .. } |
+            Instruction::Count { op, .. } |
+            Instruction::Nop { op, .. } => canon(op),
+            Instruction::Set { .. } |
+            Instruction::Search { .. } |
+            Instruction::WithProp { .. } |
+            Instruction::AllPages { .. } |
+            Instruction::UserContribs { .. } |
+            Instruction::RecentChanges { .. } |
+            Instruction::DoubleRedirects { .. } |
+            Instruction::BrokenRedirects { .. } |
+            Instruction::ProtectedTitles { .. } |
+            Instruction::ExtLink { .. } |
+            Instruction::MissingParam { .. } => {},
+        }
+        if let Some(&first) = seen.get(&key) {
+            canonical.insert(dest, first);
+            *inst = Instruction::Nop { dest, op: first };
+        } else {
+            canonical.insert(dest, dest);
+            seen.insert(key, dest);
+        }
+    }
+}
+
+/// Removes instructions whose result never reaches the final register `fin`
+/// 
+/// Such instructions are left behind by other passes, and would otherwise still be solved.
+/// The remaining instructions keep their order, so `ir` stays sorted by `dest`.
+pub(crate) fn remove_unreachable(ir: &mut Vec<Instruction>, fin: RegID) {
+    // walk the operands from the final register, works on both trees and DAGs
+    let mut reachable: HashSet<RegID> = HashSet::new();
+    let mut stack: Vec<RegID> = vec![fin];
+    while let Some(reg) = stack.pop() {
+        if reachable.insert(reg) {
+            if let Some(inst) = find(ir, reg) {
+                stack.extend(inst.get_operands());
+            }
+        }
+    }
+    ir.retain(|inst| reachable.contains(&inst.get_dest()));
 }
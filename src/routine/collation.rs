@@ -0,0 +1,33 @@
+//! Sorting titles in the order readers of the wiki's content language expect.
+//!
+//! This is the Unicode Collation Algorithm with the CLDR tailoring for the language, as implemented by `icu_collator`,
+//! e.g. `å`, `ä` and `ö` after `z` in Swedish, and by pinyin in Chinese.
+//! Languages without a tailoring of their own use the root collation, which still sorts every script sensibly.
+
+use std::cmp::Ordering;
+
+use icu_locid::Locale;
+
+/// A collator for one language.
+pub struct Collator {
+    inner: icu_collator::Collator,
+}
+
+impl Collator {
+
+    /// The collator for the language code `lang`, such as `sv` or `zh-hans`, or `None` if it cannot be built.
+    /// A language code that is not a valid locale, such as some of MediaWiki's own codes, is reduced to its language part.
+    pub fn new(lang: &str) -> Option<Self> {
+        let locale: Locale = lang.parse()
+            .or_else(|_| lang.split('-').next().unwrap_or_default().parse())
+            .unwrap_or(Locale::UND);
+        let inner = icu_collator::Collator::try_new(&(&locale).into(), icu_collator::CollatorOptions::new()).ok()?;
+        Some(Collator { inner })
+    }
+
+    /// Compare in the order of the language. Strings the collation considers equal are told apart by plain comparison.
+    pub fn compare(&self, a: &str, b: &str) -> Ordering {
+        self.inner.compare(a, b).then_with(|| a.cmp(b))
+    }
+
+}
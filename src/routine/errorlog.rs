@@ -0,0 +1,73 @@
+//! Task failures reported on an on-wiki log page, so that they are not only in the local log file.
+
+use std::collections::HashMap;
+
+use mediawiki::hashmap;
+use tokio::sync::Mutex;
+use tracing::{event, Level};
+
+use crate::apiservice::APIService;
+
+/// Collects task failures, and writes them to the log page in one edit from time to time.
+/// A task failing the same way run after run is only reported the first time, until it succeeds or fails differently.
+pub struct ErrorLog {
+    pending: Mutex<Vec<String>>,
+    last_status: Mutex<HashMap<i64, &'static str>>,
+}
+
+impl ErrorLog {
+
+    pub fn new() -> Self {
+        ErrorLog {
+            pending: Mutex::new(Vec::new()),
+            last_status: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Record how a run of task `id` ended. `status` is `success`, or the kind of failure.
+    pub async fn report(&self, id: i64, status: &'static str) {
+        let mut last_status = self.last_status.lock().await;
+        if last_status.insert(id, status) == Some(status) || status == "success" {
+            return;
+        }
+        let line = format!("* {}: task {} failed ({})", chrono::Utc::now().format("%Y-%m-%d %H:%M UTC"), id, status);
+        self.pending.lock().await.push(line);
+    }
+
+    /// Forget the failures recorded since the last flush, when there is no page to write them to.
+    pub async fn discard(&self) {
+        self.pending.lock().await.clear();
+    }
+
+    /// Append the failures recorded since the last flush to `page`.
+    /// If the edit fails, they are kept for the next flush.
+    pub async fn flush(&self, api: &APIService, page: &str) {
+        let lines: Vec<String> = {
+            let mut pending = self.pending.lock().await;
+            std::mem::take(&mut *pending)
+        };
+        if lines.is_empty() {
+            return;
+        }
+        let params = hashmap![
+            "action".to_string() => "edit".to_string(),
+            "title".to_string() => page.to_owned(),
+            "appendtext".to_string() => format!("\n{}", lines.join("\n")),
+            "summary".to_string() => format!("Report task failures: {}", lines.len()),
+            "token".to_string() => api.csrf().await
+        ];
+        let edit_result = {
+            api.get_lock().lock().await;
+            api.post_edit(&params).await
+        };
+        if let Err(e) = edit_result {
+            event!(Level::WARN, error = ?e, "cannot write error log page");
+            let mut pending = self.pending.lock().await;
+            let newer = std::mem::replace(&mut *pending, lines);
+            pending.extend(newer);
+        } else {
+            event!(Level::INFO, count = lines.len(), "error log page updated");
+        }
+    }
+
+}
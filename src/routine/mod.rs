@@ -5,7 +5,10 @@ pub mod taskfinder;
 pub mod taskrunner;
 mod queryexecutor;
 mod pagewriter;
+mod errorlog;
+mod collation;
 
 mod types;
 
 pub use taskfinder::TaskFinder;
+pub(crate) use pagewriter::{full_title, make_json_value};
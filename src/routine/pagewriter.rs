@@ -1,4 +1,4 @@
-use std::collections::HashSet;
+use std::{collections::{BTreeMap, HashMap, HashSet}, fs, path::{Component, Path, PathBuf}};
 
 use futures::future::join_all;
 use md5::{Md5, Digest};
@@ -6,28 +6,159 @@ use mediawiki::{hashmap, api::NamespaceID, title::Title};
 use tokio::sync::Mutex;
 use tracing::{event, Level, Instrument, span};
 
-use super::{types::OutputFormat, queryexecutor::{QueryExecutor, QueryExecutorError}};
-use crate::API_SERVICE;
+use super::{collation::Collator, errorlog::ErrorLog, types::{FILE_TARGET_PREFIX, OutputFormat, SummaryTemplate, OutputGroup, OutputKind, OutputPagination, OutputSort}, queryexecutor::{QueryExecutor, QueryExecutorError}};
+use crate::apiservice::{APIService, APIServiceError};
+use crate::solver::fetch_page_info;
+
+const SECTION_START: &str = "<!-- BOT LIST START -->";
+const SECTION_END: &str = "<!-- BOT LIST END -->";
+const SUBPAGES_START: &str = "<!-- BOT SUBPAGES: ";
+const SUBPAGES_END: &str = " -->";
+
+/// Where the bot-maintained section is on a page.
+enum MarkedSection {
+    /// Both markers are there, and this is the text between them.
+    Found(std::ops::Range<usize>),
+    /// Neither marker is there.
+    Missing,
+    /// Only one of the markers is there, or they are in the wrong order.
+    Broken,
+}
+
+impl MarkedSection {
+    fn find(page: &str) -> Self {
+        match (page.find(SECTION_START), page.find(SECTION_END)) {
+            (None, None) => MarkedSection::Missing,
+            (Some(start), Some(end)) if start + SECTION_START.len() <= end => MarkedSection::Found(start + SECTION_START.len()..end),
+            _ => MarkedSection::Broken,
+        }
+    }
+}
+
+/// Compare two strings, treating each run of ASCII digits as a number, so that `List 2` comes before `List 10`.
+/// Numbers equal in value, such as `7` and `007`, are told apart by plain comparison at the end.
+fn natural_cmp(a: &str, b: &str) -> std::cmp::Ordering {
+    let (mut iter_a, mut iter_b) = (a.chars().peekable(), b.chars().peekable());
+    loop {
+        match (iter_a.peek().copied(), iter_b.peek().copied()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return std::cmp::Ordering::Less,
+            (Some(_), None) => return std::cmp::Ordering::Greater,
+            (Some(ca), Some(cb)) if ca.is_ascii_digit() && cb.is_ascii_digit() => {
+                let take_number = |iter: &mut std::iter::Peekable<std::str::Chars>| {
+                    let mut digits = String::new();
+                    while let Some(c) = iter.next_if(|c| c.is_ascii_digit()) {
+                        digits.push(c);
+                    }
+                    digits.trim_start_matches('0').to_owned()
+                };
+                let (num_a, num_b) = (take_number(&mut iter_a), take_number(&mut iter_b));
+                // without leading zeros, a longer run is a larger number
+                let by_value = num_a.len().cmp(&num_b.len()).then_with(|| num_a.cmp(&num_b));
+                if by_value.is_ne() {
+                    return by_value;
+                }
+            },
+            (Some(ca), Some(cb)) => {
+                if ca != cb {
+                    return ca.cmp(&cb);
+                }
+                iter_a.next();
+                iter_b.next();
+            },
+        }
+    }
+}
+
+/// The text the pipe trick `[[Title|]]` shows for `name`, a title without its namespace.
+/// A parenthetical at the end is removed, e.g. `Mercury (planet)` gives `Mercury`, otherwise anything from the first comma, e.g. `Paris, Texas` gives `Paris`.
+fn pipe_trick(name: &str) -> &str {
+    for (open, close) in [('(', ')'), ('（', '）')] {
+        if let (true, Some(idx)) = (name.ends_with(close), name.find(open)) {
+            if idx > 0 {
+                return name[..idx].trim_end();
+            }
+        }
+    }
+    match name.find(", ") {
+        Some(idx) if idx > 0 => &name[..idx],
+        _ => name,
+    }
+}
+
+/// The title with its namespace prefix, or its language prefix for interwiki titles.
+pub(crate) async fn full_title(api: &APIService, t: &Title) -> String {
+    if t.namespace_id() == crate::solver::NS_INTERWIKI {
+        t.pretty().to_owned()
+    } else {
+        api.full_pretty(t).await.ok().flatten().unwrap_or_default()
+    }
+}
+
+/// The result as a JSON object `{"total": ..., "pages": [{"title": ..., "ns": ...}, ...]}`.
+/// The pages are left out if only the number is asked for.
+pub(crate) async fn make_json_value(api: &APIService, ls: &[Title], count_only: bool) -> serde_json::Value {
+    let mut obj = serde_json::json!({ "total": ls.len() });
+    if !count_only {
+        let pages: Vec<serde_json::Value> = join_all(ls.iter().map(|t| async move {
+            serde_json::json!({ "title": full_title(api, t).await, "ns": t.namespace_id() })
+        })).await;
+        obj["pages"] = serde_json::Value::Array(pages);
+    }
+    obj
+}
+
+/// Builds the content to write from the current content of the target, `None` if there is none.
+/// An error leaves the target as it is.
+type PageComposer<'a> = dyn Fn(Option<&str>) -> Result<String, ()> + Sync + 'a;
+
+/// The latest revision of a page, as fetched before editing it.
+struct PageRevision {
+    content: String,
+    /// When the revision was made, to detect edits made in between as edit conflicts.
+    timestamp: Option<String>,
+    /// When the revision was fetched, to detect deletions made in between as edit conflicts.
+    fetched: Option<String>,
+}
 
 pub(crate) struct PageWriter<'a> {
+    api: &'static APIService,
     task_id: i64,
     query_executor: Mutex<QueryExecutor>,
     eager_mode: bool,
+    dry_run: bool,
+    bot_flag: bool,
+    minor: bool,
     denied_namespace: Option<&'a HashSet<NamespaceID>>,
     outputformat: &'a [OutputFormat],
     header_template_name: &'a str,
+    summary_template: Option<&'a SummaryTemplate>,
+    error_log: Option<&'a ErrorLog>,
+    output_dir: Option<&'a str>,
+    namespace_limit: Option<usize>,
+    // the query result only holds titles, so page ids are looked up when an output needs them, and kept for the other outputs
+    page_ids: Mutex<HashMap<Title, u64>>,
 }
 
 impl<'a> PageWriter<'a> {
 
-    pub fn new(query_exec: QueryExecutor) -> Self {
+    pub fn new(api: &'static APIService, query_exec: QueryExecutor) -> Self {
         PageWriter {
+            api,
             task_id: 0,
             query_executor: Mutex::new(query_exec),
             eager_mode: false,
+            dry_run: false,
+            bot_flag: false,
+            minor: false,
             denied_namespace: None,
             outputformat: &[],
             header_template_name: "",
+            summary_template: None,
+            error_log: None,
+            output_dir: None,
+            namespace_limit: None,
+            page_ids: Mutex::new(HashMap::new()),
         }
     }
 
@@ -56,7 +187,67 @@ impl<'a> PageWriter<'a> {
         self
     }
 
+    /// In dry run mode, edits are logged with a diff against the current content instead of being made.
+    pub fn set_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Mark edits as bot edits. The wiki ignores this if the account lacks the bot right.
+    pub fn set_bot_flag(mut self, bot: bool) -> Self {
+        self.bot_flag = bot;
+        self
+    }
+
+    /// Mark edits as minor edits.
+    pub fn set_minor(mut self, minor: bool) -> Self {
+        self.minor = minor;
+        self
+    }
+
+    /// Use a custom edit summary instead of the built-in English one.
+    pub fn set_summary_template(mut self, template: &'a SummaryTemplate) -> Self {
+        self.summary_template = Some(template);
+        self
+    }
+
+    /// Set the directory `file://` targets are written in. `None` disables file targets.
+    pub fn set_output_dir(mut self, dir: Option<&'a str>) -> Self {
+        self.output_dir = dir;
+        self
+    }
+
+    /// Set the maximum number of results written from each namespace, the first ones in the order of each output. `None` means no limit.
+    pub fn set_namespace_limit(mut self, limit: Option<usize>) -> Self {
+        self.namespace_limit = limit;
+        self
+    }
+
+    /// Report how the query went to `log`.
+    pub fn set_error_log(mut self, log: &'a ErrorLog) -> Self {
+        self.error_log = Some(log);
+        self
+    }
+
+    fn make_status_text(&self, result: &Result<Vec<Title>, QueryExecutorError>) -> &'static str {
+        match result {
+            Ok(_) => "success",
+            Err(e) => match e {
+                QueryExecutorError::Timeout => "timeout",
+                QueryExecutorError::Parse => "parse",
+                QueryExecutorError::Solve => "runtime",
+                QueryExecutorError::Cancelled => "cancelled",
+            }
+        }
+    }
+
     fn make_edit_summary(&self, result: &Result<Vec<Title>, QueryExecutorError>) -> String {
+        if let Some(template) = self.summary_template {
+            let status_key = self.make_status_text(result);
+            let status = template.status.get(status_key).map(|s| s.as_str()).unwrap_or(status_key);
+            let total_num = result.as_ref().map(|v| v.len()).unwrap_or(0);
+            return self.substitute_summary_template(&template.template, total_num, status);
+        }
         if let Ok(v) = result {
             match v.len() {
                 0 => String::from("Update query: empty"),
@@ -68,27 +259,50 @@ impl<'a> PageWriter<'a> {
         }
     }
 
-    fn make_header_content(&self, result: &Result<Vec<Title>, QueryExecutorError>) -> String {
-        let status_text = match result {
-            Ok(_) => "success",
-            Err(e) => match e {
-                QueryExecutorError::Timeout => "timeout",
-                QueryExecutorError::Parse => "parse",
-                QueryExecutorError::Solve => "runtime",
-            }
-        };
-        format!("<noinclude>{{{{subst:{header}|taskid={id}|status={status}}}}}</noinclude>", header=self.header_template_name, id=self.task_id, status=status_text)
+    fn make_header_content(&self, result: &Result<Vec<Title>, QueryExecutorError>, description: &str) -> String {
+        let status_text = self.make_status_text(result);
+        // braces and pipes in titles or patterns would end the template parameter early
+        let desc = description.replace('{', "&#123;").replace('}', "&#125;").replace('|', "&#124;");
+        format!("<noinclude>{{{{subst:{header}|taskid={id}|status={status}|desc={desc}}}}}</noinclude>", header=self.header_template_name, id=self.task_id, status=status_text, desc=desc)
     }
 
-    fn substitute_str_template(&self, template: &str, total_num: usize) -> String {
+    fn substitute_summary_template(&self, template: &str, total_num: usize, status: &str) -> String {
         let mut output: String = String::new();
         let mut escape: bool = false;
         for char in template.chars() {
             if escape {
-                // only accept $+ (total size), $$ ($)
+                // only accept $+ (total size), $? (status word), $$ ($)
+                match char {
+                    '$' => { output.push('$'); },
+                    '+' => { output.push_str(&total_num.to_string()) },
+                    '?' => { output.push_str(status) },
+                    _ => { output.push('$'); output.push(char); },
+                }
+                escape = false;
+            } else if char == '$' {
+                escape = true;
+            } else {
+                output.push(char);
+            }
+        }
+        output
+    }
+
+    fn substitute_str_template(&self, template: &str, total_num: usize, description: &str) -> String {
+        let mut output: String = String::new();
+        let mut escape: bool = false;
+        let mut skip: usize = 0;
+        for (idx, char) in template.char_indices() {
+            if skip > 0 {
+                skip -= 1;
+                continue;
+            }
+            if escape {
+                // only accept $+ (total size), $desc (query description), $$ ($)
                 match char {
                     '$' => { output.push('$'); },
                     '+' => { output.push_str(&total_num.to_string()) },
+                    'd' if template[idx..].starts_with("desc") => { output.push_str(description); skip = "esc".len(); },
                     _ => { output.push('$'); output.push(char); },
                 }
                 escape = false;
@@ -106,12 +320,20 @@ impl<'a> PageWriter<'a> {
         let mut escape: bool = false;
         for char in template.chars() {
             if escape {
-                // only accept $0 (full name), $1 (namespace), $2 (name), $@ (current index), $+ (total size), $$ ($)
+                // only accept $0 (full name), $1 (namespace), $2 (name), $3 (page id), $4 (display text), $5 (link), $@ (current index), $+ (total size), $$ ($)
                 match char {
                     '$' => { output.push('$'); },
-                    '0' => { output.push_str(&API_SERVICE.full_pretty(t).await.unwrap_or_else(|_| Some("".to_string())).unwrap_or_else(|| "".to_string())); },
-                    '1' => { output.push_str(&API_SERVICE.namespace_name(t).await.unwrap_or_else(|_| Some("".to_string())).unwrap_or_else(|| "".to_string())); },
+                    // what the pipe trick would show, the language prefix of a page on another wiki is removed too
+                    '4' => { output.push_str(pipe_trick(Self::local_name(t))); },
+                    // the leading colon keeps files and categories from being embedded, and the page from being categorized
+                    '5' => { output.push_str(&format!("[[:{}|{}]]", full_title(self.api, t).await, pipe_trick(Self::local_name(t)))); },
+                    // a page on another wiki gets a leading colon, so that `[[$0]]` gives a link rather than an interlanguage link
+                    '0' if t.namespace_id() == crate::solver::NS_INTERWIKI => { output.push(':'); output.push_str(t.pretty()); },
+                    '0' => { output.push_str(&self.api.full_pretty(t).await.unwrap_or_else(|_| Some("".to_string())).unwrap_or_else(|| "".to_string())); },
+                    '1' => { output.push_str(&self.api.namespace_name(t).await.unwrap_or_else(|_| Some("".to_string())).unwrap_or_else(|| "".to_string())); },
                     '2' => { output.push_str(t.pretty()); },
+                    // empty for pages without an id, such as missing pages
+                    '3' => { if let Some(id) = self.page_ids.lock().await.get(t) { output.push_str(&id.to_string()); } },
                     '@' => { output.push_str(&current_num.to_string()) },
                     '+' => { output.push_str(&total_num.to_string()) },
                     _ => { output.push('$'); output.push(char); },
@@ -126,6 +348,550 @@ impl<'a> PageWriter<'a> {
         output
     }
 
+    /// The title without its namespace, or without its language prefix for interwiki titles.
+    fn local_name(t: &Title) -> &str {
+        if t.namespace_id() == crate::solver::NS_INTERWIKI {
+            t.pretty().split_once(':').map(|(_, name)| name).unwrap_or(t.pretty())
+        } else {
+            t.pretty()
+        }
+    }
+
+    /// Serialize the result as JSON, wrapped in `<syntaxhighlight>` so that it is shown as is on the page.
+    /// A count-only query has no `pages` list.
+    async fn make_json_body(&self, ls: &[Title], count_only: bool) -> String {
+        format!("<syntaxhighlight lang=\"json\">\n{}\n</syntaxhighlight>", make_json_value(self.api, ls, count_only).await)
+    }
+
+    async fn substitute_group_header(&self, template: &str, ns: NamespaceID, group_size: usize) -> String {
+        let mut output: String = String::new();
+        let mut escape: bool = false;
+        for char in template.chars() {
+            if escape {
+                // only accept $1 (namespace name), $# (namespace id), $+ (group size), $$ ($)
+                match char {
+                    '$' => { output.push('$'); },
+                    '1' => { output.push_str(&self.api.namespace_name(&Title::new("", ns)).await.unwrap_or_else(|_| Some("".to_string())).unwrap_or_else(|| "".to_string())); },
+                    '#' => { output.push_str(&ns.to_string()) },
+                    '+' => { output.push_str(&group_size.to_string()) },
+                    _ => { output.push('$'); output.push(char); },
+                }
+                escape = false;
+            } else if char == '$' {
+                escape = true;
+            } else {
+                output.push(char);
+            }
+        }
+        output
+    }
+
+    /// Write the items joined by `between`, laid out in columns if the output format asks for it.
+    /// Each item comes with its index in the list, counting from 1.
+    async fn make_items(&self, outputformat: &OutputFormat, items: &[(usize, &Title)], list_size: usize, between: &str, description: &str) -> String {
+        let rendered: Vec<String> = join_all(items.iter().map(|(idx, t)| {
+            self.substitute_str_template_with_title(&outputformat.success.item, t, *idx, list_size)
+        })).await;
+        let layout = match &outputformat.multicolumn {
+            Some(layout) => layout,
+            None => return rendered.join(between),
+        };
+        let before = self.substitute_str_template(&layout.before, list_size, description);
+        let after = self.substitute_str_template(&layout.after, list_size, description);
+        let count = match layout.count {
+            Some(count) => count.max(1),
+            None => return format!("{}{}{}", before, rendered.join(between), after),
+        };
+        // the first columns take one more item each if the items cannot be split evenly
+        let (size, extra) = (rendered.len() / count, rendered.len() % count);
+        let mut columns: Vec<String> = Vec::new();
+        let mut start: usize = 0;
+        for col in 0..count {
+            let end = start + size + usize::from(col < extra);
+            if end > start {
+                columns.push(format!("{}{}{}", before, rendered[start..end].join(between), after));
+            }
+            start = end;
+        }
+        columns.join(&self.substitute_str_template(&layout.between, list_size, description))
+    }
+
+    /// Write the items grouped by namespace, keeping the order within each group.
+    /// `ls` is a part of the whole list, starting from index `first`, and `list_size` is the size of the whole list.
+    async fn make_grouped_items(&self, outputformat: &OutputFormat, group: &OutputGroup, ls: &[Title], first: usize, list_size: usize, description: &str) -> String {
+        let mut groups: BTreeMap<NamespaceID, Vec<(usize, &Title)>> = BTreeMap::new();
+        for (idx, t) in ls.iter().enumerate() {
+            groups.entry(t.namespace_id()).or_default().push((first + idx, t));
+        }
+        let between = self.substitute_str_template(&outputformat.success.between, list_size, description);
+        let mut output: String = String::new();
+        for (ns, items) in groups {
+            output.push_str(&self.substitute_group_header(&group.header, ns, items.len()).await);
+            let items: Vec<(usize, &Title)> = items.iter().enumerate()
+                .map(|(group_idx, (idx, t))| (if group.restartindex { group_idx } else { *idx } + 1, *t))
+                .collect();
+            output.push_str(&self.make_items(outputformat, &items, list_size, &between, description).await);
+        }
+        output
+    }
+
+    /// Write a part of the list with the `success` templates.
+    /// `ls` is a part of the whole list, starting from index `first`, and `list_size` is the size of the whole list.
+    /// `omitted` items past the end of `ls` are left out of the output, and mentioned with `more`.
+    async fn make_list_body(&self, outputformat: &OutputFormat, ls: &[Title], first: usize, list_size: usize, omitted: usize, description: &str) -> String {
+        let mut output: String = String::new();
+        output.push_str(&self.substitute_str_template(&outputformat.success.before, list_size, description));
+        if let Some(group) = &outputformat.group {
+            output.push_str(&self.make_grouped_items(outputformat, group, ls, first, list_size, description).await);
+        } else {
+            let items: Vec<(usize, &Title)> = ls.iter().enumerate().map(|(idx, t)| (first + idx + 1, t)).collect();
+            let between = self.substitute_str_template(&outputformat.success.between, list_size, description);
+            output.push_str(&self.make_items(outputformat, &items, list_size, &between, description).await);
+        }
+        if omitted > 0 {
+            output.push_str(&self.substitute_more_template(&outputformat.success.more, list_size, omitted));
+        }
+        output.push_str(&self.substitute_str_template(&outputformat.success.after, list_size, description));
+        output
+    }
+
+    /// Split the list into pages, so that each page stays within the limits.
+    /// Returns the index range of each page. Every page holds at least one item, even if that alone exceeds the limits.
+    async fn paginate(&self, outputformat: &OutputFormat, pagination: &OutputPagination, ls: &[Title]) -> Vec<std::ops::Range<usize>> {
+        let list_size = ls.len();
+        let between_size = self.substitute_str_template(&outputformat.success.between, list_size, "").len();
+        let mut pages: Vec<std::ops::Range<usize>> = Vec::new();
+        let mut start: usize = 0;
+        let mut bytes: usize = 0;
+        for (idx, t) in ls.iter().enumerate() {
+            let item_size = self.substitute_str_template_with_title(&outputformat.success.item, t, idx + 1, list_size).await.len() + between_size;
+            let too_many = pagination.maxitems.map(|m| idx - start >= m).unwrap_or(false);
+            let too_large = pagination.maxbytes.map(|m| bytes + item_size > m).unwrap_or(false);
+            if idx > start && (too_many || too_large) {
+                pages.push(start..idx);
+                start = idx;
+                bytes = 0;
+            }
+            bytes += item_size;
+        }
+        pages.push(start..list_size);
+        pages
+    }
+
+    fn substitute_more_template(&self, template: &str, total_num: usize, omitted_num: usize) -> String {
+        let mut output: String = String::new();
+        let mut escape: bool = false;
+        for char in template.chars() {
+            if escape {
+                // only accept $+ (total size), $- (number left out), $$ ($)
+                match char {
+                    '$' => { output.push('$'); },
+                    '+' => { output.push_str(&total_num.to_string()) },
+                    '-' => { output.push_str(&omitted_num.to_string()) },
+                    _ => { output.push('$'); output.push(char); },
+                }
+                escape = false;
+            } else if char == '$' {
+                escape = true;
+            } else {
+                output.push(char);
+            }
+        }
+        output
+    }
+
+    fn substitute_index_template(&self, template: &str, subpage: &str, current_num: usize, total_num: usize) -> String {
+        let mut output: String = String::new();
+        let mut escape: bool = false;
+        for char in template.chars() {
+            if escape {
+                // only accept $0 (subpage name), $@ (current subpage), $+ (number of subpages), $$ ($)
+                match char {
+                    '$' => { output.push('$'); },
+                    '0' => { output.push_str(subpage); },
+                    '@' => { output.push_str(&current_num.to_string()) },
+                    '+' => { output.push_str(&total_num.to_string()) },
+                    _ => { output.push('$'); output.push(char); },
+                }
+                escape = false;
+            } else if char == '$' {
+                escape = true;
+            } else {
+                output.push(char);
+            }
+        }
+        output
+    }
+
+    /// The number of subpages the target page says it has, as recorded by `subpage_marker`.
+    fn recorded_subpages(page: &str) -> usize {
+        page.find(SUBPAGES_START)
+            .and_then(|idx| page[idx + SUBPAGES_START.len()..].split_once(SUBPAGES_END))
+            .and_then(|(count, _)| count.trim().parse().ok())
+            .unwrap_or(0)
+    }
+
+    /// A hidden note of the number of subpages holding a part of the list, put on the target page so that the next run knows which ones to clean up.
+    fn subpage_marker(count: usize) -> String {
+        if count == 0 {
+            String::new()
+        } else {
+            format!("\n{}{}{}", SUBPAGES_START, count, SUBPAGES_END)
+        }
+    }
+
+    /// Turn the subpages after the first `keep` ones, up to the number recorded on the target page, into redirects to the target page.
+    /// Returns the number of subpages that may still hold a part of an earlier list, which is `keep` unless some of them could not be changed.
+    async fn clear_stale_subpages(&self, target: &str, keep: usize, summary: &str) -> usize {
+        let recorded = self.fetch_revision(target).await.ok().flatten().map(|r| Self::recorded_subpages(&r.content)).unwrap_or(0);
+        if recorded <= keep {
+            return keep;
+        }
+        let mut titles: Vec<(usize, String, Title)> = Vec::new();
+        for num in keep + 1..=recorded {
+            let subpage = format!("{}/{}", target, num);
+            match self.api.title_new_from_full(&subpage).await {
+                Ok(title) => titles.push((num, subpage, title)),
+                Err(e) => {
+                    event!(Level::WARN, error = ?e, "cannot fetch subpage information");
+                    return recorded;
+                },
+            }
+        }
+        let info = match fetch_page_info(self.api, &titles.iter().map(|(_, _, t)| t.clone()).collect::<Vec<Title>>(), &[]).await {
+            Ok(info) => info,
+            Err(e) => {
+                event!(Level::WARN, error = ?e, "cannot fetch subpage information");
+                return recorded;
+            },
+        };
+        let mut left = keep;
+        for (num, subpage, title) in titles {
+            // deleted or already redirected, nothing to clean up
+            if info.get(&title).map(|i| i.redirect).unwrap_or(true) {
+                continue;
+            }
+            let content = format!("#REDIRECT [[{}]]", target);
+            if !self.edit_page(&subpage, &|_| Ok(content.clone()), summary, false).instrument(span!(Level::INFO, "stale subpage", page = subpage.as_str())).await {
+                left = num;
+            }
+        }
+        left
+    }
+
+    /// Write the list across subpages if it is too long, and return the body of the target page itself.
+    /// If the list fits in one page, that is the list, otherwise an index of the subpages.
+    /// Only the first `maxitems` items of the output format are written, as without pagination.
+    /// Subpages beyond the ones written this time are turned into redirects to the target page.
+    async fn write_paginated(&self, outputformat: &OutputFormat, pagination: &OutputPagination, ls: &[Title], header: &str, summary: &str, description: &str) -> String {
+        let list_size = ls.len();
+        let shown = outputformat.maxitems.unwrap_or(list_size).min(list_size);
+        let omitted = list_size - shown;
+        let pages = self.paginate(outputformat, pagination, &ls[..shown]).await;
+        let page_count = if pages.len() > 1 { pages.len() } else { 0 };
+        let mut index: Vec<String> = Vec::new();
+        for (num, range) in pages.iter().enumerate().take(page_count) {
+            let subpage = format!("{}/{}", outputformat.target, num + 1);
+            // the items left out are mentioned at the end of the last subpage
+            let more = if range.end == shown { omitted } else { 0 };
+            let mut content = header.to_owned();
+            content.push_str(&self.make_list_body(outputformat, &ls[range.clone()], range.start, list_size, more, description).await);
+            self.edit_page(&subpage, &|_| Ok(content.clone()), summary, true).instrument(span!(Level::INFO, "subpage", page = subpage.as_str())).await;
+            index.push(self.substitute_index_template(&pagination.index, &subpage, num + 1, page_count));
+        }
+        let left = self.clear_stale_subpages(&outputformat.target, page_count, summary).await;
+        let mut body = if page_count == 0 {
+            self.make_list_body(outputformat, &ls[..shown], 0, list_size, omitted, description).await
+        } else {
+            index.join("\n")
+        };
+        body.push_str(&Self::subpage_marker(left));
+        body
+    }
+
+    /// Fetch the latest revision of a page, or `None` if the page does not exist.
+    async fn fetch_revision(&self, title: &str) -> Result<Option<PageRevision>, ()> {
+        let params = hashmap![
+            "action".to_string() => "query".to_string(),
+            "prop".to_string() => "revisions".to_string(),
+            "titles".to_string() => title.to_owned(),
+            "rvslots".to_string() => "*".to_string(),
+            "rvprop".to_string() => "content|timestamp".to_string(),
+            "rvlimit".to_string() => "1".to_string(),
+            "curtimestamp".to_string() => "1".to_string()
+        ];
+        let page_content = {
+            self.api.get_lock().lock().await;
+            self.api.get(&params).await
+        };
+        if let Ok(page_content) = page_content {
+            if page_content["query"]["pages"][0].get("missing").is_some() {
+                event!(Level::DEBUG, "page does not exist, no content");
+                return Ok(None);
+            }
+            let page_content_str = page_content["query"]["pages"][0]["revisions"][0]["slots"]["main"]["content"].as_str();
+            if let Some(page_content_str) = page_content_str {
+                Ok(Some(PageRevision {
+                    content: page_content_str.to_owned(),
+                    timestamp: page_content["query"]["pages"][0]["revisions"][0]["timestamp"].as_str().map(|s| s.to_owned()),
+                    fetched: page_content["curtimestamp"].as_str().map(|s| s.to_owned()),
+                }))
+            } else {
+                event!(Level::WARN, response = ?page_content, "cannot find page content in response");
+                Err(())
+            }
+        } else {
+            event!(Level::WARN, error = ?page_content.unwrap_err(), "cannot fetch original target page content");
+            Err(())
+        }
+    }
+
+    /// The local file a `file://` target stands for, or `None` if the target is a wiki page.
+    /// The path is relative to the output directory, and may not leave it, so that a task page cannot overwrite other files on the host.
+    /// Without an output directory, no file can be written.
+    fn target_file(&self, target: &str) -> Option<Result<PathBuf, ()>> {
+        let path = Path::new(target.strip_prefix(FILE_TARGET_PREFIX)?);
+        let inside = path.components().next().is_some() && path.components().all(|c| matches!(c, Component::Normal(_)));
+        match self.output_dir {
+            Some(dir) if inside => Some(Ok(Path::new(dir).join(path))),
+            _ => Some(Err(())),
+        }
+    }
+
+    /// Write the content `compose` builds from the current content of a local file, creating its directory if needed.
+    /// If the file already has this content, nothing is written.
+    async fn write_file(&self, path: &Path, compose: &PageComposer<'_>) {
+        let orig_content = fs::read_to_string(path).map_err(|e| event!(Level::DEBUG, error = %e, "cannot read target file")).ok();
+        let content = match compose(orig_content.as_deref()) {
+            Ok(content) => content,
+            Err(()) => {
+                event!(Level::WARN, "page edit cancelled");
+                return;
+            },
+        };
+        event!(Level::DEBUG, "content ready");
+        let orig_content = orig_content.unwrap_or_default();
+        if orig_content == content {
+            event!(Level::INFO, "no change, skipping");
+            return;
+        }
+        if self.dry_run {
+            event!(Level::INFO, diff = %self.make_diff(&orig_content, &content), "dry run, not writing");
+            return;
+        }
+        let written = match path.parent() {
+            Some(parent) => fs::create_dir_all(parent).and_then(|_| fs::write(path, &content)),
+            None => fs::write(path, &content),
+        };
+        match written {
+            Ok(()) => event!(Level::INFO, "write file successful"),
+            Err(e) => event!(Level::WARN, error = %e, "cannot write file"),
+        }
+    }
+
+    /// Write the content `compose` builds from the latest revision of `title`. Only subpages written by pagination may be created, other pages must exist.
+    /// If the page already has this content, nothing is written.
+    /// The edit is made against the revision the content was built from. If someone else edits the page in the meantime,
+    /// the content is built once more from their revision and tried again.
+    /// Returns whether the page has the content now, or would have in dry run mode.
+    async fn edit_page(&self, title: &str, compose: &PageComposer<'_>, summary: &str, create: bool) -> bool {
+        for attempt in 0..2 {
+            let revision = match self.fetch_revision(title).await {
+                Ok(revision) => revision,
+                Err(()) => return false,
+            };
+            let content = match compose(revision.as_ref().map(|r| r.content.as_str())) {
+                Ok(content) => content,
+                Err(()) => {
+                    event!(Level::WARN, "page edit cancelled");
+                    return false;
+                },
+            };
+            event!(Level::DEBUG, "content ready");
+            // a page to be created has no content to compare with
+            // trailing whitespace is trimmed when a page is saved, so it makes no difference
+            let orig_content = revision.as_ref().map(|r| r.content.as_str()).unwrap_or("");
+            if !orig_content.is_empty() && orig_content.trim_end() == content.trim_end() {
+                event!(Level::INFO, "no change, skipping");
+                return true;
+            }
+            if self.dry_run {
+                event!(Level::INFO, summary, diff = %self.make_diff(orig_content, &content), "dry run, not editing");
+                return true;
+            }
+            let mut params = hashmap![
+                "action".to_string() => "edit".to_string(),
+                "title".to_string() => title.to_owned(),
+                "md5".to_string() => self.get_md5(&content),
+                "text".to_string() => content,
+                "summary".to_string() => summary.to_owned(),
+                "token".to_string() => self.api.csrf().await
+            ];
+            if !create {
+                params.insert("nocreate".to_string(), "1".to_string());
+            }
+            if self.bot_flag {
+                params.insert("bot".to_string(), "1".to_string());
+            }
+            if self.minor {
+                params.insert("minor".to_string(), "1".to_string());
+            }
+            if let Some(revision) = &revision {
+                if let Some(timestamp) = &revision.timestamp {
+                    params.insert("basetimestamp".to_string(), timestamp.to_owned());
+                }
+                if let Some(fetched) = &revision.fetched {
+                    params.insert("starttimestamp".to_string(), fetched.to_owned());
+                }
+            }
+            let edit_result = {
+                self.api.get_lock().lock().await;
+                self.api.post_edit(&params).await
+            };
+            match edit_result {
+                Err(APIServiceError::Server(e)) if attempt == 0 && e["code"].as_str() == Some("editconflict") => {
+                    event!(Level::WARN, "edit conflict, rebuild against the new revision");
+                },
+                Err(e) => {
+                    event!(Level::WARN, error = ?e, "cannot edit page");
+                    return false;
+                },
+                Ok(res) if res["edit"]["result"].as_str() == Some("Success") => {
+                    event!(Level::INFO, "edit page successful");
+                    return true;
+                },
+                // e.g. skipped in read-only mode, or stopped by an extension such as a captcha
+                Ok(res) => {
+                    event!(Level::INFO, result = ?res["edit"], "page not edited");
+                    return false;
+                },
+            }
+        }
+        false
+    }
+
+    /// Write the result as a sortable wikitable, one cell per column in each row.
+    async fn make_table_body(&self, outputformat: &OutputFormat, ls: &[Title], description: &str) -> String {
+        let list_size = ls.len();
+        let mut output: String = String::new();
+        output.push_str("{| class=\"wikitable sortable\"\n");
+        for column in outputformat.columns.iter() {
+            output.push_str(&format!("! {}\n", self.substitute_str_template(&column.header, list_size, description)));
+        }
+        for (idx, t) in ls.iter().enumerate() {
+            output.push_str("|-\n");
+            for column in outputformat.columns.iter() {
+                output.push_str(&format!("| {}\n", self.substitute_str_template_with_title(&column.cell, t, idx + 1, list_size).await));
+            }
+        }
+        output.push_str("|}");
+        output
+    }
+
+    /// Look up the page ids of the titles not looked up yet.
+    /// Pages without an id, such as missing pages, are left out.
+    async fn load_page_ids(&self, ls: &[Title]) {
+        let todo: Vec<Title> = {
+            let ids = self.page_ids.lock().await;
+            ls.iter().filter(|t| !ids.contains_key(t)).cloned().collect()
+        };
+        match fetch_page_info(self.api, &todo, &[]).await {
+            Ok(info) => {
+                let mut ids = self.page_ids.lock().await;
+                ids.extend(info.into_iter().map(|(t, i)| (t, i.page_id)));
+            },
+            Err(e) => event!(Level::WARN, error = ?e, "cannot fetch page ids"),
+        }
+    }
+
+    /// Whether any template of the output uses the page id.
+    fn uses_page_id(outputformat: &OutputFormat) -> bool {
+        outputformat.success.item.contains("$3") || outputformat.columns.iter().any(|c| c.cell.contains("$3"))
+    }
+
+    /// Sort the titles as requested by the output format.
+    /// The titles are already sorted by namespace, so `OutputSort::Namespace` keeps them as they are.
+    /// Sort `ls` the way `outputformat` asks, then keep only the first titles of each namespace if there is a namespace limit.
+    async fn arrange_titles(&self, ls: &[Title], outputformat: &OutputFormat, count_only: bool) -> Vec<Title> {
+        // the order makes no difference if only the number is written
+        let sort = if count_only { OutputSort::Namespace } else { outputformat.sort };
+        let mut ls = self.sort_titles(ls, sort).await;
+        if outputformat.reverse && !count_only {
+            ls.reverse();
+        }
+        if let Some(namespace_limit) = self.namespace_limit {
+            let mut ns_count: HashMap<NamespaceID, usize> = HashMap::new();
+            ls.retain(|t| {
+                let count = ns_count.entry(t.namespace_id()).or_insert(0);
+                *count += 1;
+                *count <= namespace_limit
+            });
+        }
+        ls
+    }
+
+    async fn sort_titles(&self, ls: &[Title], sort: OutputSort) -> Vec<Title> {
+        let mut sorted = ls.to_vec();
+        if sort == OutputSort::Namespace {
+            return sorted;
+        }
+        let mut names: HashMap<Title, String> = HashMap::new();
+        for t in ls {
+            names.insert(t.clone(), full_title(self.api, t).await);
+        }
+        if sort == OutputSort::PageId {
+            self.load_page_ids(ls).await;
+        }
+        let lang = if sort == OutputSort::Collation {
+            self.api.content_language().await.ok().flatten()
+        } else {
+            None
+        };
+        let ids = self.page_ids.lock().await;
+        // the collator is built after the last await, as it cannot be sent between threads
+        let collator = if sort == OutputSort::Collation {
+            let lang = lang.unwrap_or_default();
+            let collator = Collator::new(&lang);
+            if collator.is_none() {
+                event!(Level::WARN, lang = lang.as_str(), "cannot build the collation for the content language, sorting by title");
+            }
+            collator
+        } else {
+            None
+        };
+        sorted.sort_by(|a, b| {
+            let (name_a, name_b) = (&names[a], &names[b]);
+            match (sort, &collator) {
+                (OutputSort::PageId, _) => {
+                    let by_id = match (ids.get(a), ids.get(b)) {
+                        (Some(x), Some(y)) => x.cmp(y),
+                        (Some(_), None) => std::cmp::Ordering::Less,
+                        (None, Some(_)) => std::cmp::Ordering::Greater,
+                        (None, None) => std::cmp::Ordering::Equal,
+                    };
+                    by_id.then_with(|| name_a.cmp(name_b))
+                },
+                (OutputSort::Natural, _) => natural_cmp(name_a, name_b),
+                (OutputSort::Collation, Some(collator)) => collator.compare(name_a, name_b),
+                _ => name_a.cmp(name_b),
+            }
+        });
+        sorted
+    }
+
+    /// A diff of the changed lines, each prefixed by `-` or `+`.
+    fn make_diff(&self, old: &str, new: &str) -> String {
+        let mut output: String = String::new();
+        for line in diff::lines(old.trim_end(), new.trim_end()) {
+            match line {
+                diff::Result::Left(l) => { output.push('-'); output.push_str(l); output.push('\n'); },
+                diff::Result::Right(r) => { output.push('+'); output.push_str(r); output.push('\n'); },
+                diff::Result::Both(..) => (),
+            }
+        }
+        output
+    }
+
     fn get_md5(&self, text: &str) -> String {
         let mut hasher = Md5::new();
         hasher.update(text);
@@ -133,154 +899,197 @@ impl<'a> PageWriter<'a> {
         hex::encode(result)
     }
 
-    pub async fn write_by_output_format(&self, outputformat: &OutputFormat) {
+    /// Put `content` between the markers on the target page, keeping everything outside them.
+    /// If neither marker is there, they are appended to the page along with the content.
+    /// If only one of them is there, the page is left alone, as there is no telling where the bot-maintained part ends.
+    fn splice_section(page: &str, content: String) -> Result<String, ()> {
+        match MarkedSection::find(page) {
+            MarkedSection::Found(range) => Ok(format!("{}\n{}\n{}", &page[..range.start], content, &page[range.end..])),
+            MarkedSection::Missing => {
+                let mut output = page.trim_end().to_owned();
+                if !output.is_empty() {
+                    output.push_str("\n\n");
+                }
+                output.push_str(&format!("{}\n{}\n{}", SECTION_START, content, SECTION_END));
+                Ok(output)
+            },
+            MarkedSection::Broken => {
+                event!(Level::WARN, "only one of the list markers is on the target page");
+                Err(())
+            },
+        }
+    }
+
+    /// Build the new content of the target from its current content `page`: the header, followed by `body`.
+    /// Without a body, the list already on the target is kept under the new header. If there is none, nothing is written.
+    /// In section mode, only the marked section of the page is replaced.
+    fn compose(header: &str, body: Option<&str>, section: bool, page: Option<&str>) -> Result<String, ()> {
+        let mut content = header.to_owned();
+        if let Some(body) = body {
+            content.push_str(body);
+        } else {
+            // The original content of the target page, or of its marked section
+            let orig_content = if section {
+                page.and_then(|page| match MarkedSection::find(page) {
+                    MarkedSection::Found(range) => Some(&page[range]),
+                    // nothing written by the bot before, so nothing to keep
+                    _ => None,
+                })
+            } else {
+                page
+            };
+            let orig_content = orig_content.ok_or(())?;
+            // The page content, when trimmed from start, should start with <noinclude>
+            // If that is the case, copy everything after the first </noinclude> if it exists
+            // Otherwise, just copy the whole page
+            // Cannot defend against some complicated scenarios such as </noinclude> in comments, in <nowiki> tags, etc
+            // Luckily if the original content is generated by the bot this will not be a problem
+            match orig_content.find("</noinclude>") {
+                Some(offset) if orig_content.trim_start().starts_with("<noinclude>") => content.push_str(&orig_content[offset + "</noinclude>".len()..]),
+                _ => content.push_str(orig_content),
+            }
+        }
+        if section {
+            Self::splice_section(page.unwrap_or_default(), content)
+        } else {
+            Ok(content)
+        }
+    }
+
+    /// Whether the target page can be written: it exists, is not a redirect, and is not in a denied namespace.
+    async fn check_target_page(&self, target: &str) -> bool {
         // Check whether the page is a redirect or missing
-        let params = hashmap![
-            "action".to_string() => "query".to_string(),
-            "prop".to_string() => "info".to_string(),
-            "titles".to_string() => outputformat.target.clone()
-        ];
-        let page_query = {
-            API_SERVICE.get_lock().lock().await;
-            API_SERVICE.get(&params).await
+        let title = match self.api.title_new_from_full(target).await {
+            Ok(title) => title,
+            Err(e) => {
+                event!(Level::WARN, error = ?e, "cannot fetch page information");
+                return false;
+            },
         };
-        if page_query.is_err() {
-            event!(Level::WARN, error = ?page_query.unwrap_err(), "cannot fetch page information");
-        } else {
-            let res = page_query.unwrap();
-            let info = res["query"]["pages"].as_array().unwrap()[0].as_object().unwrap();
-            if info.get("missing").is_some() {
+        let info = match fetch_page_info(self.api, std::slice::from_ref(&title), &[]).await {
+            Ok(mut info) => info.remove(&title),
+            Err(e) => {
+                event!(Level::WARN, error = ?e, "cannot fetch page information");
+                return false;
+            },
+        };
+        let info = match info {
+            Some(info) => info,
+            None => {
                 event!(Level::INFO, "target page does not exist, skip");
-            } else if info.get("redirect").is_some() {
-                event!(Level::INFO, "target page is a redirect page, skip");
+                return false;
+            },
+        };
+        if info.redirect {
+            event!(Level::INFO, "target page is a redirect page, skip");
+            return false;
+        }
+        let deny_ns = {
+            if let Some(denied_namespace) = self.denied_namespace {
+                denied_namespace.clone()
             } else {
-                let deny_ns = {
-                    if let Some(denied_namespace) = self.denied_namespace {
-                        denied_namespace.clone()
-                    } else {
-                        HashSet::<NamespaceID>::new()
+                HashSet::<NamespaceID>::new()
+            }
+        };
+        if deny_ns.contains(&title.namespace_id()) {
+            event!(Level::INFO, "target page is in disallowed namespace, skip");
+            return false;
+        }
+        true
+    }
+
+    /// Render the result with `outputformat`, and write it to its target page, or to a local file for a `file://` target.
+    pub async fn write_by_output_format(&self, outputformat: &OutputFormat) {
+        let file = match self.target_file(&outputformat.target) {
+            Some(Ok(path)) => Some(path),
+            Some(Err(())) => {
+                event!(Level::WARN, "no output directory, or the target file is outside it, skip");
+                return;
+            },
+            None => None,
+        };
+        if file.is_none() && !self.check_target_page(&outputformat.target).await {
+            return;
+        }
+        let mut executor = self.query_executor.lock().await;
+        let description = executor.describe().unwrap_or_default();
+        let count_only = executor.is_count();
+        let result = executor.execute().instrument(span!(Level::INFO, "query executor routine")).await;
+        // the bot is shutting down, leave the target as is
+        if let Err(QueryExecutorError::Cancelled) = result {
+            event!(Level::INFO, "query cancelled, skip");
+            return;
+        }
+        // sort the list for this output first, so that the namespace limit keeps the titles shown first
+        let result = match result {
+            Ok(ls) => Ok(self.arrange_titles(ls, outputformat, count_only).await),
+            Err(e) => Err(*e),
+        };
+        let result = &result;
+        // Prepare contents
+        let summary = self.make_edit_summary(result);
+        let header = self.make_header_content(result, &description);
+        // an error keeps the list already on the target
+        let body: Result<String, ()> = match result {
+            Ok(ls) => {
+                if !count_only && Self::uses_page_id(outputformat) {
+                    self.load_page_ids(ls).await;
+                }
+                if outputformat.kind == OutputKind::Json {
+                    Ok(self.make_json_body(ls, count_only).await)
+                } else if count_only {
+                    // only the number is written, via `$+` in the templates around the list
+                    let mut output: String = String::new();
+                    output.push_str(&self.substitute_str_template(&outputformat.success.before, ls.len(), &description));
+                    output.push_str(&self.substitute_str_template(&outputformat.success.after, ls.len(), &description));
+                    Ok(output)
+                } else if ls.is_empty() {
+                    let mut output = outputformat.empty.clone();
+                    if let (OutputKind::Wikitext, Some(_), None) = (outputformat.kind, &outputformat.pagination, &file) {
+                        let left = self.clear_stale_subpages(&outputformat.target, 0, &summary).await;
+                        output.push_str(&Self::subpage_marker(left));
                     }
-                };
-                if deny_ns.contains(&info["ns"].as_i64().unwrap()) {
-                    event!(Level::INFO, "target page is in disallowed namespace, skip");
+                    Ok(output)
+                } else if let (OutputKind::Wikitext, Some(pagination), None) = (outputformat.kind, &outputformat.pagination, &file) {
+                    Ok(self.write_paginated(outputformat, pagination, ls, &header, &summary, &description).await)
                 } else {
-                    // Not a redirect nor a missing page nor in a denied namespace, continue
-                    let mut executor = self.query_executor.lock().await;
-                    let result = executor.execute().instrument(span!(Level::INFO, "query executor routine")).await;
-                    // Prepare contents
-                    let summary = self.make_edit_summary(result);
-                    let content: Result<String, ()> = {
-                        let mut content = self.make_header_content(result);
-                        let body = match result {
-                            Ok(ls) => {
-                                if ls.is_empty() {
-                                    Ok(outputformat.empty.clone())
-                                } else {
-                                    let list_size = ls.len();
-                                    let mut output: String = String::new();
-                                    output.push_str(&self.substitute_str_template(&outputformat.success.before, list_size));
-                                    let item_str: String = join_all(ls.iter().enumerate().map(|(idx, t)| async move {
-                                        self.substitute_str_template_with_title(&outputformat.success.item, t, idx + 1, list_size).await
-                                    })).await.join(&self.substitute_str_template(&outputformat.success.between, list_size));
-                                    output.push_str(&item_str);
-                                    output.push_str(&self.substitute_str_template(&outputformat.success.after, list_size));
-                                    Ok(output)
-                                }
-                            },
-                            Err(_) => {
-                                if self.eager_mode {
-                                    Ok(outputformat.failure.clone())
-                                } else {
-                                    Err(())
-                                }
-                            },
-                        };
-
-                        if let Ok(body) = body {
-                            content.push_str(&body);
-                            Ok(content)
-                        } else {
-                            // Fetch the original content of the target page
-                            let orig_content = {
-                                let params = hashmap![
-                                    "action".to_string() => "query".to_string(),
-                                    "prop".to_string() => "revisions".to_string(),
-                                    "titles".to_string() => outputformat.target.clone(),
-                                    "rvslots".to_string() => "*".to_string(),
-                                    "rvprop".to_string() => "content".to_string(),
-                                    "rvlimit".to_string() => "1".to_string()
-                                ];
-                                let page_content = {
-                                    API_SERVICE.get_lock().lock().await;
-                                    API_SERVICE.get(&params).await
-                                };
-                                if let Ok(page_content) = page_content {
-                                    let page_content_str = page_content["query"]["pages"][0]["revisions"][0]["slots"]["main"]["content"].as_str();
-                                    if let Some(page_content_str) = page_content_str {
-                                        Ok(page_content_str.to_owned())
-                                    } else {
-                                        event!(Level::WARN, response = ?page_content, "cannot find page content in response");
-                                        Err(())
-                                    }
-                                } else {
-                                    event!(Level::WARN, error = ?page_content.unwrap_err(), "cannot fetch original target page content");
-                                    Err(())
-                                }
-                            };
-
-                            if let Ok(orig_content) = orig_content {
-                                // The page content, when trimmed from start, should start with <noinclude>
-                                // If that is the case, copy everything after the first </noinclude> if it exists
-                                // Otherwise, just copy the whole page
-                                if orig_content.trim_start().starts_with("<noinclude>") {
-                                    // If the remaining parts has a pairing </noinclude>, copy everything after the first </noinclude>
-                                    // Otherwise copy the whole page
-                                    // Cannot defend against some complicated scenarios such as </noinclude> in comments, in <nowiki> tags, etc
-                                    // Luckily if the original content is generated by the bot this will not be a problem
-                                    if let Some(offset) = orig_content.find("</noinclude>") {
-                                        content.push_str(&orig_content[offset + "</noinclude>".len()..]);
-                                        Ok(content)
-                                    } else {
-                                        content.push_str(&orig_content);
-                                        Ok(content)
-                                    }
-                                } else {
-                                    content.push_str(&orig_content);
-                                    Ok(content)
-                                }
-                            } else {
-                                Err(())
-                            }
+                    // the list is sorted already, so the same items are kept every time
+                    let shown = outputformat.maxitems.unwrap_or(ls.len()).min(ls.len());
+                    let omitted = ls.len() - shown;
+                    if outputformat.kind == OutputKind::Table {
+                        let mut output: String = String::new();
+                        output.push_str(&self.substitute_str_template(&outputformat.success.before, ls.len(), &description));
+                        // a table only starts at the beginning of a line
+                        if !output.is_empty() && !output.ends_with('\n') {
+                            output.push('\n');
                         }
-                    };
-                    
-                    if let Ok(content) = content {
-                        event!(Level::DEBUG, "content ready");
-                        // write to page
-                        let md5 = self.get_md5(&content);
-                        let params = hashmap![
-                            "action".to_string() => "edit".to_string(),
-                            "title".to_string() => outputformat.target.clone(),
-                            "text".to_string() => content,
-                            "summary".to_string() => summary,
-                            "md5".to_string() => md5,
-                            "nocreate".to_string() => "1".to_string(),
-                            "token".to_string() => API_SERVICE.csrf().await
-                        ];
-                        let edit_result = {
-                            API_SERVICE.get_lock().lock().await;
-                            API_SERVICE.post_edit(&params).await
-                        };
-                        if edit_result.is_err() {
-                            event!(Level::WARN, error = ?edit_result.unwrap_err(), "cannot edit page");
-                        } else {
-                            event!(Level::INFO, "edit page successful");
+                        output.push_str(&self.make_table_body(outputformat, &ls[..shown], &description).await);
+                        if omitted > 0 {
+                            output.push('\n');
+                            output.push_str(&self.substitute_more_template(&outputformat.success.more, ls.len(), omitted));
                         }
+                        output.push_str(&self.substitute_str_template(&outputformat.success.after, ls.len(), &description));
+                        Ok(output)
                     } else {
-                        event!(Level::WARN, "page edit cancelled");
+                        Ok(self.make_list_body(outputformat, &ls[..shown], 0, ls.len(), omitted, &description).await)
                     }
                 }
-            }
+            },
+            Err(_) => {
+                if self.eager_mode {
+                    Ok(outputformat.failure.clone())
+                } else {
+                    Err(())
+                }
+            },
+        };
+
+        let compose = |page: Option<&str>| Self::compose(&header, body.as_deref().ok(), outputformat.section, page);
+        if let Some(path) = &file {
+            self.write_file(path, &compose).await;
+        } else {
+            // write to page
+            self.edit_page(&outputformat.target, &compose, &summary, false).await;
         }
     }
 
@@ -291,6 +1100,14 @@ impl<'a> PageWriter<'a> {
             .instrument(span!(Level::INFO, "page writer routine for one", page = outputformat.target.as_str()))
             .await;
         }
+        // the query does not run if no page can be written
+        if let Some(error_log) = self.error_log {
+            let executor = self.query_executor.lock().await;
+            match executor.result() {
+                None | Some(Err(QueryExecutorError::Cancelled)) => (),
+                Some(result) => error_log.report(self.task_id, self.make_status_text(result)).await,
+            }
+        }
     }
 
 }
@@ -1,25 +1,116 @@
+use std::{path::Path, time::Duration};
+
+use md5::{Md5, Digest};
 use mediawiki::title::Title;
+use tokio_util::sync::CancellationToken;
 use tracing::{event, Level};
 
-use crate::API_SERVICE;
+use crate::apiservice::APIService;
+use crate::parser::DepthMergePolicy;
+use crate::solver::{SolveError, SolveProgress, ResultCache};
 use super::types::TaskConfig;
 
+#[derive(Clone, Copy)]
 pub enum QueryExecutorError {
     Timeout,
     Parse,
     Solve,
+    Cancelled,
 }
 
 pub struct QueryExecutor {
+    api: &'static APIService,
     query: String,
     querylimit: TaskConfig,
+    state_dir: Option<String>,
+    result_cache_ttl: Option<Duration>,
+    excluded: Vec<String>,
+    excluded_subpages: Vec<String>,
+    depth_merge: DepthMergePolicy,
+    partial: bool,
+    cancel: CancellationToken,
 
     result: Option<Result<Vec<Title>, QueryExecutorError>>,
 }
 
 impl QueryExecutor {
-    pub fn new(query: &str, limit: &TaskConfig) -> Self {
-        QueryExecutor { query: query.to_string(), querylimit: limit.clone(), result: None }
+    pub fn new(api: &'static APIService, query: &str, limit: &TaskConfig) -> Self {
+        QueryExecutor { api, query: query.to_string(), querylimit: limit.clone(), state_dir: None, result_cache_ttl: None, excluded: Vec::new(), excluded_subpages: Vec::new(), depth_merge: DepthMergePolicy::default(), partial: false, cancel: CancellationToken::new(), result: None }
+    }
+
+    /// Set the token to cancel the query with.
+    pub fn set_cancellation_token(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Set how two different depth constraints in the query are merged.
+    pub fn set_depth_merge_policy(mut self, depth_merge: DepthMergePolicy) -> Self {
+        self.depth_merge = depth_merge;
+        self
+    }
+
+    /// Set whether to go on with the results gathered so far when a continued query fails mid-way.
+    /// The result may then be incomplete.
+    pub fn set_partial_results(mut self, partial: bool) -> Self {
+        self.partial = partial;
+        self
+    }
+
+    /// Set the pages that should never appear in the result, such as the pages the task writes to.
+    pub fn set_excluded_titles(mut self, titles: &[String]) -> Self {
+        self.excluded = titles.to_vec();
+        self
+    }
+
+    /// Set the pages whose numbered subpages, i.e. `Page/1`, `Page/2` and so on, are removed from the result.
+    pub fn set_excluded_subpages_of(mut self, titles: &[String]) -> Self {
+        self.excluded_subpages = titles.to_vec();
+        self
+    }
+
+    /// Set the directory for query checkpoints. If set, an interrupted query resumes from its last checkpoint.
+    pub fn set_state_dir(mut self, state_dir: Option<&str>) -> Self {
+        self.state_dir = state_dir.map(|s| s.to_owned());
+        self
+    }
+
+    /// Set how long query results are kept in the cache under the state directory, to be reused by later queries.
+    /// `None` disables the cache. Without a state directory, there is no cache either.
+    pub fn set_result_cache_ttl(mut self, ttl: Option<Duration>) -> Self {
+        self.result_cache_ttl = ttl;
+        self
+    }
+
+    /// The cache is shared by every query on the same site, but not across sites.
+    async fn result_cache(&self) -> Option<ResultCache> {
+        let (state_dir, ttl) = (self.state_dir.as_ref()?, self.result_cache_ttl?);
+        let mut hasher = Md5::new();
+        hasher.update(self.api.api_url().await?);
+        Some(ResultCache::new(&Path::new(state_dir).join("cache").join(hex::encode(hasher.finalize())), ttl))
+    }
+
+    /// The checkpoint file name is derived from the query itself, so that an edited query never picks up a stale checkpoint.
+    /// The site is part of it too, as the same query on another site has nothing to resume from.
+    async fn checkpoint_name(&self) -> String {
+        let mut hasher = Md5::new();
+        hasher.update(format!("{}\n{}\n{}", self.api.api_url().await.unwrap_or_default(), self.querylimit.querylimit, self.query));
+        format!("{}.json", hex::encode(hasher.finalize()))
+    }
+
+    /// Describe the query in plain language. Returns `None` if the query does not parse.
+    pub fn describe(&self) -> Option<String> {
+        crate::parser::parse(&self.query, self.depth_merge).ok().map(|query| crate::parser::describe(&query))
+    }
+
+    /// Whether the query only asks for the number of results. Returns `false` if the query does not parse.
+    pub fn is_count(&self) -> bool {
+        crate::parser::parse(&self.query, self.depth_merge).map(|query| crate::parser::is_count(&query)).unwrap_or(false)
+    }
+
+    /// The result of the query, if it has been executed.
+    pub fn result(&self) -> Option<&Result<Vec<Title>, QueryExecutorError>> {
+        self.result.as_ref()
     }
 
     pub async fn execute(&mut self) -> &Result<Vec<Title>, QueryExecutorError> {
@@ -27,27 +118,59 @@ impl QueryExecutor {
         if self.result.is_none() {
             event!(Level::INFO, "executor lazy loads");
             // run the query first
-            let parse_result = crate::parser::parse(&self.query);
+            let parse_result = crate::parser::parse(&self.query, self.depth_merge);
             if parse_result.is_err() {
                 event!(Level::WARN, error = ?parse_result.unwrap_err(), "parse failure");
                 self.result = Some(Err(QueryExecutorError::Parse));
             } else {
                 let query_inst = parse_result.unwrap();
+                if crate::parser::is_likely_unbounded(&query_inst) {
+                    event!(Level::WARN, "query result is likely unbounded, which may cover a whole namespace");
+                }
                 let query_result = {
-                    API_SERVICE.get_lock().lock().await;
-                    tokio::time::timeout(tokio::time::Duration::from_secs(self.querylimit.timeout), crate::solver::solve_api(&query_inst, self.querylimit.querylimit)).await
+                    self.api.get_lock().lock().await;
+                    let timeout = Some(Duration::from_secs(self.querylimit.timeout));
+                    let mut report = |p: &SolveProgress| {
+                        event!(Level::DEBUG, step = p.step, total = p.total, instruction = ?p.instruction, size = p.size, "solve progress");
+                    };
+                    if let Some(state_dir) = &self.state_dir {
+                        let checkpoint = Path::new(state_dir).join(self.checkpoint_name().await);
+                        let result_cache = self.result_cache().await;
+                        crate::solver::solve_api_resumable(self.api, &query_inst, self.querylimit.querylimit, timeout, self.partial, &checkpoint, result_cache.as_ref(), &self.cancel, Some(&mut report)).await
+                    } else {
+                        crate::solver::solve_api_with_progress(self.api, &query_inst, self.querylimit.querylimit, timeout, self.partial, &self.cancel, &mut report).await
+                    }
                 };
 
-                if query_result.is_err() {
-                    event!(Level::WARN, "query timeout");
-                    self.result = Some(Err(QueryExecutorError::Timeout));
-                } else {
-                    let query_result = query_result.unwrap();
-                    if query_result.is_err() {
-                        event!(Level::WARN, error = ?query_result.unwrap_err(), "solve failure");
+                match query_result {
+                    Err(SolveError::Timeout) => {
+                        event!(Level::WARN, "query timeout");
+                        self.result = Some(Err(QueryExecutorError::Timeout));
+                    },
+                    Err(SolveError::Cancelled) => {
+                        event!(Level::INFO, "query cancelled");
+                        self.result = Some(Err(QueryExecutorError::Cancelled));
+                    },
+                    Err(e) => {
+                        event!(Level::WARN, error = ?e, "solve failure");
                         self.result = Some(Err(QueryExecutorError::Solve));
-                    } else {
-                        let query_result = query_result.unwrap();
+                    },
+                    Ok(mut query_result) => {
+                        // remove the excluded pages, so that a report never lists itself
+                        for t in &self.excluded {
+                            if let Ok(title) = self.api.title_new_from_full(t).await {
+                                query_result.remove(&title);
+                            }
+                        }
+                        for t in &self.excluded_subpages {
+                            if let Ok(base) = self.api.title_new_from_full(t).await {
+                                let prefix = format!("{}/", base.pretty());
+                                query_result.retain(|title| {
+                                    let num = title.pretty().strip_prefix(prefix.as_str());
+                                    !(title.namespace_id() == base.namespace_id() && num.map(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit())).unwrap_or(false))
+                                });
+                            }
+                        }
                         let mut titles_vec = Vec::from_iter(query_result.into_iter());
                         titles_vec.sort_by(|a, b| {
                             match a.namespace_id().cmp(&b.namespace_id()) {
@@ -57,8 +180,8 @@ impl QueryExecutor {
                             }
                         });
                         self.result = Some(Ok(titles_vec));
-                    }
-                    event!(Level::INFO, "query successful");
+                        event!(Level::INFO, "query successful");
+                    },
                 }
             }
         }
@@ -1,38 +1,58 @@
-use std::{collections::{HashMap, HashSet}, sync::Arc};
+use std::{collections::{HashMap, HashSet}, sync::Arc, time::Duration};
 
 use mediawiki::{hashmap, api::NamespaceID};
-use tokio::{task::JoinHandle, sync::{RwLock, Mutex}};
+use tokio::{task::JoinHandle, sync::{Notify, RwLock, Mutex, Semaphore}};
+use tokio_util::sync::CancellationToken;
 use tracing::{event, Level, Instrument, span};
 
-use crate::API_SERVICE;
+use crate::apiservice::APIService;
 
 use super::types::{SiteConfig, TaskConfig};
 use super::taskrunner::TaskRunner;
+use super::errorlog::ErrorLog;
 
 pub struct TaskFinder {
+    api: &'static APIService,
     on_site_config_location: Mutex<String>,
+    state_dir: Mutex<Option<String>>,
+    output_dir: Mutex<Option<String>>,
+    result_cache_ttl: Mutex<Option<Duration>>,
 
     global_activate: Arc<RwLock<bool>>,
     global_query_config: Arc<RwLock<TaskConfig>>,
     global_denied_namespace: Arc<RwLock<HashSet<NamespaceID>>>,
     global_output_header: Arc<RwLock<String>>,
+    global_task_slots: Arc<RwLock<Option<Arc<Semaphore>>>>,
+    max_concurrent: Mutex<Option<usize>>,
+    error_log: Arc<ErrorLog>,
     task_map: Mutex<HashMap<i64, TaskRunner>>,
+    cancel: CancellationToken,
+    reload: Notify,
 
     finderhandle: Mutex<Option<JoinHandle<()>>>,
 }
 
 impl TaskFinder {
 
-    pub fn new() -> Self {
+    pub fn new(api: &'static APIService) -> Self {
         TaskFinder {
+            api,
             on_site_config_location: Mutex::new("".to_owned()),
+            state_dir: Mutex::new(None),
+            output_dir: Mutex::new(None),
+            result_cache_ttl: Mutex::new(None),
 
             global_activate: Arc::new(RwLock::new(false)),
             global_query_config: Arc::new(RwLock::new(TaskConfig::new())),
             global_denied_namespace: Arc::new(RwLock::new(HashSet::new())),
             global_output_header: Arc::new(RwLock::new(String::new())),
+            global_task_slots: Arc::new(RwLock::new(None)),
+            max_concurrent: Mutex::new(None),
+            error_log: Arc::new(ErrorLog::new()),
 
             task_map: Mutex::new(HashMap::new()),
+            cancel: CancellationToken::new(),
+            reload: Notify::new(),
             finderhandle: Mutex::new(None),
         }
     }
@@ -42,11 +62,33 @@ impl TaskFinder {
         *self_config_loc = config_location.to_owned();
     }
 
+    /// Set the directory for query checkpoints. `None` disables checkpoints.
+    pub async fn set_state_dir(&self, state_dir: Option<&str>) {
+        let mut self_state_dir = self.state_dir.lock().await;
+        *self_state_dir = state_dir.map(|s| s.to_owned());
+    }
+
+    /// Set the directory `file://` output targets are written in. `None` disables file targets.
+    pub async fn set_output_dir(&self, output_dir: Option<&str>) {
+        let mut self_output_dir = self.output_dir.lock().await;
+        *self_output_dir = output_dir.map(|s| s.to_owned());
+    }
+
+    /// Set how long query results are kept in the cache under the state directory. `None` disables the cache.
+    pub async fn set_result_cache_ttl(&self, ttl: Option<Duration>) {
+        let mut self_result_cache_ttl = self.result_cache_ttl.lock().await;
+        *self_result_cache_ttl = ttl;
+    }
+
     pub async fn start(&'static self) {
         _ = tokio::task::spawn_blocking(|| self.stop()).await;
         let handle = tokio::spawn(async {
+            // whether the last sleep was cut short by `reload`
+            let mut reloading: bool = false;
             loop {
                 event!(Level::INFO, "task finder starts");
+                // a broken configuration page keeps the current configuration, instead of stopping every task
+                let mut keep_config: bool = false;
                 // fetch on-site config
                 let on_site_config: Result<SiteConfig, ()> = {
                     // fetch page content
@@ -62,8 +104,8 @@ impl TaskFinder {
                         "rvlimit".to_string() => "1".to_string()
                     ];
                     let page_content = {
-                        API_SERVICE.get_lock().lock().await;
-                        API_SERVICE.get(&params).await
+                        self.api.get_lock().lock().await;
+                        self.api.get(&params).await
                     };
                     if let Ok(page_content) = page_content {
                         let page_content_str = page_content["query"]["pages"][0]["revisions"][0]["slots"]["main"]["content"].as_str();
@@ -72,7 +114,8 @@ impl TaskFinder {
                             if let Ok(config) = config {
                                 Ok(config)
                             } else {
-                                event!(Level::WARN, content = page_content_str, "cannot parse on-site configuration");
+                                event!(Level::WARN, content = page_content_str, "cannot parse on-site configuration, keeping the current one");
+                                keep_config = true;
                                 Err(())
                             }
                         } else {
@@ -103,10 +146,25 @@ impl TaskFinder {
                         let mut global_output_header = self.global_output_header.write().await;
                         *global_output_header = config.resultheader;
                     }
+                    {
+                        // only replace the slots when the limit changes, as tasks running now hold slots of the current ones
+                        let mut max_concurrent = self.max_concurrent.lock().await;
+                        if *max_concurrent != config.maxconcurrent {
+                            *max_concurrent = config.maxconcurrent;
+                            let mut global_task_slots = self.global_task_slots.write().await;
+                            *global_task_slots = config.maxconcurrent.map(|n| Arc::new(Semaphore::new(n.max(1))));
+                        }
+                    }
                     event!(Level::INFO, "global params update successful");
+                    // write the failures since the last check in one edit
+                    if let Some(errorlog) = &config.errorlog {
+                        self.error_log.flush(self.api, errorlog).await;
+                    } else {
+                        self.error_log.discard().await;
+                    }
                     // fetch tasks
                     // so long as we can get site config, there is always an `Api` present in the service
-                    let taskdir_title = API_SERVICE.title_new_from_full(&config.taskdir).await.unwrap(); 
+                    let taskdir_title = self.api.title_new_from_full(&config.taskdir).await.unwrap(); 
                     let params = hashmap![
                         "action".to_string() => "query".to_string(),
                         "prop".to_string() => "info".to_string(),
@@ -117,8 +175,8 @@ impl TaskFinder {
                         "gapfilterredir".to_string() => "nonredirects".to_string()
                     ];
                     let tasks = {
-                        API_SERVICE.get_lock().lock().await;
-                        API_SERVICE.get_all(&params).await
+                        self.api.get_lock().lock().await;
+                        self.api.get_all(&params).await
                     };
                     if let Ok(tasks_result) = tasks {
                         let tasks = tasks_result["query"]["pages"].as_array().unwrap();
@@ -132,14 +190,32 @@ impl TaskFinder {
                             }
                         }
                         event!(Level::DEBUG, pool = ?task_pool, count = task_pool.len(), "task gathered");
+                        let state_dir = {
+                            let lock = self.state_dir.lock().await;
+                            (*lock).clone()
+                        };
+                        let output_dir = {
+                            let lock = self.output_dir.lock().await;
+                            (*lock).clone()
+                        };
+                        let result_cache_ttl = {
+                            let lock = self.result_cache_ttl.lock().await;
+                            *lock
+                        };
                         {
                             let mut task_map = self.task_map.lock().await;
                             // kill all tasks whose id does not live in the pool
                             (*task_map).retain(|k, _| task_pool.contains(k));
+                            // let the remaining tasks pick up their changes
+                            if reloading {
+                                for task_runner in task_map.values() {
+                                    task_runner.reload();
+                                }
+                            }
                             // create and start new tasks
                             for id in task_pool {
                                 (*task_map).entry(id).or_insert_with(|| {
-                                    let mut task_runner: TaskRunner = TaskRunner::new(id, self.global_activate.clone(), self.global_query_config.clone(), self.global_denied_namespace.clone(), self.global_output_header.clone());
+                                    let mut task_runner: TaskRunner = TaskRunner::new(id, self.api, self.global_activate.clone(), self.global_query_config.clone(), self.global_denied_namespace.clone(), self.global_output_header.clone(), self.global_task_slots.clone(), self.error_log.clone(), state_dir.clone(), result_cache_ttl, output_dir.clone(), self.cancel.clone());
                                     task_runner.start();
                                     task_runner
                                 });
@@ -154,21 +230,61 @@ impl TaskFinder {
                         }
                         event!(Level::WARN, error = ?tasks.unwrap_err(), "cannot get task list");
                     }
-                } else {
+                } else if !keep_config {
                     // we always set the global activated to false to prevent any accidents
                     {
                         let mut global_activate = self.global_activate.write().await;
                         *global_activate = false;
                     }
                 }
-                // sleep for a fixed 10 minutes
-                tokio::time::sleep(tokio::time::Duration::from_secs(10 * 60)).await;
+                // sleep for a fixed 10 minutes, or until asked to reload
+                reloading = tokio::select! {
+                    _ = tokio::time::sleep(tokio::time::Duration::from_secs(10 * 60)) => false,
+                    _ = self.reload.notified() => true,
+                };
+                if reloading {
+                    event!(Level::INFO, "reload requested");
+                }
             }
         }.instrument(span!(target: "Task Finder", Level::INFO, "task finder routine")));
         let mut finderhandle = self.finderhandle.lock().await;
         *finderhandle = Some(handle);
     }
 
+    /// Fetch the configuration and the task list again right away, and let every task fetch its information again.
+    pub fn reload(&self) {
+        self.reload.notify_one();
+    }
+
+    /// Run the task `id` once now, regardless of its schedule.
+    /// Returns whether this finder has such a task.
+    pub async fn trigger(&self, id: i64) -> bool {
+        let task_map = self.task_map.lock().await;
+        if let Some(task_runner) = task_map.get(&id) {
+            event!(Level::WARN, task_id = id, "manual trigger received");
+            task_runner.trigger();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Stop finding new tasks, cancel all running queries, and wait for every task runner to finish.
+    /// Pages whose query is cancelled are left untouched.
+    pub async fn shutdown(&self) {
+        self.cancel.cancel();
+        {
+            let mut finderhandle = self.finderhandle.lock().await;
+            if let Some(handle) = finderhandle.take() {
+                handle.abort();
+            }
+        }
+        let mut task_map = self.task_map.lock().await;
+        for (_, mut task_runner) in task_map.drain() {
+            task_runner.join().await;
+        }
+    }
+
     #[inline]
     fn stop(&self) {
         let mut finderhandle = self.finderhandle.blocking_lock();
@@ -1,41 +1,68 @@
 use std::str::FromStr;
-use std::{sync::Arc, collections::HashSet};
+use std::{sync::Arc, collections::HashSet, time::Duration};
 
 use mediawiki::api::NamespaceID;
 use mediawiki::hashmap;
-use tokio::{task::JoinHandle, sync::RwLock};
+use tokio::{task::JoinHandle, sync::{Notify, RwLock, Semaphore}};
+use tokio_util::sync::CancellationToken;
 use tracing::{event, Level, Instrument, span};
 
-use crate::API_SERVICE;
+use crate::apiservice::APIService;
 
-use super::types::{TaskInfo, TaskConfig};
-use super::{pagewriter::PageWriter, queryexecutor::QueryExecutor};
+use super::types::{TaskInfo, TaskConfig, FILE_TARGET_PREFIX};
+use super::{errorlog::ErrorLog, pagewriter::PageWriter, queryexecutor::QueryExecutor};
 
 pub struct TaskRunner {
     id: i64,
+    api: &'static APIService,
     global_activate: Arc<RwLock<bool>>,
     global_query_config: Arc<RwLock<TaskConfig>>,
     global_denied_namespace: Arc<RwLock<HashSet<NamespaceID>>>,
     global_output_header: Arc<RwLock<String>>,
+    global_task_slots: Arc<RwLock<Option<Arc<Semaphore>>>>,
+    error_log: Arc<ErrorLog>,
+    state_dir: Option<String>,
+    result_cache_ttl: Option<Duration>,
+    output_dir: Option<String>,
+    cancel: CancellationToken,
+    trigger: Arc<Notify>,
+    reload: Arc<Notify>,
 
     runnerhandle: Option<JoinHandle<()>>,
 }
 
 impl TaskRunner {
 
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         id: i64,
+        api: &'static APIService,
         global_activate: Arc<RwLock<bool>>,
         global_query_config: Arc<RwLock<TaskConfig>>,
         global_denied_namespace: Arc<RwLock<HashSet<NamespaceID>>>,
-        global_output_header: Arc<RwLock<String>>
+        global_output_header: Arc<RwLock<String>>,
+        global_task_slots: Arc<RwLock<Option<Arc<Semaphore>>>>,
+        error_log: Arc<ErrorLog>,
+        state_dir: Option<String>,
+        result_cache_ttl: Option<Duration>,
+        output_dir: Option<String>,
+        cancel: CancellationToken,
     ) -> Self {
         TaskRunner {
             id,
+            api,
             global_activate,
             global_query_config,
             global_denied_namespace,
             global_output_header,
+            global_task_slots,
+            error_log,
+            state_dir,
+            result_cache_ttl,
+            output_dir,
+            cancel,
+            trigger: Arc::new(Notify::new()),
+            reload: Arc::new(Notify::new()),
             runnerhandle: None,
         }
     }
@@ -44,15 +71,30 @@ impl TaskRunner {
         self.stop();
         let handler: JoinHandle<()> = {
             let id = self.id;
+            let api = self.api;
             let global_activate = self.global_activate.clone();
             let global_query_config = self.global_query_config.clone();
             let global_denied_namespace = self.global_denied_namespace.clone();
             let global_output_header = self.global_output_header.clone();
+            let global_task_slots = self.global_task_slots.clone();
+            let error_log = self.error_log.clone();
+            let state_dir = self.state_dir.clone();
+            let result_cache_ttl = self.result_cache_ttl;
+            let output_dir = self.output_dir.clone();
+            let cancel = self.cancel.clone();
+            let trigger = self.trigger.clone();
+            let reload = self.reload.clone();
 
             tokio::spawn(async move {
                 // used in first run; we need to align the task runner to cron
                 let mut aligned_to_cron: bool = false;
-                loop {
+                // why the runner woke up last time
+                let mut woken = Wake::Schedule;
+                // the task information last parsed, in case the page is broken later
+                let mut last_task: Option<TaskInfo> = None;
+                // when the current interval started, which a reload does not change
+                let mut interval_start: Option<tokio::time::Instant> = None;
+                while !cancel.is_cancelled() {
                     // fetch task information
                     event!(Level::INFO, "task started");
                     let task: Result<TaskInfo, ()> = {
@@ -66,16 +108,19 @@ impl TaskRunner {
                             "rvlimit".to_string() => "1".to_string()
                         ];
                         let page_content = {
-                            API_SERVICE.get_lock().lock().await;
-                            API_SERVICE.get(&params).await
+                            api.get_lock().lock().await;
+                            api.get(&params).await
                         };
 
                         if let Ok(page_content) = page_content {
                             let page_content_str = page_content["query"]["pages"][0]["revisions"][0]["slots"]["main"]["content"].as_str();
                             if let Some(page_content_str) = page_content_str {
-                                let task = serde_json::from_str(page_content_str);
-                                if let Ok(task) = task {
+                                let task = serde_json::from_str::<TaskInfo>(page_content_str).ok().filter(TaskInfo::has_schedule);
+                                if let Some(task) = task {
                                     Ok(task)
+                                } else if let Some(last_task) = &last_task {
+                                    event!(Level::WARN, content = page_content_str, "cannot parse task information, keeping the previous one");
+                                    Ok(last_task.clone())
                                 } else {
                                     event!(Level::WARN, content = page_content_str, "cannot parse task information");
                                     Err(())
@@ -90,12 +135,44 @@ impl TaskRunner {
                         } 
                     };
                     if let Ok(task) = task {
+                        last_task = Some(task.clone());
                         let global_activated = {
                             let glb_lock = global_activate.read().await;
                             *glb_lock
                         };
                         // run the task only if bot is globally activated, the task is activated, and the runner is aligned to cron
-                        if global_activated && task.activate && aligned_to_cron {
+                        // a task run on an interval needs no alignment
+                        let due = match woken {
+                            Wake::Schedule => aligned_to_cron || task.interval.is_some(),
+                            Wake::Trigger => {
+                                event!(Level::WARN, "task triggered manually");
+                                true
+                            },
+                            // only pick up the new schedule
+                            Wake::Reload => {
+                                event!(Level::INFO, "task information reloaded");
+                                false
+                            },
+                        };
+                        if global_activated && task.activate && due {
+                            // wait for a free slot, if the number of tasks running at the same time is limited
+                            // the slot is held until the pages are written
+                            let task_slots = {
+                                let value = global_task_slots.read().await;
+                                value.clone()
+                            };
+                            let _task_slot = if let Some(task_slots) = task_slots {
+                                tokio::select! {
+                                    permit = task_slots.acquire_owned() => permit.ok(),
+                                    _ = cancel.cancelled() => None,
+                                }
+                            } else {
+                                None
+                            };
+                            // the bot is shutting down while the task waits for its turn
+                            if cancel.is_cancelled() {
+                                break;
+                            }
                             let task_config = {
                                 let value = global_query_config.read().await;
                                 let timeout = task.timeout.unwrap_or(value.timeout);
@@ -110,43 +187,101 @@ impl TaskRunner {
                                 let value = global_output_header.read().await;
                                 value.clone()
                             };
-                            let writer = PageWriter::new(QueryExecutor::new(&task.expr, &task_config))
+                            // never list the task's own output pages in the result
+                            let excluded: Vec<String> = task.output.iter()
+                                .filter(|o| !o.target.starts_with(FILE_TARGET_PREFIX))
+                                .map(|o| o.target.clone())
+                                .chain(task.exclude.clone().unwrap_or_default())
+                                .collect();
+                            // nor the subpages a paginated list is split into
+                            let paginated: Vec<String> = task.output.iter()
+                                .filter(|o| o.pagination.is_some() && !o.target.starts_with(FILE_TARGET_PREFIX))
+                                .map(|o| o.target.clone())
+                                .collect();
+                            let executor = QueryExecutor::new(api, &task.expr, &task_config)
+                                .set_state_dir(state_dir.as_deref())
+                                .set_result_cache_ttl(result_cache_ttl)
+                                .set_excluded_titles(&excluded)
+                                .set_excluded_subpages_of(&paginated)
+                                .set_depth_merge_policy(task.depthmerge.unwrap_or_default())
+                                .set_partial_results(task.partial.unwrap_or(false))
+                                .set_cancellation_token(cancel.clone());
+                            let writer = PageWriter::new(api, executor)
                                 .set_task_id(id)
                                 .set_output_format(&task.output)
                                 .set_eager_mode(task.eager.unwrap_or(false))
+                                .set_dry_run(task.dryrun.unwrap_or(false))
+                                .set_bot_flag(task.bot.unwrap_or(false))
+                                .set_minor(task.minor.unwrap_or(false))
                                 .set_denied_namespace(&denied_ns)
-                                .set_header_template_name(&output_header);
+                                .set_header_template_name(&output_header)
+                                .set_error_log(&error_log)
+                                .set_output_dir(output_dir.as_deref())
+                                .set_namespace_limit(task.nslimit);
+                            let writer = match &task.summary {
+                                Some(summary) => writer.set_summary_template(summary),
+                                None => writer,
+                            };
                             writer.start().instrument(span!(Level::INFO, "Page writer")).await;
                         }
                         // sleep until next cron time
                         let schedule = cron::Schedule::from_str(&task.cron);
-                        if let Ok(schedule) = schedule {
+                        if let Some(interval) = task.interval {
+                            // after a reload, the task is still due when the interval started before it ends, so that reloads cannot keep pushing it back
+                            let start = match (&woken, interval_start) {
+                                (Wake::Reload, Some(start)) => start,
+                                _ => tokio::time::Instant::now(),
+                            };
+                            interval_start = Some(start);
+                            let duration = (start + std::time::Duration::from_secs(interval.max(1) * 60)).saturating_duration_since(tokio::time::Instant::now());
+                            event!(Level::INFO, "task will sleep for {} minutes", duration.as_secs() / 60);
+                            woken = sleep_until_woken(duration, &cancel, &trigger, &reload).await;
+                        } else if let Ok(schedule) = schedule {
+                            interval_start = None;
                             let waketime = schedule.upcoming(chrono::Utc).next().unwrap();
                             let duration = waketime.signed_duration_since(chrono::Utc::now()).to_std().unwrap();
                             event!(Level::INFO, "task will sleep until {}", waketime);
                             aligned_to_cron = true;
-                            tokio::time::sleep(duration).await;
+                            woken = sleep_until_woken(duration, &cancel, &trigger, &reload).await;
                         } else {
                             event!(Level::WARN, cron = task.cron.as_str(), error = ?schedule.unwrap_err(), "cannot parse cron specification");
                             // need to re-align later
                             aligned_to_cron = false;
                             // retry in 10 minutes
                             event!(Level::INFO, "task will retry in 10 minutes");
-                            tokio::time::sleep(tokio::time::Duration::from_secs(10 * 60)).await;
+                            woken = sleep_until_woken(tokio::time::Duration::from_secs(10 * 60), &cancel, &trigger, &reload).await;
                         }
                     } else {
                         // need to re-align later
                         aligned_to_cron = false;
                         // retry in 10 minutes
                         event!(Level::INFO, "task will retry in 10 minutes");
-                        tokio::time::sleep(tokio::time::Duration::from_secs(10 * 60)).await;
+                        woken = sleep_until_woken(tokio::time::Duration::from_secs(10 * 60), &cancel, &trigger, &reload).await;
                     }
                 }
+                event!(Level::INFO, "task runner stops");
             }.instrument(span!(target: "Task Runner", Level::INFO, "task runner routine", task_id = id)))
         };
         self.runnerhandle = Some(handler);
     }
 
+    /// Run the task once now, regardless of its schedule. If the task is running, it runs again right after.
+    pub fn trigger(&self) {
+        self.trigger.notify_one();
+    }
+
+    /// Fetch the task information again, so that a changed schedule applies right away. The task is not run.
+    pub fn reload(&self) {
+        self.reload.notify_one();
+    }
+
+    /// Wait for the runner to finish. The runner only finishes by itself after its cancellation token is cancelled.
+    pub async fn join(&mut self) {
+        if let Some(handler) = self.runnerhandle.take() {
+            _ = handler.await;
+        }
+    }
+
     #[inline]
     fn stop(&mut self) {
         if let Some(handler) = &self.runnerhandle {
@@ -162,3 +297,23 @@ impl Drop for TaskRunner {
         self.stop();
     }
 }
+
+/// Why a task runner woke up.
+enum Wake {
+    /// The task is due, or the runner is shutting down.
+    Schedule,
+    /// The task is to run right away.
+    Trigger,
+    /// The task information is to be fetched again, without running the task.
+    Reload,
+}
+
+/// Sleep for `duration`, but wake up early if `cancel` is cancelled, or `trigger` or `reload` is notified.
+async fn sleep_until_woken(duration: std::time::Duration, cancel: &CancellationToken, trigger: &Notify, reload: &Notify) -> Wake {
+    tokio::select! {
+        _ = tokio::time::sleep(duration) => Wake::Schedule,
+        _ = cancel.cancelled() => Wake::Schedule,
+        _ = trigger.notified() => Wake::Trigger,
+        _ = reload.notified() => Wake::Reload,
+    }
+}
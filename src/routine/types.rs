@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 #[derive(PartialEq, Eq, Clone, Debug, serde::Deserialize)]
 pub struct TaskConfig {
     pub timeout: u64,
@@ -17,9 +19,15 @@ impl TaskConfig {
 pub struct SiteConfig {
     pub activate: bool,
     pub taskdir: String,
+    /// The template substituted at the top of every result, with the parameters `taskid`, `status`, and `desc` (the query described in plain words).
     pub resultheader: String,
     pub denyns: Vec<mediawiki::api::NamespaceID>,
     pub default: TaskConfig,
+    /// The maximum number of tasks running at the same time. Tasks due beyond that wait for a running one to finish.
+    /// `None` means no limit.
+    pub maxconcurrent: Option<usize>,
+    /// The page to report task failures on. `None` means failures are only logged locally.
+    pub errorlog: Option<String>,
 }
 
 #[derive(PartialEq, Eq, Clone, Debug, serde::Deserialize)]
@@ -27,25 +35,190 @@ pub struct TaskInfo {
     pub activate: bool,
     pub description: String,
     pub expr: String,
+    /// When the task runs, as a cron expression with seconds, e.g. `0 0 3 * * *` for every day at 03:00 UTC.
+    #[serde(alias = "schedule", default)]
     pub cron: String,
+    /// Minutes between two runs of the task, counted from the end of a run. Takes the place of `cron` if set.
+    /// Without it, the task runs on `cron`. A task with neither is rejected when it is loaded, the same as one that cannot be parsed.
+    pub interval: Option<u64>,
     pub eager: Option<bool>,
+    /// Go on with the results gathered so far when a query that spans several responses fails mid-way, such as the backlinks of a widely linked page.
+    /// The list written may then be incomplete, without any mark on the page, only a warning in the log.
+    /// Without it, such a failure fails the whole query.
+    pub partial: Option<bool>,
     pub timeout: Option<u64>,
     pub querylimit: Option<i64>,
+    pub exclude: Option<Vec<String>>,
+    /// The maximum number of titles written from each namespace. The titles kept are the first ones in the order of each output, after `sort` and `reverse`.
+    pub nslimit: Option<usize>,
+    pub depthmerge: Option<crate::parser::DepthMergePolicy>,
+    pub dryrun: Option<bool>,
+    pub summary: Option<SummaryTemplate>,
+    pub bot: Option<bool>,
+    pub minor: Option<bool>,
     pub output: Vec<OutputFormat>,
 }
 
-#[derive(PartialEq, Eq, Clone, Debug, serde::Deserialize)]
+impl TaskInfo {
+    /// Whether the task says when it runs, i.e. has a `cron` or an `interval`.
+    pub fn has_schedule(&self) -> bool {
+        self.interval.is_some() || !self.cron.trim().is_empty()
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Debug, Default, serde::Deserialize)]
 pub struct OutputFormatSuccess {
     pub before: String,
+    /// Written for every title. Accepts `$0` (full title), `$1` (namespace name), `$2` (title without the namespace),
+    /// `$3` (page ID), `$4` (the text the pipe trick `[[$0|]]` would show, e.g. `Mercury` for `Help:Mercury (planet)`),
+    /// `$5` (a link to the page showing `$4`, which never embeds a file or categorizes the list), `$@` (index of the title),
+    /// `$+` (size of the whole list) and `$$` (`$`).
+    /// 
+    /// `$3` is empty for titles that do not exist, such as those from `protectedtitles()`, so they can be told apart with e.g. `{{#if:$3|...|...}}`.
     pub item: String,
     pub between: String,
     pub after: String,
+    /// Written after the items when some are left out because of `maxitems`.
+    /// Accepts `$+` (size of the whole list), `$-` (number of items left out) and `$$` (`$`).
+    #[serde(default)]
+    pub more: String,
+}
+
+/// How the result list is written.
+/// 
+/// `Wikitext`: every title is substituted into the `success` templates.
+/// 
+/// `Json`: the result is written as a JSON object `{"total": ..., "pages": [{"title": ..., "ns": ...}, ...]}`, and the `success` and `empty` templates are not used.
+/// 
+/// `Table`: the result is written as a sortable wikitable with the given `columns`, one row per title, between the `before` and `after` templates of `success`.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputKind {
+    #[default]
+    Wikitext,
+    Json,
+    Table,
+}
+
+/// The order in which titles are written.
+/// 
+/// `Namespace`: by namespace, then by title. This is the order the query executor returns.
+/// 
+/// `Title`: by full title, including the namespace prefix.
+/// 
+/// `Natural`: by full title, but runs of digits are compared by their value, so that `List 2` comes before `List 10`.
+/// 
+/// `Collation`: by full title, in the order of the site's content language, e.g. `Ärlig` after `Zebra` on a Swedish wiki, and by pinyin on a Chinese wiki.
+/// Languages without rules of their own use the default Unicode order, which still sorts accented letters with their base letter.
+/// 
+/// `PageId`: by page ID, i.e. roughly by creation time. Pages without an ID, such as missing pages, come last, by full title.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputSort {
+    #[default]
+    Namespace,
+    Title,
+    Natural,
+    Collation,
+    PageId,
+}
+
+/// Grouping of a wikitext output by namespace.
+/// Each group starts with `header`, followed by its items joined by `between`.
+/// 
+/// `header` accepts `$1` (namespace name, empty for the main namespace), `$#` (namespace ID), `$+` (size of the group) and `$$` (`$`).
+/// 
+/// `restartindex`: whether `$@` in items restarts from 1 in each group, instead of counting through the whole list.
+#[derive(PartialEq, Eq, Clone, Debug, serde::Deserialize)]
+pub struct OutputGroup {
+    pub header: String,
+    #[serde(default)]
+    pub restartindex: bool,
+}
+
+/// Laying out the items of a wikitext output in columns.
+/// The items are wrapped in `before` and `after`, e.g. `{{div col|colwidth=20em}}` and `{{div col end}}`, leaving the columns to the wiki.
+/// With `count`, the items are split into `count` columns of nearly equal size instead, each wrapped in `before` and `after`,
+/// and the columns are joined by `between`. In a grouped output, each group is laid out by itself.
+/// 
+/// `before`, `between` and `after` accept what `before` of `success` does.
+#[derive(PartialEq, Eq, Clone, Debug, serde::Deserialize)]
+pub struct OutputMultiColumn {
+    pub before: String,
+    pub after: String,
+    #[serde(default)]
+    pub between: String,
+    pub count: Option<usize>,
+}
+
+/// Splitting of a long wikitext output across numbered subpages `Target/1`, `Target/2`, and so on.
+/// A new subpage is started once the current one would have more than `maxitems` items or more than `maxbytes` bytes of items.
+/// The target page itself then lists the subpages, each rendered with `index`,
+/// which accepts `$0` (full name of the subpage), `$@` (subpage number), `$+` (number of subpages) and `$$` (`$`).
+/// 
+/// The `maxitems` of the output format still applies, and the items left out are mentioned with `more` at the end of the last subpage.
+/// 
+/// The target page keeps a hidden note of the number of subpages in use.
+/// Subpages left over from a longer earlier result are turned into redirects to the target page, and are not looked at again.
+/// Like the target page, the numbered subpages are never listed in the result of the task.
+#[derive(PartialEq, Eq, Clone, Debug, serde::Deserialize)]
+pub struct OutputPagination {
+    pub maxitems: Option<usize>,
+    pub maxbytes: Option<usize>,
+    pub index: String,
 }
 
+/// The edit summary of a task.
+/// `template` accepts `$+` (number of results, 0 on failure), `$?` (status word) and `$$` (`$`).
+/// 
+/// `status` maps the status of the query, one of `success`, `timeout`, `parse`, `runtime` and `cancelled`, to the word put in place of `$?`.
+/// Statuses not in the map are written as they are.
+#[derive(PartialEq, Eq, Clone, Debug, serde::Deserialize)]
+pub struct SummaryTemplate {
+    pub template: String,
+    #[serde(default)]
+    pub status: HashMap<String, String>,
+}
+
+/// A column of a table output.
+/// `header` is substituted like `before`, and `cell` like `item`.
+#[derive(PartialEq, Eq, Clone, Debug, serde::Deserialize)]
+pub struct OutputColumn {
+    pub header: String,
+    pub cell: String,
+}
+
+/// Prefix of a target written to a local file in the output directory, instead of a wiki page.
+pub const FILE_TARGET_PREFIX: &str = "file://";
+
 #[derive(PartialEq, Eq, Clone, Debug, serde::Deserialize)]
 pub struct OutputFormat {
+    /// The page to write, or `file://` followed by a path relative to the output directory to write a local file instead.
+    /// A local file is written as is, without `pagination`.
     pub target: String,
+    #[serde(default)]
+    pub kind: OutputKind,
+    #[serde(default)]
+    pub sort: OutputSort,
+    /// Write the titles in the reverse of `sort`. The list is reversed before `maxitems` and `pagination` apply,
+    /// so with `sort` set to `pageid`, `maxitems` keeps the newest pages, newest first.
+    /// Groups stay in namespace order, but the titles within each group are reversed.
+    #[serde(default)]
+    pub reverse: bool,
     pub failure: String,
     pub empty: String,
+    #[serde(default)]
     pub success: OutputFormatSuccess,
+    #[serde(default)]
+    pub columns: Vec<OutputColumn>,
+    pub group: Option<OutputGroup>,
+    pub multicolumn: Option<OutputMultiColumn>,
+    pub pagination: Option<OutputPagination>,
+    /// Only write the first items of a wikitext or table output, followed by `more` of `success`.
+    /// With `pagination`, only these items are split across subpages.
+    pub maxitems: Option<usize>,
+    /// Only replace the text between `<!-- BOT LIST START -->` and `<!-- BOT LIST END -->` on the target page,
+    /// leaving the rest of the page to human editors. The markers are appended to the page if neither is there.
+    #[serde(default)]
+    pub section: bool,
 }
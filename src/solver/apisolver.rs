@@ -1,11 +1,14 @@
 //! This module performs actions using MediaWiki API
 //! 
 
-use super::{util, error::SolveError};
-use std::collections::{HashSet, VecDeque};
+use super::{util, template, error::SolveError, source::{PageInfo, PageProtection, FileMedia}};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Mutex;
+use futures::{stream, StreamExt};
 use mediawiki::{api::NamespaceID, title::Title, hashmap};
-use crate::API_SERVICE;
-use crate::parser::ir::{DepthNum, RedirectFilterStrategy};
+use tokio_util::sync::CancellationToken;
+use crate::apiservice::{APIService, APIServiceError, json_merge, query_result_count, continue_params};
+use crate::parser::ir::{DepthNum, RedirectFilterStrategy, ProtectionFilter, SearchWhat, CategorySort, CategorySortKey, SortDirection, RecentChangeType, Timestamp};
 
 fn limit_to_max(limit: i64) -> Option<usize> {
     if limit < 0 {
@@ -15,12 +18,95 @@ fn limit_to_max(limit: i64) -> Option<usize> {
     }
 }
 
-async fn pages_object_to_titles_set(data: &serde_json::Value, redirected: bool, redirect_filter: RedirectFilterStrategy) -> HashSet<Title> {
+/// Reads the `from`/`to` pairs of a `normalized` or `converted` list in a response to `titles=`.
+fn title_mapping<'a>(res: &'a serde_json::Value, key: &str) -> HashMap<&'a str, &'a str> {
+    res["query"][key].as_array().into_iter().flatten()
+        .filter_map(|n| Some((n["from"].as_str()?, n["to"].as_str()?)))
+        .collect()
+}
+
+/// Maps each name sent in `titles=` to the name the API lists the page under.
+/// The API first normalizes a name, e.g. `foo_bar` to `Foo bar`, and then, with `converttitles=1` on a wiki with language variants,
+/// converts it to the variant the page exists under, e.g. `计算机` to `計算機`. Names the API left as they are are not in the map.
+fn canonical_names(res: &serde_json::Value) -> HashMap<&str, &str> {
+    let normalized = title_mapping(res, "normalized");
+    let converted = title_mapping(res, "converted");
+    let mut canonical: HashMap<&str, &str> = normalized.iter()
+        .map(|(&from, &to)| (from, converted.get(to).copied().unwrap_or(to)))
+        .collect();
+    for (from, to) in converted {
+        canonical.entry(from).or_insert(to);
+    }
+    canonical
+}
+
+/// Failures of continued queries that ended early, keeping what was gathered before the failure.
+#[derive(Debug, Default)]
+pub(crate) struct Incomplete(Mutex<Vec<APIServiceError>>);
+
+impl Incomplete {
+    /// Take the failures recorded so far.
+    pub fn take(&self) -> Vec<APIServiceError> {
+        std::mem::take(&mut *self.0.lock().unwrap())
+    }
+
+    fn record(&self, e: APIServiceError) {
+        self.0.lock().unwrap().push(e);
+    }
+}
+
+/// Sends a query, and follows its continuation until `limit` results are gathered.
+/// 
+/// Without `partial`, `APIService::get_limit` follows the continuation, and a failure of any continued request fails the whole query.
+/// With `partial`, each continued request is sent and retried by itself. One that still fails ends the query early with what was gathered so far,
+/// and the failure is recorded in `partial`, so the results may be incomplete. A failure of the first request still fails the query, as there is nothing to keep.
+async fn get_continued(api: &APIService, params: &HashMap<String, String>, limit: i64, partial: Option<&Incomplete>) -> Result<serde_json::Value, SolveError> {
+    let partial = match partial {
+        Some(partial) => partial,
+        None => return Ok(util::retry(util::API_RETRY, || api.get_limit(params, limit_to_max(limit))).await?),
+    };
+    let max = limit_to_max(limit);
+    let mut acc = serde_json::json!({});
+    let mut cont: HashMap<String, String> = HashMap::new();
+    let mut first = true;
+    loop {
+        let mut cont_params = params.clone();
+        cont_params.extend(cont.drain());
+        let res = match util::retry(util::API_RETRY, || api.get(&cont_params)).await {
+            Ok(res) => res,
+            Err(e) if first => return Err(e.into()),
+            Err(e) => {
+                partial.record(e);
+                break;
+            },
+        };
+        first = false;
+        cont = continue_params(&res);
+        json_merge(&mut acc, res);
+        if cont.is_empty() || max.map(|max| query_result_count(&acc) >= max).unwrap_or(false) {
+            break;
+        }
+    }
+    if let Some(acc) = acc.as_object_mut() {
+        acc.remove("continue");
+    }
+    Ok(acc)
+}
+
+/// Collects the titles from the `query` object of a generator response.
+/// 
+/// Every query here goes through a generator, whose `pages` list is always flat: pages reached through a redirect
+/// (e.g. `gblredirect` for backlinks) are listed alongside the others, rather than nested under the redirect as
+/// `list=backlinks&blredirect` would do. So no nested redirect titles can be dropped here.
+/// 
+/// `redirected`: whether the query was sent with `redirects=1`. If so, the `redirects` list holds the redirects that were resolved,
+/// and `redirect_filter` decides whether they, the resolved pages, or both are returned.
+async fn pages_object_to_titles_set(api: &APIService, data: &serde_json::Value, redirected: bool, redirect_filter: RedirectFilterStrategy) -> HashSet<Title> {
     if let Some(obj) = data.as_object() {
         let mut redirects: HashSet<Title> = HashSet::new();
         if let Some(redirs) = obj.get("redirects") {
             for itm in redirs.as_array().unwrap().iter() {
-                redirects.insert(API_SERVICE.title_new_from_full(itm["from"].as_str().unwrap()).await.unwrap());
+                redirects.insert(api.title_new_from_full(itm["from"].as_str().unwrap()).await.unwrap());
             }
         }
         let mut pages: HashSet<Title> = HashSet::new();
@@ -64,8 +150,8 @@ async fn pages_object_to_titles_set(data: &serde_json::Value, redirected: bool,
 /// 
 /// `limit`: Query limit.
 #[allow(clippy::too_many_arguments)]
-pub(crate) async fn get_backlinks_one(title: &Title, ns: Option<&HashSet<NamespaceID>>, level_2: bool, redirect_strat: RedirectFilterStrategy, follow_redir: bool, limit: i64) -> Result<HashSet<Title>, SolveError> {
-    let elem_name = API_SERVICE.full_pretty(title).await?;
+pub(crate) async fn get_backlinks_one(api: &APIService, title: &Title, ns: Option<&HashSet<NamespaceID>>, level_2: bool, redirect_strat: RedirectFilterStrategy, follow_redir: bool, limit: i64, partial: Option<&Incomplete>) -> Result<HashSet<Title>, SolveError> {
+    let elem_name = api.full_pretty(title).await?;
     if elem_name.is_none() {
         Ok(HashSet::new())
     } else {
@@ -93,8 +179,8 @@ pub(crate) async fn get_backlinks_one(title: &Title, ns: Option<&HashSet<Namespa
                 params.insert("gblnamespace".to_string(), util::concat_params(ns_list));
             }
         }
-        let res = API_SERVICE.get_limit(&params, limit_to_max(limit)).await?;
-        let mut title_set = pages_object_to_titles_set(&res["query"], follow_redir, redirect_strat).await;
+        let res = get_continued(api, &params, limit, partial).await?;
+        let mut title_set = pages_object_to_titles_set(api, &res["query"], follow_redir, redirect_strat).await;
         // Need to filter by namespace...
         if level_2 {
             if let Some(ns_list) = ns {
@@ -120,8 +206,16 @@ pub(crate) async fn get_backlinks_one(title: &Title, ns: Option<&HashSet<Namespa
 /// 
 /// `follow_redir`: Whether should follow redirects.
 /// 
+/// `added_after`, `added_before`: Only keep pages added to the category within the time range. Subcategories are still visited regardless of when they were added.
+/// 
+/// `sort`: The order to fetch members in. If set to `None`, the API default is used, which is by sort key ascending, or by timestamp ascending if a time range is given.
+/// As the result is a set, the order only decides which members are kept when the query limit is hit.
+/// 
 /// `limit`: Query limit.
-pub(crate) async fn get_category_members_one(title: &Title, ns: Option<&HashSet<NamespaceID>>, depth: DepthNum, follow_redir: bool, limit: i64) -> Result<HashSet<Title>, SolveError> {
+/// 
+/// `cancel`: Checked before visiting each category. If cancelled, returns `SolveError::Cancelled`.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn get_category_members_one(api: &APIService, title: &Title, ns: Option<&HashSet<NamespaceID>>, depth: DepthNum, follow_redir: bool, added_after: Option<Timestamp>, added_before: Option<Timestamp>, sort: Option<CategorySort>, limit: i64, partial: Option<&Incomplete>, cancel: &CancellationToken) -> Result<HashSet<Title>, SolveError> {
     // Due to miser mode, we need to do some preparations to cs.
     let mut ns_clone = ns.cloned();
     let mut result_has_ns_category: bool = true;
@@ -139,20 +233,43 @@ pub(crate) async fn get_category_members_one(title: &Title, ns: Option<&HashSet<
     visited_cats.insert(title.to_owned());
     let mut visit_cat_queue: VecDeque<(Title, DepthNum)> = VecDeque::new();
     visit_cat_queue.push_back((title.to_owned(), 0));
+    // with a time range, subcategories added outside of it would be missed, so they are fetched in a separate query
+    let timed = added_after.is_some() || added_before.is_some();
     while let Some((this_cat, this_depth)) = visit_cat_queue.pop_front() {
+        // a deep category tree may take long, so also check for cancellation between categories
+        if cancel.is_cancelled() {
+            return Err(SolveError::Cancelled);
+        }
         if this_cat.namespace_id() != super::def::NS_CATEGORY {
             return Err(SolveError::NotCategory);
         }
-        let cat_name = API_SERVICE.full_pretty(&this_cat).await?.unwrap();
+        let cat_name = api.full_pretty(&this_cat).await?.unwrap();
+        let dive = depth < 0 || this_depth < depth;
         let mut params = hashmap![
             "action".to_string() => "query".to_string(),
             "generator".to_string() => "categorymembers".to_string(),
-            "gcmtitle".to_string() => cat_name,
+            "gcmtitle".to_string() => cat_name.clone(),
             "gcmlimit".to_string() => "max".to_string()
         ];
         if follow_redir {
             params.insert("redirects".to_string(), "1".to_string());
         }
+        if timed {
+            // only sorting by timestamp allows a time range, and the range goes from start to end in the sort direction
+            let desc = sort.map(|s| s.dir == SortDirection::Desc).unwrap_or(false);
+            let (start, end) = if desc { (added_before, added_after) } else { (added_after, added_before) };
+            params.insert("gcmsort".to_string(), CategorySortKey::Timestamp.to_string());
+            params.insert("gcmdir".to_string(), if desc { "older" } else { "newer" }.to_string());
+            if let Some(t) = start {
+                params.insert("gcmstart".to_string(), t.to_rfc3339_opts(chrono::SecondsFormat::Secs, true));
+            }
+            if let Some(t) = end {
+                params.insert("gcmend".to_string(), t.to_rfc3339_opts(chrono::SecondsFormat::Secs, true));
+            }
+        } else if let Some(sort) = sort {
+            params.insert("gcmsort".to_string(), sort.key.to_string());
+            params.insert("gcmdir".to_string(), sort.dir.to_string());
+        }
         // determine what cmtype and cmnamespace should we insert
         let mut cmtype: Vec<String> = Vec::new();
         let mut cmnamespace: HashSet<NamespaceID> = HashSet::new();
@@ -170,7 +287,7 @@ pub(crate) async fn get_category_members_one(title: &Title, ns: Option<&HashSet<
             cmnamespace.insert(super::def::NS_FILE);
         }
         // If we still need to find subcats, or `result_has_ns_category`...
-        if result_has_ns_category || (depth < 0 || this_depth < depth) {
+        if result_has_ns_category || (dive && !timed) {
             cmtype.push("subcat".to_string());
             cmnamespace.insert(super::def::NS_CATEGORY);
         }
@@ -179,14 +296,28 @@ pub(crate) async fn get_category_members_one(title: &Title, ns: Option<&HashSet<
         }
         params.insert("gcmtype".to_string(), cmtype.join("|"));
         // fetch results
-        let res = API_SERVICE.get_limit(&params, limit_to_max(limit)).await?;
-        let mut title_set_2 = pages_object_to_titles_set(&res["query"], follow_redir, RedirectFilterStrategy::NoRedirect).await;
-        if depth < 0 || this_depth < depth {
+        let res = get_continued(api, &params, limit, partial).await?;
+        let mut title_set_2 = pages_object_to_titles_set(api, &res["query"], follow_redir, RedirectFilterStrategy::NoRedirect).await;
+        if dive {
             // filter out subcategories from title_vec, and add to visit queue
-            for sub in title_set_2.iter().filter(|&t| t.namespace_id() == super::def::NS_CATEGORY) {
-                if !visited_cats.contains(sub) {
+            let subcats: HashSet<Title> = if timed {
+                let sub_params = hashmap![
+                    "action".to_string() => "query".to_string(),
+                    "generator".to_string() => "categorymembers".to_string(),
+                    "gcmtitle".to_string() => cat_name,
+                    "gcmlimit".to_string() => "max".to_string(),
+                    "gcmtype".to_string() => "subcat".to_string(),
+                    "gcmnamespace".to_string() => super::def::NS_CATEGORY.to_string()
+                ];
+                let sub_res = get_continued(api, &sub_params, -1, partial).await?;
+                pages_object_to_titles_set(api, &sub_res["query"], false, RedirectFilterStrategy::NoRedirect).await
+            } else {
+                title_set_2.iter().filter(|&t| t.namespace_id() == super::def::NS_CATEGORY).cloned().collect()
+            };
+            for sub in subcats {
+                if !visited_cats.contains(&sub) {
                     visited_cats.insert(sub.to_owned());
-                    visit_cat_queue.push_back((sub.to_owned(), this_depth + 1));
+                    visit_cat_queue.push_back((sub, this_depth + 1));
                 }
             }
         }
@@ -216,15 +347,19 @@ pub(crate) async fn get_category_members_one(title: &Title, ns: Option<&HashSet<
 /// 
 /// `redirect_strat`: The redirect strategy to use when querying.
 /// 
+/// `protect`: Protection filter. If set to `None`, then the result is not filtered by protection.
+/// The filter is applied server-side together with `redirect_strat`, so a page must satisfy both to be returned.
+/// For example, `.noredir().protect("edit", "sysop")` yields fully edit-protected non-redirect pages.
+/// 
 /// `limit`: Query limit.
-pub(crate) async fn get_prefix_index_one(title: &Title, ns: Option<&HashSet<NamespaceID>>, redirect_strat: RedirectFilterStrategy, limit: i64) -> Result<HashSet<Title>, SolveError> {
+pub(crate) async fn get_prefix_index_one(api: &APIService, title: &Title, ns: Option<&HashSet<NamespaceID>>, redirect_strat: RedirectFilterStrategy, protect: Option<&ProtectionFilter>, limit: i64, partial: Option<&Incomplete>) -> Result<HashSet<Title>, SolveError> {
     let title_ns_id = title.namespace_id();
     if let Some(ns_list) = ns {
         if !ns_list.contains(&title_ns_id) {
             return Ok(HashSet::new());
         }
     }
-    let params = hashmap![
+    let mut params = hashmap![
         "action".to_string() => "query".to_string(),
         "generator".to_string() => "allpages".to_string(),
         "gapprefix".to_string() => title.pretty().to_string(),
@@ -232,8 +367,53 @@ pub(crate) async fn get_prefix_index_one(title: &Title, ns: Option<&HashSet<Name
         "gaplimit".to_string() => "max".to_string(),
         "gapfilterredir".to_string() => redirect_strat.to_string()
     ];
-    let res = API_SERVICE.get_limit(&params, limit_to_max(limit)).await?;
-    let title_set = pages_object_to_titles_set(&res["query"], false, redirect_strat).await;
+    if let Some(protect) = protect {
+        params.insert("gapprtype".to_string(), protect.prtype.to_string());
+        if let Some(level) = &protect.prlevel {
+            params.insert("gapprlevel".to_string(), level.to_owned());
+        }
+    }
+    let res = get_continued(api, &params, limit, partial).await?;
+    let title_set = pages_object_to_titles_set(api, &res["query"], false, redirect_strat).await;
+    Ok(title_set)
+}
+
+/// Retrives every page in a namespace. That is how [[Special:AllPages]] works.
+/// 
+/// `ns`: The namespace to enumerate.
+/// 
+/// `redirect_strat`: The redirect strategy to use when querying.
+/// 
+/// `protect`: Protection filter. If set to `None`, then the result is not filtered by protection.
+/// 
+/// `min_size`, `max_size`: Page size bounds in bytes, both inclusive. If set to `None`, then the result is not bounded on that side.
+/// 
+/// As with `get_prefix_index_one`, redirects cannot be resolved in the same request, resolve them with `resolve_redirects` afterwards.
+/// 
+/// `limit`: Query limit.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn get_all_pages(api: &APIService, ns: NamespaceID, redirect_strat: RedirectFilterStrategy, protect: Option<&ProtectionFilter>, min_size: Option<u64>, max_size: Option<u64>, limit: i64, partial: Option<&Incomplete>) -> Result<HashSet<Title>, SolveError> {
+    let mut params = hashmap![
+        "action".to_string() => "query".to_string(),
+        "generator".to_string() => "allpages".to_string(),
+        "gapnamespace".to_string() => ns.to_string(),
+        "gaplimit".to_string() => "max".to_string(),
+        "gapfilterredir".to_string() => redirect_strat.to_string()
+    ];
+    if let Some(protect) = protect {
+        params.insert("gapprtype".to_string(), protect.prtype.to_string());
+        if let Some(level) = &protect.prlevel {
+            params.insert("gapprlevel".to_string(), level.to_owned());
+        }
+    }
+    if let Some(min_size) = min_size {
+        params.insert("gapminsize".to_string(), min_size.to_string());
+    }
+    if let Some(max_size) = max_size {
+        params.insert("gapmaxsize".to_string(), max_size.to_string());
+    }
+    let res = get_continued(api, &params, limit, partial).await?;
+    let title_set = pages_object_to_titles_set(api, &res["query"], false, redirect_strat).await;
     Ok(title_set)
 }
 
@@ -254,8 +434,8 @@ pub(crate) async fn get_prefix_index_one(title: &Title, ns: Option<&HashSet<Name
 /// `follow_redir`: Whether should follow redirects.
 /// 
 /// `limit`: Query limit.
-pub(crate) async fn get_embed_one(title: &Title, ns: Option<&HashSet<NamespaceID>>, redirect_strat: RedirectFilterStrategy, follow_redir: bool, limit: i64) -> Result<HashSet<Title>, SolveError> {
-    let elem_name = API_SERVICE.full_pretty(title).await?;
+pub(crate) async fn get_embed_one(api: &APIService, title: &Title, ns: Option<&HashSet<NamespaceID>>, redirect_strat: RedirectFilterStrategy, follow_redir: bool, limit: i64, partial: Option<&Incomplete>) -> Result<HashSet<Title>, SolveError> {
+    let elem_name = api.full_pretty(title).await?;
     if elem_name.is_none() {
         Ok(HashSet::new())
     } else {
@@ -272,9 +452,147 @@ pub(crate) async fn get_embed_one(title: &Title, ns: Option<&HashSet<NamespaceID
         if follow_redir {
             params.insert("redirects".to_string(), "1".to_string());
         }
-        let res = API_SERVICE.get_limit(&params, limit_to_max(limit)).await?;
-        let title_set = pages_object_to_titles_set(&res["query"], follow_redir, redirect_strat).await;
+        let res = get_continued(api, &params, limit, partial).await?;
+        let title_set = pages_object_to_titles_set(api, &res["query"], follow_redir, redirect_strat).await;
+        Ok(title_set)
+    }
+}
+
+/// Retrives the pages embedding a template without a value for one of its parameters.
+/// 
+/// The embedders are listed as in `get_embed_one`, and then their wikitext is fetched and scanned for calls of the template, or of a redirect to it.
+/// A page is kept if any of these calls leaves the parameter out, or passes an empty value.
+/// Pages that only embed the template through another template have no such call, so they are never kept.
+/// 
+/// This is expensive: the wikitext of every embedder is downloaded, see `filter_by_content`.
+/// 
+/// `template`: The title of the template.
+/// 
+/// `param`: The name of the parameter, or the position of an unnamed one, e.g. `1`.
+/// 
+/// `ns`: Namespace filter. If set to `None`, then the result is not filtered by namespace.
+/// 
+/// `limit`: Query limit. Note that it counts the embedders scanned, not the pages kept.
+pub(crate) async fn get_embed_with_param_check(api: &APIService, template: &Title, param: &str, ns: Option<&HashSet<NamespaceID>>, limit: i64, partial: Option<&Incomplete>) -> Result<HashSet<Title>, SolveError> {
+    let embedders = get_embed_one(api, template, ns, RedirectFilterStrategy::NoRedirect, false, limit, partial).await?;
+    if embedders.is_empty() {
+        return Ok(HashSet::new());
+    }
+    // a call may use the template under any of its names
+    let mut names: HashSet<String> = HashSet::new();
+    let aliases = get_redirects_one(api, template, Some(&HashSet::from([template.namespace_id()])), -1, partial).await?;
+    for t in std::iter::once(template).chain(aliases.iter()) {
+        if let Some(name) = api.full_pretty(t).await? {
+            names.insert(template::normalize_name(&name));
+        }
+        // the namespace can be left out for templates, and their canonical name is always understood
+        if t.namespace_id() == super::def::NS_TEMPLATE {
+            names.insert(template::normalize_name(t.pretty()));
+            names.insert(template::normalize_name(&format!("Template:{}", t.pretty())));
+        }
+    }
+    let embedders: Vec<Title> = embedders.into_iter().collect();
+    filter_by_content(api, &embedders, &|text| template::lacks_param(text, &names, param)).await
+}
+
+/// Retrives the pages transcluded by a page, usually templates.
+/// If the page does not exist, an empty set is returned.
+/// 
+/// `title`: The title of the page.
+/// 
+/// `ns`: Namespace filter. If set to `None`, then the result is not filtered by namespace.
+/// 
+/// `follow_redir`: Whether should follow redirects.
+/// 
+/// `limit`: Query limit
+pub(crate) async fn get_templates_one(api: &APIService, title: &Title, ns: Option<&HashSet<NamespaceID>>, follow_redir: bool, limit: i64, partial: Option<&Incomplete>) -> Result<HashSet<Title>, SolveError> {
+    let elem_name = api.full_pretty(title).await?;
+    if let Some(elem_name) = elem_name {
+        let mut params = hashmap![
+            "action".to_string() => "query".to_string(),
+            "generator".to_string() => "templates".to_string(),
+            "titles".to_string() => elem_name,
+            "gtllimit".to_string() => "max".to_string()
+        ];
+        if let Some(ns_list) = ns {
+            params.insert("gtlnamespace".to_string(), util::concat_params(ns_list));
+        }
+        if follow_redir {
+            params.insert("redirects".to_string(), "1".to_string());
+        }
+        let res = get_continued(api, &params, limit, partial).await?;
+        let title_set = pages_object_to_titles_set(api, &res["query"], follow_redir, RedirectFilterStrategy::NoRedirect).await;
         Ok(title_set)
+    } else {
+        Ok(HashSet::new())
+    }
+}
+
+/// Retrives the pages that use a file.
+/// Only files can be used, so if `title` is not in the File namespace, an empty set is returned.
+/// 
+/// `title`: The title of the file.
+/// 
+/// `ns`: Namespace filter. If set to `None`, then the result is not filtered by namespace.
+/// 
+/// `redirect_strat`: The redirect strategy to use when querying.
+/// 
+/// `follow_redir`: Whether should follow redirects.
+/// 
+/// `limit`: Query limit.
+pub(crate) async fn get_image_usage_one(api: &APIService, title: &Title, ns: Option<&HashSet<NamespaceID>>, redirect_strat: RedirectFilterStrategy, follow_redir: bool, limit: i64, partial: Option<&Incomplete>) -> Result<HashSet<Title>, SolveError> {
+    if title.namespace_id() != super::def::NS_FILE {
+        return Ok(HashSet::new());
+    }
+    let elem_name = api.full_pretty(title).await?;
+    if let Some(elem_name) = elem_name {
+        let mut params = hashmap![
+            "action".to_string() => "query".to_string(),
+            "generator".to_string() => "imageusage".to_string(),
+            "giutitle".to_string() => elem_name,
+            "giulimit".to_string() => "max".to_string(),
+            "giufilterredir".to_string() => redirect_strat.to_string()
+        ];
+        if let Some(ns_list) = ns {
+            params.insert("giunamespace".to_string(), util::concat_params(ns_list));
+        }
+        if follow_redir {
+            params.insert("redirects".to_string(), "1".to_string());
+        }
+        let res = get_continued(api, &params, limit, partial).await?;
+        let title_set = pages_object_to_titles_set(api, &res["query"], follow_redir, redirect_strat).await;
+        Ok(title_set)
+    } else {
+        Ok(HashSet::new())
+    }
+}
+
+/// Retrives the redirect pages that point to a page.
+/// Unlike `get_backlinks_one`, this returns the redirects themselves, not the pages linking through them.
+/// If the page does not exist, an empty set is returned.
+/// 
+/// `title`: The title of the page.
+/// 
+/// `ns`: Namespace filter. If set to `None`, then the result is not filtered by namespace.
+/// 
+/// `limit`: Query limit.
+pub(crate) async fn get_redirects_one(api: &APIService, title: &Title, ns: Option<&HashSet<NamespaceID>>, limit: i64, partial: Option<&Incomplete>) -> Result<HashSet<Title>, SolveError> {
+    let elem_name = api.full_pretty(title).await?;
+    if let Some(elem_name) = elem_name {
+        let mut params = hashmap![
+            "action".to_string() => "query".to_string(),
+            "generator".to_string() => "redirects".to_string(),
+            "titles".to_string() => elem_name,
+            "grdlimit".to_string() => "max".to_string()
+        ];
+        if let Some(ns_list) = ns {
+            params.insert("grdnamespace".to_string(), util::concat_params(ns_list));
+        }
+        let res = get_continued(api, &params, limit, partial).await?;
+        let title_set = pages_object_to_titles_set(api, &res["query"], false, RedirectFilterStrategy::All).await;
+        Ok(title_set)
+    } else {
+        Ok(HashSet::new())
     }
 }
 
@@ -291,8 +609,8 @@ pub(crate) async fn get_embed_one(title: &Title, ns: Option<&HashSet<NamespaceID
 /// `follow_redir`: Whether should follow redirects.
 /// 
 /// `limit`: Query limit
-pub(crate) async fn get_links_one(title: &Title, ns: Option<&HashSet<NamespaceID>>, follow_redir: bool, limit: i64) -> Result<HashSet<Title>, SolveError> {
-    let elem_name = API_SERVICE.full_pretty(title).await?;
+pub(crate) async fn get_links_one(api: &APIService, title: &Title, ns: Option<&HashSet<NamespaceID>>, follow_redir: bool, limit: i64, partial: Option<&Incomplete>) -> Result<HashSet<Title>, SolveError> {
+    let elem_name = api.full_pretty(title).await?;
     if elem_name.is_none() {
         Ok(HashSet::new())
     } else {
@@ -308,9 +626,584 @@ pub(crate) async fn get_links_one(title: &Title, ns: Option<&HashSet<NamespaceID
         if follow_redir {
             params.insert("redirects".to_string(), "1".to_string());
         }
-        let res = API_SERVICE.get_limit(&params, limit_to_max(limit)).await?;
-        let title_vec = pages_object_to_titles_set(&res["query"], follow_redir, RedirectFilterStrategy::NoRedirect).await;
+        let res = get_continued(api, &params, limit, partial).await?;
+        let title_vec = pages_object_to_titles_set(api, &res["query"], follow_redir, RedirectFilterStrategy::NoRedirect).await;
         let title_set = HashSet::from_iter(title_vec.into_iter());
         Ok(title_set)
     }
 }
+
+/// Retrives the pages matching a search.
+/// 
+/// `query`: The search string. On sites with CirrusSearch, keywords such as `insource:` can be used.
+/// 
+/// `ns`: Namespace filter. If set to `None`, then only the main namespace is searched, the default of MediaWiki API.
+/// 
+/// `what`: Whether to search in page text or page titles.
+/// 
+/// `follow_redir`: Whether should follow redirects.
+/// 
+/// `limit`: Query limit.
+pub(crate) async fn get_search(api: &APIService, query: &str, ns: Option<&HashSet<NamespaceID>>, what: SearchWhat, follow_redir: bool, limit: i64, partial: Option<&Incomplete>) -> Result<HashSet<Title>, SolveError> {
+    let mut params = hashmap![
+        "action".to_string() => "query".to_string(),
+        "generator".to_string() => "search".to_string(),
+        "gsrsearch".to_string() => query.to_string(),
+        "gsrwhat".to_string() => what.to_string(),
+        "gsrlimit".to_string() => "max".to_string()
+    ];
+    if let Some(ns_list) = ns {
+        params.insert("gsrnamespace".to_string(), util::concat_params(ns_list));
+    }
+    if follow_redir {
+        params.insert("redirects".to_string(), "1".to_string());
+    }
+    let res = get_continued(api, &params, limit, partial).await?;
+    let title_set = pages_object_to_titles_set(api, &res["query"], follow_redir, RedirectFilterStrategy::NoRedirect).await;
+    Ok(title_set)
+}
+
+/// Retrives the pages with an external link to a URL. That is how [[Special:LinkSearch]] works.
+/// 
+/// `url`: The URL, taken literally except that `*.` may start the domain to match its subdomains, e.g. `*.example.org/path`.
+/// If it has no protocol, links with both `http://` and `https://` are looked for, and the query limit applies to each of them.
+/// 
+/// `ns`: Namespace filter. If set to `None`, then the result is not filtered by namespace.
+/// 
+/// `follow_redir`: Whether should follow redirects.
+/// 
+/// `limit`: Query limit.
+pub(crate) async fn get_external_link_usage(api: &APIService, url: &str, ns: Option<&HashSet<NamespaceID>>, follow_redir: bool, limit: i64, partial: Option<&Incomplete>) -> Result<HashSet<Title>, SolveError> {
+    // the API takes the protocol apart, and defaults to `http`
+    let queries: Vec<(&str, &str)> = match url.split_once("://") {
+        Some((protocol, rest)) => vec![(protocol, rest)],
+        None => vec![("http", url), ("https", url)],
+    };
+    let mut title_set: HashSet<Title> = HashSet::new();
+    for (protocol, query) in queries {
+        let mut params = hashmap![
+            "action".to_string() => "query".to_string(),
+            "generator".to_string() => "exturlusage".to_string(),
+            "geuprotocol".to_string() => protocol.to_string(),
+            "geuquery".to_string() => query.to_string(),
+            "geulimit".to_string() => "max".to_string()
+        ];
+        if let Some(ns_list) = ns {
+            params.insert("geunamespace".to_string(), util::concat_params(ns_list));
+        }
+        if follow_redir {
+            params.insert("redirects".to_string(), "1".to_string());
+        }
+        let res = get_continued(api, &params, limit, partial).await?;
+        title_set.extend(pages_object_to_titles_set(api, &res["query"], follow_redir, RedirectFilterStrategy::NoRedirect).await);
+    }
+    Ok(title_set)
+}
+
+/// Retrives the pages with a given page property, such as `disambiguation` or `noindex`.
+/// 
+/// `prop`: The name of the page property.
+/// 
+/// `ns`: Namespace filter. If set to `None`, then the result is not filtered by namespace.
+/// The API cannot filter by namespace, so it is applied after fetching. Note that this also means the query limit counts pages in every namespace.
+/// 
+/// `follow_redir`: Whether should follow redirects.
+/// 
+/// `limit`: Query limit.
+pub(crate) async fn get_pages_with_prop(api: &APIService, prop: &str, ns: Option<&HashSet<NamespaceID>>, follow_redir: bool, limit: i64, partial: Option<&Incomplete>) -> Result<HashSet<Title>, SolveError> {
+    let mut params = hashmap![
+        "action".to_string() => "query".to_string(),
+        "generator".to_string() => "pageswithprop".to_string(),
+        "gpwppropname".to_string() => prop.to_string(),
+        "gpwplimit".to_string() => "max".to_string()
+    ];
+    if follow_redir {
+        params.insert("redirects".to_string(), "1".to_string());
+    }
+    let res = get_continued(api, &params, limit, partial).await?;
+    let mut title_set = pages_object_to_titles_set(api, &res["query"], follow_redir, RedirectFilterStrategy::NoRedirect).await;
+    if let Some(ns_list) = ns {
+        title_set.retain(|t| ns_list.contains(&t.namespace_id()));
+    }
+    Ok(title_set)
+}
+
+/// Retrives the pages a user has edited. That is how [[Special:Contributions]] works.
+/// 
+/// `user`: The user name, without the `User:` prefix.
+/// 
+/// `ns`: Namespace filter. If set to `None`, then the result is not filtered by namespace.
+/// 
+/// `created`: Whether to only keep the pages the user has created.
+/// 
+/// `follow_redir`: Whether should follow redirects.
+/// 
+/// `limit`: Query limit. Note that it counts edits rather than pages, so a page edited many times takes up as many.
+pub(crate) async fn get_user_contributions(api: &APIService, user: &str, ns: Option<&HashSet<NamespaceID>>, created: bool, follow_redir: bool, limit: i64, partial: Option<&Incomplete>) -> Result<HashSet<Title>, SolveError> {
+    // there is no generator for contributions, so the titles are collected from the list
+    let mut params = hashmap![
+        "action".to_string() => "query".to_string(),
+        "list".to_string() => "usercontribs".to_string(),
+        "ucuser".to_string() => user.to_string(),
+        "ucprop".to_string() => "title".to_string(),
+        "uclimit".to_string() => "max".to_string()
+    ];
+    if let Some(ns_list) = ns {
+        params.insert("ucnamespace".to_string(), util::concat_params(ns_list));
+    }
+    if created {
+        params.insert("ucshow".to_string(), "new".to_string());
+    }
+    let res = get_continued(api, &params, limit, partial).await?;
+    let mut title_set: HashSet<Title> = HashSet::new();
+    if let Some(contribs) = res["query"]["usercontribs"].as_array() {
+        for contrib in contribs {
+            title_set.insert(Title::new_from_api_result(contrib));
+        }
+    }
+    if follow_redir {
+        title_set = resolve_redirects(api, &title_set).await?;
+    }
+    Ok(title_set)
+}
+
+/// How long recent changes are kept by default (`$wgRCMaxAge`), in hours. Changes older than this are not listed.
+pub(crate) const RECENT_CHANGES_MAX_AGE: u64 = 90 * 24;
+
+/// Retrives the pages changed recently. That is how [[Special:RecentChanges]] works.
+/// 
+/// `hours`: How far to look back from now. Recent changes are only kept for a while, usually 90 days, so a longer window is cut short.
+/// 
+/// `kind`: The type of changes to look at. If set to `None`, edits, page creations and log entries are all counted.
+/// 
+/// `ns`: Namespace filter. If set to `None`, then the result is not filtered by namespace.
+/// 
+/// `redirect_strat`: Whether to keep redirect pages, judged by whether the page is a redirect now.
+/// 
+/// `follow_redir`: Whether should follow redirects.
+/// 
+/// `limit`: Query limit. Note that it counts changes rather than pages, so a page changed many times takes up as many.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn get_recent_changes(api: &APIService, hours: u64, kind: Option<RecentChangeType>, ns: Option<&HashSet<NamespaceID>>, redirect_strat: RedirectFilterStrategy, follow_redir: bool, limit: i64, partial: Option<&Incomplete>) -> Result<HashSet<Title>, SolveError> {
+    let since = chrono::Utc::now() - chrono::Duration::hours(hours.min(i64::MAX as u64) as i64);
+    // there is no generator for recent changes, so the titles are collected from the list
+    // changes are listed from the newest, so the oldest one to list is `rcend`
+    let mut params = hashmap![
+        "action".to_string() => "query".to_string(),
+        "list".to_string() => "recentchanges".to_string(),
+        "rcend".to_string() => since.to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        "rctype".to_string() => kind.map(|k| k.to_string()).unwrap_or_else(|| "edit|new|log".to_string()),
+        "rcprop".to_string() => "title".to_string(),
+        "rclimit".to_string() => "max".to_string()
+    ];
+    if let Some(ns_list) = ns {
+        params.insert("rcnamespace".to_string(), util::concat_params(ns_list));
+    }
+    match redirect_strat {
+        RedirectFilterStrategy::NoRedirect => { params.insert("rcshow".to_string(), "!redirect".to_string()); },
+        RedirectFilterStrategy::OnlyRedirect => { params.insert("rcshow".to_string(), "redirect".to_string()); },
+        RedirectFilterStrategy::All => (),
+    }
+    let res = get_continued(api, &params, limit, partial).await?;
+    let mut title_set: HashSet<Title> = HashSet::new();
+    if let Some(changes) = res["query"]["recentchanges"].as_array() {
+        for change in changes {
+            title_set.insert(Title::new_from_api_result(change));
+        }
+    }
+    if follow_redir {
+        title_set = resolve_redirects(api, &title_set).await?;
+    }
+    Ok(title_set)
+}
+
+/// Retrives the redirects whose target is a redirect too. That is how [[Special:DoubleRedirects]] works.
+/// 
+/// `ns`: Namespace filter. If set to `None`, then the result is not filtered by namespace.
+/// 
+/// `limit`: Query limit.
+pub(crate) async fn get_double_redirects(api: &APIService, ns: Option<&HashSet<NamespaceID>>, limit: i64, partial: Option<&Incomplete>) -> Result<HashSet<Title>, SolveError> {
+    get_query_page(api, "DoubleRedirects", ns, limit, partial).await
+}
+
+/// Retrives the redirects whose target does not exist. That is how [[Special:BrokenRedirects]] works.
+/// 
+/// `ns`: Namespace filter. If set to `None`, then the result is not filtered by namespace.
+/// 
+/// `limit`: Query limit.
+pub(crate) async fn get_broken_redirects(api: &APIService, ns: Option<&HashSet<NamespaceID>>, limit: i64, partial: Option<&Incomplete>) -> Result<HashSet<Title>, SolveError> {
+    get_query_page(api, "BrokenRedirects", ns, limit, partial).await
+}
+
+/// Retrives the titles protected from creation, a.k.a. salted titles. That is how [[Special:ProtectedTitles]] works.
+/// None of these titles exist, so they have no page id.
+/// 
+/// `level`: Protection level, e.g. `sysop`. Levels are site-specific. If set to `None`, any level matches.
+/// 
+/// `ns`: Namespace filter. If set to `None`, then the result is not filtered by namespace.
+/// 
+/// `limit`: Query limit.
+pub(crate) async fn get_protected_titles(api: &APIService, level: Option<&str>, ns: Option<&HashSet<NamespaceID>>, limit: i64, partial: Option<&Incomplete>) -> Result<HashSet<Title>, SolveError> {
+    // there is no generator for protected titles, so the titles are collected from the list
+    let mut params = hashmap![
+        "action".to_string() => "query".to_string(),
+        "list".to_string() => "protectedtitles".to_string(),
+        "ptprop".to_string() => "".to_string(),
+        "ptlimit".to_string() => "max".to_string()
+    ];
+    if let Some(level) = level {
+        params.insert("ptlevel".to_string(), level.to_string());
+    }
+    if let Some(ns_list) = ns {
+        params.insert("ptnamespace".to_string(), util::concat_params(ns_list));
+    }
+    let res = get_continued(api, &params, limit, partial).await?;
+    let mut title_set: HashSet<Title> = HashSet::new();
+    if let Some(titles) = res["query"]["protectedtitles"].as_array() {
+        for title in titles {
+            title_set.insert(Title::new_from_api_result(title));
+        }
+    }
+    Ok(title_set)
+}
+
+/// Retrives the pages listed on a maintenance special page, such as [[Special:DoubleRedirects]].
+/// 
+/// On large wikis, these special pages are only refreshed from time to time, so the result may be days old:
+/// some pages in it may have been fixed already, and recent ones may be missing.
+/// 
+/// `page`: Name of the special page, without the namespace.
+/// 
+/// `ns`: Namespace filter. If set to `None`, then the result is not filtered by namespace.
+/// The API cannot filter by namespace, so it is applied after fetching. Note that this also means the query limit counts pages in every namespace.
+/// 
+/// `limit`: Query limit.
+async fn get_query_page(api: &APIService, page: &str, ns: Option<&HashSet<NamespaceID>>, limit: i64, partial: Option<&Incomplete>) -> Result<HashSet<Title>, SolveError> {
+    let params = hashmap![
+        "action".to_string() => "query".to_string(),
+        "list".to_string() => "querypage".to_string(),
+        "qppage".to_string() => page.to_string(),
+        "qplimit".to_string() => "max".to_string()
+    ];
+    let res = get_continued(api, &params, limit, partial).await?;
+    let mut title_set: HashSet<Title> = HashSet::new();
+    if let Some(results) = res["query"]["querypage"]["results"].as_array() {
+        for result in results {
+            title_set.insert(Title::new_from_api_result(result));
+        }
+    }
+    if let Some(ns_list) = ns {
+        title_set.retain(|t| ns_list.contains(&t.namespace_id()));
+    }
+    Ok(title_set)
+}
+
+/// Retrives the interlanguage links of one page.
+/// 
+/// The linked pages are on other wikis, so they are returned in the pseudo namespace `NS_INTERWIKI`,
+/// with the language code as interwiki prefix, e.g. `fr:Paris`.
+/// Any generator fed with such a title returns nothing, as it does not exist on this wiki.
+/// Like any other foreign page, they are dropped from the result by the solver unless `keepforeign()` is given.
+/// 
+/// `title`: The title of the page.
+pub(crate) async fn get_langlinks_one(api: &APIService, title: &Title) -> Result<HashSet<Title>, SolveError> {
+    let elem_name = api.full_pretty(title).await?;
+    if let Some(elem_name) = elem_name {
+        let params = hashmap![
+            "action".to_string() => "query".to_string(),
+            "prop".to_string() => "langlinks".to_string(),
+            "titles".to_string() => elem_name,
+            "lllimit".to_string() => "max".to_string()
+        ];
+        let res = util::retry(util::API_RETRY, || api.get_all(&params)).await?;
+        let mut title_set: HashSet<Title> = HashSet::new();
+        if let Some(pages) = res["query"]["pages"].as_array() {
+            for pageobj in pages {
+                if let Some(links) = pageobj["langlinks"].as_array() {
+                    for link in links {
+                        if let (Some(lang), Some(name)) = (link["lang"].as_str(), link["title"].as_str()) {
+                            title_set.insert(Title::new(&format!("{}:{}", lang, name), super::def::NS_INTERWIKI));
+                        }
+                    }
+                }
+            }
+        }
+        Ok(title_set)
+    } else {
+        Ok(HashSet::new())
+    }
+}
+
+/// Replaces each redirect in a set of pages with its target.
+/// Pages that are not redirects, including missing pages, are kept as they are.
+/// A double redirect is followed as far as the API resolves it in the same request.
+/// 
+/// `titles`: The pages to resolve.
+pub(crate) async fn resolve_redirects(api: &APIService, titles: &HashSet<Title>) -> Result<HashSet<Title>, SolveError> {
+    let mut title_set: HashSet<Title> = HashSet::new();
+    let mut names: Vec<(String, &Title)> = Vec::new();
+    for t in titles {
+        if let Some(name) = api.full_pretty(t).await? {
+            names.push((name, t));
+        } else {
+            title_set.insert(t.clone());
+        }
+    }
+    let batch_size = api.title_batch_size().await;
+    for batch in names.chunks(batch_size) {
+        let params = hashmap![
+            "action".to_string() => "query".to_string(),
+            "titles".to_string() => batch.iter().map(|(name, _)| name.as_str()).collect::<Vec<&str>>().join("|"),
+            "redirects".to_string() => "1".to_string(),
+            "converttitles".to_string() => "1".to_string()
+        ];
+        let res = util::retry(util::API_RETRY, || api.get(&params)).await?;
+        let canonical = canonical_names(&res);
+        let mut targets: HashMap<&str, &str> = HashMap::new();
+        if let Some(redirs) = res["query"]["redirects"].as_array() {
+            for itm in redirs {
+                if let (Some(from), Some(to)) = (itm["from"].as_str(), itm["to"].as_str()) {
+                    targets.insert(from, to);
+                }
+            }
+        }
+        for (name, t) in batch {
+            // follow the chain from the name the API knows the page by, but stop at a loop of redirects
+            let mut current = canonical.get(name.as_str()).copied().unwrap_or(name.as_str());
+            let mut visited: HashSet<&str> = HashSet::from([current]);
+            while let Some(&next) = targets.get(current) {
+                if !visited.insert(next) {
+                    break;
+                }
+                current = next;
+            }
+            if current == name.as_str() {
+                title_set.insert((*t).clone());
+            } else {
+                title_set.insert(api.title_new_from_full(current).await?);
+            }
+        }
+    }
+    Ok(title_set)
+}
+
+/// Retrives the information of a set of pages, in batches.
+/// Missing and invalid pages have no information, so they are left out of the result.
+/// The result is keyed by the titles as given, even if the API normalizes or converts their names.
+/// 
+/// `titles`: The pages to look up.
+/// 
+/// `inprop`: Additional properties to fetch, e.g. `protection`. Size, last touched time, redirect flag and page ID are always fetched.
+pub(crate) async fn fetch_page_info(api: &APIService, titles: &[Title], inprop: &[&str]) -> Result<HashMap<Title, PageInfo>, SolveError> {
+    let mut info_map: HashMap<Title, PageInfo> = HashMap::new();
+    let mut names: HashMap<String, &Title> = HashMap::new();
+    for t in titles {
+        if let Some(name) = api.full_pretty(t).await? {
+            names.insert(name, t);
+        }
+    }
+    let name_list: Vec<&str> = names.keys().map(|n| n.as_str()).collect();
+    let batch_size = api.title_batch_size().await;
+    for batch in name_list.chunks(batch_size) {
+        let mut params = hashmap![
+            "action".to_string() => "query".to_string(),
+            "prop".to_string() => "info".to_string(),
+            "titles".to_string() => batch.join("|"),
+            "converttitles".to_string() => "1".to_string()
+        ];
+        if !inprop.is_empty() {
+            params.insert("inprop".to_string(), inprop.join("|"));
+        }
+        let res = util::retry(util::API_RETRY, || api.get(&params)).await.map_err(SolveError::PageInfo)?;
+        let canonical = canonical_names(&res);
+        let pages: HashMap<&str, &serde_json::Value> = res["query"]["pages"].as_array().into_iter().flatten()
+            .filter(|pageobj| pageobj.get("missing").is_none() && pageobj.get("invalid").is_none())
+            .filter_map(|pageobj| Some((pageobj["title"].as_str()?, pageobj)))
+            .collect();
+        // pages are listed under the names the API turned the asked names into, so map them back
+        for &name in batch {
+            let pageobj = match pages.get(canonical.get(name).copied().unwrap_or(name)) {
+                Some(pageobj) => pageobj,
+                None => continue,
+            };
+            let info = PageInfo {
+                size: pageobj["length"].as_u64().unwrap_or(0),
+                touched: pageobj["touched"].as_str()
+                    .and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok())
+                    .map(|t| t.with_timezone(&chrono::Utc)),
+                protection: pageobj["protection"].as_array().map(|prs| prs.iter().filter_map(|pr| Some(PageProtection {
+                    prtype: pr["type"].as_str()?.to_string(),
+                    level: pr["level"].as_str()?.to_string(),
+                    // indefinite protections have an expiry of "infinity", which is not a timestamp
+                    expiry: pr["expiry"].as_str()
+                        .and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok())
+                        .map(|t| t.with_timezone(&chrono::Utc)),
+                })).collect()).unwrap_or_default(),
+                redirect: pageobj.get("redirect").is_some(),
+                page_id: pageobj["pageid"].as_u64().unwrap_or(0),
+            };
+            info_map.insert(names[name].clone(), info);
+        }
+    }
+    Ok(info_map)
+}
+
+/// Retrives the wikitext of the latest revision of a batch of pages, along with the batch.
+async fn get_content_batch<'a>(api: &APIService, batch: &'a [&'a str]) -> Result<(&'a [&'a str], serde_json::Value), SolveError> {
+    let params = hashmap![
+        "action".to_string() => "query".to_string(),
+        "prop".to_string() => "revisions".to_string(),
+        "rvprop".to_string() => "content".to_string(),
+        "rvslots".to_string() => "main".to_string(),
+        "titles".to_string() => batch.join("|"),
+        "converttitles".to_string() => "1".to_string()
+    ];
+    // the wikitext of a batch may not fit in one response, so continue until every page is listed
+    let res = util::retry(util::API_RETRY, || api.get_all(&params)).await?;
+    Ok((batch, res))
+}
+
+/// Keeps the pages in a set whose wikitext passes `keep`, fetching the wikitext of the latest revision in batches.
+/// Missing pages have no wikitext, so they are never kept.
+/// 
+/// This is expensive, as the wikitext of every page is downloaded. At most `util::MAX_CONCURRENT_REQUESTS` batches are fetched at the same time,
+/// each request still waiting for the rate limit of the `APIService`, and the wikitext of a batch is dropped as soon as it has been checked.
+/// 
+/// `titles`: The pages to check.
+/// 
+/// `keep`: Whether to keep a page, given its wikitext.
+pub(crate) async fn filter_by_content(api: &APIService, titles: &[Title], keep: &(dyn Fn(&str) -> bool + Sync)) -> Result<HashSet<Title>, SolveError> {
+    let mut title_set: HashSet<Title> = HashSet::new();
+    let mut names: HashMap<String, &Title> = HashMap::new();
+    for t in titles {
+        if let Some(name) = api.full_pretty(t).await? {
+            names.insert(name, t);
+        }
+    }
+    let name_list: Vec<&str> = names.keys().map(|n| n.as_str()).collect();
+    let batch_size = api.title_batch_size().await;
+    let requests: Vec<_> = name_list.chunks(batch_size).map(|batch| get_content_batch(api, batch)).collect();
+    let mut responses = stream::iter(requests).buffer_unordered(util::MAX_CONCURRENT_REQUESTS);
+    while let Some(res) = responses.next().await {
+        let (batch, res) = res?;
+        let canonical = canonical_names(&res);
+        // a page continued in a later response is listed again, only one of them holds the wikitext
+        let kept: HashSet<&str> = res["query"]["pages"].as_array().into_iter().flatten()
+            .filter_map(|pageobj| Some((pageobj["title"].as_str()?, pageobj["revisions"][0]["slots"]["main"]["content"].as_str()?)))
+            .filter(|(_, text)| keep(text))
+            .map(|(name, _)| name)
+            .collect();
+        for &name in batch {
+            if kept.contains(canonical.get(name).copied().unwrap_or(name)) {
+                title_set.insert(names[name].clone());
+            }
+        }
+    }
+    Ok(title_set)
+}
+
+/// Retrives the media type and MIME type of the files in a set of pages, in batches.
+/// Pages outside the File namespace are not looked up. Files that were never uploaded have no type, so they are left out of the result.
+/// 
+/// `titles`: The pages to look up.
+pub(crate) async fn get_media_info(api: &APIService, titles: &HashSet<Title>) -> Result<HashMap<Title, FileMedia>, SolveError> {
+    let mut media_map: HashMap<Title, FileMedia> = HashMap::new();
+    let mut names: HashMap<String, &Title> = HashMap::new();
+    for t in titles.iter().filter(|t| t.namespace_id() == super::def::NS_FILE) {
+        if let Some(name) = api.full_pretty(t).await? {
+            names.insert(name, t);
+        }
+    }
+    let name_list: Vec<&str> = names.keys().map(|n| n.as_str()).collect();
+    let batch_size = api.title_batch_size().await;
+    for batch in name_list.chunks(batch_size) {
+        let params = hashmap![
+            "action".to_string() => "query".to_string(),
+            "prop".to_string() => "imageinfo".to_string(),
+            "iiprop".to_string() => "mediatype|mime".to_string(),
+            "titles".to_string() => batch.join("|"),
+            "converttitles".to_string() => "1".to_string()
+        ];
+        // image information may not fit in one response, so continue until every file is listed
+        let res = util::retry(util::API_RETRY, || api.get_all(&params)).await.map_err(SolveError::PageInfo)?;
+        let mut media: HashMap<&str, FileMedia> = HashMap::new();
+        for pageobj in res["query"]["pages"].as_array().into_iter().flatten() {
+            // a page continued in a later response is listed again, only one of them holds the information
+            if let (Some(name), Some(info)) = (pageobj["title"].as_str(), pageobj["imageinfo"].as_array().and_then(|ii| ii.first())) {
+                if let (Some(media_type), Some(mime)) = (info["mediatype"].as_str(), info["mime"].as_str()) {
+                    media.insert(name, FileMedia { media_type: media_type.to_string(), mime: mime.to_string() });
+                }
+            }
+        }
+        let canonical = canonical_names(&res);
+        for &name in batch {
+            if let Some(m) = media.get(canonical.get(name).copied().unwrap_or(name)) {
+                media_map.insert(names[name].clone(), m.clone());
+            }
+        }
+    }
+    Ok(media_map)
+}
+
+/// Normalizes a list of user-supplied titles according to the site's rules.
+/// 
+/// Local normalization cannot handle every site-specific rule, so `Foo_bar`, `foo bar` and `Foo  bar` might end up as different titles.
+/// Instead, the titles are sent to the API in batches, and the normalized titles in the response are used.
+/// On a wiki with language variants, a title written in another variant is converted to the one the page exists under,
+/// so that it matches the titles other queries return.
+/// Titles on other wikis, such as `fr:Paris`, are put in the pseudo namespace `NS_INTERWIKI`, as `get_langlinks_one` does.
+/// Missing pages are kept. Titles the API considers invalid, such as the bare namespace `Category:` commonly fed to `Prefix`, are normalized locally instead.
+/// 
+/// `titles`: The titles to normalize.
+/// 
+/// `keep_fragment`: Whether to keep the `#fragment` of a title. If not set, the fragment is stripped, and a bare fragment such as `#foo` is dropped.
+/// The API always strips fragments, so a title with a fragment to keep is normalized locally instead.
+pub(crate) async fn normalize_titles(api: &APIService, titles: &[String], keep_fragment: bool) -> Result<HashSet<Title>, SolveError> {
+    let mut title_set: HashSet<Title> = HashSet::new();
+    let mut names: Vec<&str> = Vec::new();
+    for t in titles {
+        // the API silently skips empty titles, but an empty prefix is meaningful
+        if t.trim().is_empty() {
+            title_set.insert(api.title_new_from_full(t).await?);
+            continue;
+        }
+        match t.split_once('#') {
+            Some(_) if keep_fragment => {
+                title_set.insert(api.title_new_from_full(t).await?);
+            },
+            Some((base, _)) => {
+                if !base.trim().is_empty() {
+                    names.push(base);
+                }
+            },
+            None => names.push(t),
+        }
+    }
+    let batch_size = api.title_batch_size().await;
+    for batch in names.chunks(batch_size) {
+        let params = hashmap![
+            "action".to_string() => "query".to_string(),
+            "titles".to_string() => batch.join("|"),
+            "converttitles".to_string() => "1".to_string()
+        ];
+        let res = util::retry(util::API_RETRY, || api.get(&params)).await?;
+        if let Some(pages) = res["query"]["pages"].as_array() {
+            for pageobj in pages {
+                if pageobj.get("invalid").is_none() {
+                    title_set.insert(Title::new_from_api_result(pageobj));
+                } else if let Some(t) = pageobj["title"].as_str() {
+                    title_set.insert(api.title_new_from_full(t).await?);
+                }
+            }
+        }
+        // titles on other wikis are not pages here, the API lists them apart, prefix included
+        if let Some(interwiki) = res["query"]["interwiki"].as_array() {
+            for iwobj in interwiki {
+                if let Some(t) = iwobj["title"].as_str() {
+                    title_set.insert(Title::new(t, super::def::NS_INTERWIKI));
+                }
+            }
+        }
+    }
+    Ok(title_set)
+}
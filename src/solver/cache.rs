@@ -0,0 +1,64 @@
+//! An intra-query cache for generator results.
+//!
+//! The same page may be fed into the same generator through different branches of a query,
+//! e.g. `linkto("A") - linkto("A", "B")` queries the backlinks of `A` twice.
+//! The cache makes sure each such API query is only issued once per solve.
+
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+
+use mediawiki::title::Title;
+
+use crate::parser::ir::SetConstraint;
+use super::{error::SolveError, util::query_each};
+
+/// The kind of generator whose result is cached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum Generator {
+    Link,
+    LinkTo,
+    EmbeddedIn,
+    TemplatesOn,
+    FileUsage,
+    RedirectsTo,
+    InCat,
+    Prefix,
+    LangLinks,
+}
+
+/// Results of generators on single pages, grouped by generator kind and constraint.
+#[derive(Debug, Default)]
+pub(crate) struct SolverCache {
+    entries: HashMap<(Generator, SetConstraint), HashMap<Title, HashSet<Title>>>,
+}
+
+impl SolverCache {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `query` on every title in `set` and union the results.
+    /// Titles that were already queried with the same generator and constraint are answered from the cache,
+    /// the rest are queried concurrently and stored in the cache.
+    pub(crate) async fn query_each<'a, F, Fut>(&mut self, kind: Generator, cs: &SetConstraint, set: &'a HashSet<Title>, query: F) -> Result<HashSet<Title>, SolveError>
+    where
+        F: Fn(&'a Title) -> Fut,
+        Fut: Future<Output = Result<HashSet<Title>, SolveError>>,
+    {
+        let entry = self.entries.entry((kind, cs.clone())).or_default();
+        let mut result_set: HashSet<Title> = HashSet::new();
+        let mut queries = Vec::new();
+        for t in set.iter() {
+            if let Some(cached) = entry.get(t) {
+                result_set.extend(cached.iter().cloned());
+            } else {
+                queries.push((t, query(t)));
+            }
+        }
+        for (t, res_one) in query_each(queries).await? {
+            result_set.extend(res_one.iter().cloned());
+            entry.insert(t.clone(), res_one);
+        }
+        Ok(result_set)
+    }
+}
@@ -0,0 +1,109 @@
+//! Checkpoints allow a long solve to survive a restart.
+//!
+//! After each completed instruction, the whole register and the index of the
+//! next instruction are written to a checkpoint file. When the same query is
+//! solved again, the solver picks up from the checkpoint instead of starting over.
+//! Registers that only hold the results gathered before a query failed mid-way are marked,
+//! so that the solver computes them again instead of taking them as complete.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use mediawiki::{api::NamespaceID, title::Title};
+use tracing::{event, Level};
+
+use crate::parser::ir::RegID;
+use super::Register;
+
+/// Checkpoints older than this are not resumed, as the wiki has likely changed too much since.
+/// They are left over from a run that never came back, e.g. because the task was turned off in the meantime.
+pub(crate) const MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Checkpoint {
+    /// When the checkpoint was saved, as a Unix timestamp. Checkpoints saved before it was recorded count as too old.
+    #[serde(default)]
+    saved: i64,
+    /// The total number of instructions, used as a sanity check.
+    total: usize,
+    /// The index of the next instruction to execute.
+    next: usize,
+    registers: HashMap<RegID, Vec<(NamespaceID, String)>>,
+    /// The registers whose results are incomplete.
+    #[serde(default)]
+    incomplete: Vec<RegID>,
+}
+
+/// `Title` cannot be serialized, so titles are saved as namespace and name pairs.
+pub(crate) fn titles_to_plain(titles: &HashSet<Title>) -> Vec<(NamespaceID, String)> {
+    titles.iter().map(|t| (t.namespace_id(), t.pretty().to_string())).collect()
+}
+
+/// The reverse of `titles_to_plain`.
+pub(crate) fn titles_from_plain(titles: Vec<(NamespaceID, String)>) -> HashSet<Title> {
+    titles.into_iter().map(|(ns, t)| Title::new(&t, ns)).collect()
+}
+
+/// Load a checkpoint. Returns the index of the next instruction, the saved register, and the registers whose results are incomplete.
+/// Returns `None` if there is no usable checkpoint.
+pub(crate) fn load(path: &Path, total: usize) -> Option<(usize, Register, HashSet<RegID>)> {
+    let content = fs::read_to_string(path).ok()?;
+    let checkpoint: Checkpoint = match serde_json::from_str(&content) {
+        Ok(c) => c,
+        Err(e) => {
+            event!(Level::WARN, error = ?e, path = ?path, "cannot parse checkpoint, ignore");
+            return None;
+        },
+    };
+    if checkpoint.total != total || checkpoint.next > total {
+        event!(Level::WARN, path = ?path, "checkpoint does not match the query, ignore");
+        return None;
+    }
+    let age = chrono::Utc::now().timestamp().saturating_sub(checkpoint.saved);
+    if age < 0 || age as u64 > MAX_AGE.as_secs() {
+        event!(Level::WARN, path = ?path, age, "checkpoint is too old, ignore");
+        return None;
+    }
+    let reg: Register = checkpoint.registers.into_iter()
+        .map(|(id, titles)| (id, titles_from_plain(titles)))
+        .collect();
+    Some((checkpoint.next, reg, checkpoint.incomplete.into_iter().collect()))
+}
+
+/// Save a checkpoint. Failure to save is logged but not fatal, the solve simply cannot be resumed.
+pub(crate) fn save(path: &Path, total: usize, next: usize, reg: &Register, incomplete: &HashSet<RegID>) {
+    let checkpoint = Checkpoint {
+        saved: chrono::Utc::now().timestamp(),
+        total,
+        next,
+        registers: reg.iter()
+            .map(|(id, titles)| (*id, titles_to_plain(titles)))
+            .collect(),
+        incomplete: incomplete.iter().copied().collect(),
+    };
+    let write_result = serde_json::to_string(&checkpoint)
+        .map_err(std::io::Error::from)
+        .and_then(|content| {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            // Write to a temporary file first, so that a crash never leaves a half-written checkpoint
+            let tmp_path = path.with_extension("tmp");
+            fs::write(&tmp_path, content)?;
+            fs::rename(&tmp_path, path)
+        });
+    if let Err(e) = write_result {
+        event!(Level::WARN, error = ?e, path = ?path, "cannot save checkpoint");
+    }
+}
+
+/// Remove a checkpoint once the solve completes.
+pub(crate) fn clear(path: &Path) {
+    if path.exists() {
+        if let Err(e) = fs::remove_file(path) {
+            event!(Level::WARN, error = ?e, path = ?path, "cannot remove checkpoint");
+        }
+    }
+}
@@ -19,4 +19,8 @@ pub const NS_HELP_TALK: NamespaceID = 13;
 pub const NS_CATEGORY: NamespaceID = 14;
 pub const NS_CATEGORY_TALK: NamespaceID = 15;
 pub const NS_SPECIAL: NamespaceID = -1;
-pub const NS_MEDIA: NamespaceID = -2;
\ No newline at end of file
+pub const NS_MEDIA: NamespaceID = -2;
+
+// Not a real namespace. Pages on other wikis are put here, with the interwiki prefix in front of the page name,
+// so that they never mix with local pages.
+pub const NS_INTERWIKI: NamespaceID = -1000;
\ No newline at end of file
@@ -7,9 +7,12 @@ use crate::apiservice::APIServiceError;
 pub enum SolveError {
     MediaWiki(mediawiki::media_wiki_error::MediaWikiError),
     APIService(APIServiceError),
-    QueryForMultiplePages,
     UnknownIntermediateValue,
     NotCategory,
+    PageInfo(APIServiceError),
+    Timeout,
+    Cancelled,
+    InvalidRegex(regex::Error),
 }
 
 impl Error for SolveError {}
@@ -19,10 +22,13 @@ impl fmt::Display for SolveError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::MediaWiki(e) => e.fmt(f),
-            Self::QueryForMultiplePages => f.write_str("cannot query for multiple pages"),
             Self::APIService(e) => f.write_fmt(format_args!("API Service fails with error: \"{}\"", e)),
             Self::UnknownIntermediateValue => f.write_str("cannot access an intermediate value before it is initialized"),
             Self::NotCategory => f.write_str("cannot query for members of something not a category"),
+            Self::PageInfo(e) => f.write_fmt(format_args!("cannot fetch page information for filtering: \"{}\"", e)),
+            Self::Timeout => f.write_str("query did not finish in time"),
+            Self::Cancelled => f.write_str("query is cancelled"),
+            Self::InvalidRegex(e) => f.write_fmt(format_args!("invalid regular expression in content filter: \"{}\"", e)),
         }
     }
 }
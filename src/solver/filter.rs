@@ -0,0 +1,98 @@
+//! Post filters, which narrow down the result of an instruction after it is generated.
+//!
+//! Generators can only filter on what the API offers for them, e.g. namespaces.
+//! Post filters look up the information of each resulting page instead,
+//! so they work the same on the result of any instruction.
+
+use std::collections::{HashMap, HashSet};
+
+use mediawiki::title::Title;
+use regex::Regex;
+
+use crate::parser::ir::{SetConstraint, ProtectionStatus, Timestamp};
+use super::{def, error::SolveError, source::{PageSource, PageInfo, FileMedia}};
+
+/// Whether a page is a local page, i.e. neither a special page nor a page on another wiki.
+/// Only local pages can be fed into generators or linked from a result page as usual.
+pub(crate) fn is_local(title: &Title) -> bool {
+    let ns = title.namespace_id();
+    ns != def::NS_SPECIAL && ns != def::NS_MEDIA && ns != def::NS_INTERWIKI
+}
+
+/// Whether a page passes every post filter in `cs`, as of `now`.
+/// `media` is the type of the page if it is a file, as looked up for the media type filter.
+fn passes(cs: &SetConstraint, title: &Title, info: &PageInfo, media: Option<&FileMedia>, now: Timestamp) -> bool {
+    if let Some(lt) = cs.size_lt {
+        if info.size >= lt {
+            return false;
+        }
+    }
+    if let Some(gt) = cs.size_gt {
+        if info.size <= gt {
+            return false;
+        }
+    }
+    if cs.touched_after.is_some() || cs.touched_before.is_some() {
+        let touched = match info.touched {
+            Some(t) => t,
+            None => return false,
+        };
+        if cs.touched_after.map(|t| touched < t).unwrap_or(false) || cs.touched_before.map(|t| touched > t).unwrap_or(false) {
+            return false;
+        }
+    }
+    if let Some(status) = &cs.protected {
+        // a protection that has expired but not yet been cleaned up by the wiki does not count
+        let mut active = info.protection.iter().filter(|pr| pr.expiry.map(|e| e > now).unwrap_or(true));
+        let matched = match status {
+            ProtectionStatus::Unprotected(prtype) => !active.any(|pr| pr.prtype == prtype.to_string()),
+            ProtectionStatus::Protected { filter, indefinite } => active.any(|pr|
+                pr.prtype == filter.prtype.to_string()
+                && filter.prlevel.as_ref().map(|l| *l == pr.level).unwrap_or(true)
+                && (!indefinite || pr.expiry.is_none())
+            ),
+        };
+        if !matched {
+            return false;
+        }
+    }
+    if let Some(is_redirect) = cs.is_redirect {
+        if info.redirect != is_redirect {
+            return false;
+        }
+    }
+    if let Some(filter) = &cs.media_type {
+        let matched = if title.namespace_id() == def::NS_FILE {
+            media.map(|m| filter.matches(&m.media_type, &m.mime)).unwrap_or(false)
+        } else {
+            filter.keep_non_file
+        };
+        if !matched {
+            return false;
+        }
+    }
+    true
+}
+
+/// Keep only the pages in `set` that pass every post filter in `cs`.
+/// Missing pages never pass, since there is nothing to filter on.
+/// Time bounds are inclusive, as with the time range of category members.
+/// 
+/// `content` are the compiled regular expressions of the content filters of `cs`, in the same order. A page must pass all of them.
+/// The content filters download the wikitext of every page it checks, so it only checks the pages that pass every other filter.
+pub(crate) async fn apply<S: PageSource>(source: &S, cs: &SetConstraint, content: &[Regex], set: HashSet<Title>) -> Result<HashSet<Title>, SolveError> {
+    if !cs.has_post_filter() || set.is_empty() {
+        return Ok(set);
+    }
+    let titles: Vec<Title> = set.iter().cloned().collect();
+    let inprop: &[&str] = if cs.protected.is_some() { &["protection"] } else { &[] };
+    let info = source.page_info(&titles, inprop).await?;
+    let media = if cs.media_type.is_some() { source.media_info(&set).await? } else { HashMap::new() };
+    let now = chrono::Utc::now();
+    let kept: HashSet<Title> = set.into_iter().filter(|t| info.get(t).map(|i| passes(cs, t, i, media.get(t), now)).unwrap_or(false)).collect();
+    if content.is_empty() || kept.is_empty() {
+        return Ok(kept);
+    }
+    let titles: Vec<Title> = kept.into_iter().collect();
+    source.filter_by_content(&titles, &|text| cs.content.iter().zip(content).all(|(filter, regex)| regex.is_match(text) != filter.invert)).await
+}
@@ -3,23 +3,188 @@ extern crate mediawiki;
 mod util;
 mod error;
 mod apisolver;
+mod checkpoint;
+mod resultcache;
+mod cache;
+mod source;
+mod filter;
+mod template;
 mod def;
 
 pub use error::SolveError;
-use crate::{parser::{ir::RegID, ir::RedirectFilterStrategy}, API_SERVICE};
+pub use def::NS_INTERWIKI;
+pub use resultcache::ResultCache;
+pub(crate) use apisolver::fetch_page_info;
+use crate::parser::{ir::RegID, ir::RedirectFilterStrategy, ir::SetConstraint};
 use util::{get_set_1, get_set_2};
+use cache::{SolverCache, Generator};
+use source::{PageSource, ApiSource};
+use apisolver::Incomplete;
 
 use crate::parser::{Query, ir::Instruction};
+use crate::apiservice::APIService;
 
 use std::collections::{HashSet, HashMap};
+use std::path::Path;
+use std::time::{Duration, Instant};
 use mediawiki::{title::Title};
+use tokio_util::sync::CancellationToken;
+use tracing::{event, Level};
 
 pub(crate) type Register = HashMap<RegID, HashSet<Title>>;
 
-pub async fn solve_api(query: &Query, default_limit: i64) -> Result<HashSet<Title>, SolveError> {
+/// Progress of a solve, reported after each instruction finishes.
+pub struct SolveProgress<'a> {
+    /// The 1-based index of the finished instruction.
+    pub step: usize,
+    /// The total number of instructions.
+    pub total: usize,
+    pub instruction: &'a Instruction,
+    /// The number of titles the instruction produced.
+    pub size: usize,
+    /// The wall-clock time the instruction took, post filters included.
+    /// `None` if it was not run, because its result was cached or is not needed.
+    pub elapsed: Option<Duration>,
+}
+
+/// The time one instruction of a solve took, as returned by `solve_api_with_metrics`.
+#[derive(Debug, Clone)]
+pub struct InstructionTiming {
+    pub dest: RegID,
+    /// The name of the instruction, see `Instruction::kind`.
+    pub kind: &'static str,
+    /// The number of titles the instruction produced.
+    pub size: usize,
+    pub elapsed: Duration,
+}
+
+pub type ProgressCallback<'a> = &'a mut (dyn FnMut(&SolveProgress) + Send);
+
+/// Solve a query using MediaWiki API.
+/// If `timeout` is set and the solve does not finish in time, `SolveError::Timeout` is returned.
+pub async fn solve_api(api: &APIService, query: &Query, default_limit: i64, timeout: Option<Duration>) -> Result<HashSet<Title>, SolveError> {
+    solve(&ApiSource(api, None), query, default_limit, None, None, timeout, &CancellationToken::new(), None).await
+}
+
+/// Same as `solve_api`, but also returns the time each instruction took, in the order they were run.
+pub async fn solve_api_with_metrics(api: &APIService, query: &Query, default_limit: i64, timeout: Option<Duration>) -> Result<(HashSet<Title>, Vec<InstructionTiming>), SolveError> {
+    let mut timings: Vec<InstructionTiming> = Vec::new();
+    let mut record = |p: &SolveProgress| {
+        if let Some(elapsed) = p.elapsed {
+            timings.push(InstructionTiming { dest: p.instruction.get_dest(), kind: p.instruction.kind(), size: p.size, elapsed });
+        }
+    };
+    let result = solve(&ApiSource(api, None), query, default_limit, None, None, timeout, &CancellationToken::new(), Some(&mut record)).await?;
+    Ok((result, timings))
+}
+
+/// Same as `solve_api`, but calls `progress` after every instruction.
+/// The solve stops with `SolveError::Cancelled` soon after `cancel` is cancelled.
+/// 
+/// With `partial`, a continued query that fails mid-way does not fail the solve, but goes on with the results gathered before the failure.
+/// The result may then be incomplete, which is only logged.
+#[allow(clippy::too_many_arguments)]
+pub async fn solve_api_with_progress(api: &APIService, query: &Query, default_limit: i64, timeout: Option<Duration>, partial: bool, cancel: &CancellationToken, progress: ProgressCallback<'_>) -> Result<HashSet<Title>, SolveError> {
+    solve(&ApiSource(api, partial.then(Incomplete::default)), query, default_limit, None, None, timeout, cancel, Some(progress)).await
+}
+
+/// Same as `solve_api_with_progress`, but saves the progress to `checkpoint` after every instruction.
+/// If `checkpoint` already holds the progress of an interrupted solve, the solve resumes from there.
+/// The checkpoint is removed once the solve succeeds. A solve that times out keeps its checkpoint.
+/// A cancelled solve keeps its checkpoint too.
+/// With `partial`, results that are incomplete are marked in the checkpoint, and computed again on resume.
+/// 
+/// If `result_cache` is set, results of earlier solves found in it are reused, and new results are saved to it.
+/// Incomplete results are never saved to it.
+#[allow(clippy::too_many_arguments)]
+pub async fn solve_api_resumable(api: &APIService, query: &Query, default_limit: i64, timeout: Option<Duration>, partial: bool, checkpoint: &Path, result_cache: Option<&ResultCache>, cancel: &CancellationToken, progress: Option<ProgressCallback<'_>>) -> Result<HashSet<Title>, SolveError> {
+    solve(&ApiSource(api, partial.then(Incomplete::default)), query, default_limit, Some(checkpoint), result_cache, timeout, cancel, progress).await
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn solve<S: PageSource>(source: &S, query: &Query, default_limit: i64, checkpoint: Option<&Path>, result_cache: Option<&ResultCache>, timeout: Option<Duration>, cancel: &CancellationToken, progress: Option<ProgressCallback<'_>>) -> Result<HashSet<Title>, SolveError> {
+    if let Some(timeout) = timeout {
+        tokio::time::timeout(timeout, solve_inner(source, query, default_limit, checkpoint, result_cache, cancel, progress)).await.unwrap_or(Err(SolveError::Timeout))
+    } else {
+        solve_inner(source, query, default_limit, checkpoint, result_cache, cancel, progress).await
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn solve_inner<S: PageSource>(source: &S, query: &Query, default_limit: i64, checkpoint: Option<&Path>, result_cache: Option<&ResultCache>, cancel: &CancellationToken, mut progress: Option<ProgressCallback<'_>>) -> Result<HashSet<Title>, SolveError> {
     // prepare a mock register pool using HashMap
-    let mut reg: Register = HashMap::new();
+    let total = query.0.len();
+    let (mut start, mut reg): (usize, Register) = match checkpoint.and_then(|path| checkpoint::load(path, total)) {
+        Some((next, mut reg, incomplete)) => {
+            // an incomplete result is computed again, along with every result after it
+            let next = match query.0.iter().take(next).position(|i| incomplete.contains(&i.get_dest())) {
+                Some(redo) => {
+                    event!(Level::INFO, redo, "checkpoint holds incomplete results, compute them again");
+                    for inst in query.0.iter().skip(redo) {
+                        reg.remove(&inst.get_dest());
+                    }
+                    redo
+                },
+                None => next,
+            };
+            event!(Level::INFO, next, total, "resume from checkpoint");
+            (next, reg)
+        },
+        None => (0, HashMap::new()),
+    };
+    // with a result cache, an instruction is skipped if its result is cached, or only needed by instructions whose results are cached
+    let mut skipped: HashSet<RegID> = HashSet::new();
+    let fingerprints = if result_cache.is_some() { resultcache::fingerprints(query, default_limit) } else { HashMap::new() };
+    if let Some(result_cache) = result_cache {
+        let mut needed: HashSet<RegID> = HashSet::new();
+        let mut cached: Register = HashMap::new();
+        let mut stack: Vec<RegID> = vec![query.1];
+        while let Some(r) = stack.pop() {
+            if !needed.insert(r) || reg.contains_key(&r) {
+                continue;
+            }
+            if let Ok(idx) = query.0.binary_search_by(|probe| probe.get_dest().cmp(&r)) {
+                let inst = &query.0[idx];
+                if resultcache::is_cacheable(inst) {
+                    if let Some(set) = fingerprints.get(&r).and_then(|fp| result_cache.load(fp)) {
+                        cached.insert(r, set);
+                        continue;
+                    }
+                }
+                stack.extend(inst.get_operands());
+            }
+        }
+        // a result cached when the checkpoint was saved may have expired since, leaving the checkpoint without what it relies on
+        if query.0.iter().take(start).any(|i| needed.contains(&i.get_dest()) && !reg.contains_key(&i.get_dest()) && !cached.contains_key(&i.get_dest())) {
+            event!(Level::WARN, "checkpoint relies on expired cached results, start over");
+            start = 0;
+            reg.clear();
+        }
+        event!(Level::DEBUG, count = cached.len(), "reuse cached results");
+        skipped = query.0.iter().map(|i| i.get_dest()).filter(|r| !needed.contains(r) || cached.contains_key(r)).collect();
+        reg.extend(cached);
+    }
+    // content filters are compiled before anything is fetched, so that a bad pattern fails the query right away
+    let mut content_regexes: HashMap<RegID, Vec<regex::Regex>> = HashMap::new();
     for inst in query.0.iter() {
+        if let Some(cs) = inst.get_constraint().filter(|cs| !cs.content.is_empty()) {
+            let regexes = cs.content.iter().map(|filter| filter.regex()).collect::<Result<Vec<_>, _>>().map_err(SolveError::InvalidRegex)?;
+            content_regexes.insert(inst.get_dest(), regexes);
+        }
+    }
+    let mut cache = SolverCache::new();
+    // once a result is incomplete, so is every result built on it
+    let mut incomplete = false;
+    let mut incomplete_regs: HashSet<RegID> = HashSet::new();
+    for (idx, inst) in query.0.iter().enumerate().skip(start) {
+        if cancel.is_cancelled() {
+            return Err(SolveError::Cancelled);
+        }
+        if skipped.contains(&inst.get_dest()) {
+            step_done(checkpoint, total, idx, inst, &reg, &incomplete_regs, None, &mut progress);
+            continue;
+        }
+        let started = Instant::now();
         match inst {
             Instruction::And { dest, op1, op2 } => {
                 let (set1, set2) = get_set_2(&reg, op1, op2)?;
@@ -43,106 +208,239 @@ pub async fn solve_api(query: &Query, default_limit: i64) -> Result<HashSet<Titl
             },
             Instruction::Link { dest, op, cs } => {
                 let set = get_set_1(&reg, op)?;
-                if set.is_empty() {
-                    reg.insert(*dest, HashSet::new());
-                } else if set.len() > 1 {
-                    return Err(SolveError::QueryForMultiplePages);
-                } else {
-                    let mut result_set: HashSet<Title> = HashSet::new();
-                    for t in set.iter() {
-                        let res_one = apisolver::get_links_one(t, cs.ns.as_ref(), cs.resolveredir.unwrap_or(false), cs.limit.unwrap_or(default_limit)).await?;
-                        result_set.extend(res_one);
-                    }
-                    reg.insert(*dest, result_set);
-                }
+                let result_set = cache.query_each(Generator::Link, cs, set, |t| {
+                    source.links(t, cs.ns.as_ref(), cs.resolveredir.unwrap_or(false), cs.limit.unwrap_or(default_limit))
+                }).await?;
+                reg.insert(*dest, result_set);
             },
             Instruction::LinkTo { dest, op, cs } => {
+                // The operand may hold any number of pages, the result is the union of each page's result.
+                // Notice that `limit` applies to each page separately.
                 let set = get_set_1(&reg, op)?;
-                if set.is_empty() {
-                    reg.insert(*dest, HashSet::new());
-                } else if set.len() > 1 {
-                    return Err(SolveError::QueryForMultiplePages);
-                } else {
-                    let mut result_set: HashSet<Title> = HashSet::new();
-                    for t in set.iter() {
-                        let res_one = apisolver::get_backlinks_one(t, cs.ns.as_ref(), !cs.directlink.unwrap_or(false), cs.redir.unwrap_or(RedirectFilterStrategy::All), cs.resolveredir.unwrap_or(false), cs.limit.unwrap_or(default_limit)).await?;
-                        result_set.extend(res_one);
-                    }
-                    reg.insert(*dest, result_set);
-                }
+                let result_set = cache.query_each(Generator::LinkTo, cs, set, |t| {
+                    source.backlinks(t, cs.ns.as_ref(), !cs.directlink.unwrap_or(false), cs.redir.unwrap_or(RedirectFilterStrategy::All), cs.resolveredir.unwrap_or(false), cs.limit.unwrap_or(default_limit))
+                }).await?;
+                reg.insert(*dest, result_set);
             },
             Instruction::EmbeddedIn { dest, op, cs } => {
                 let set = get_set_1(&reg, op)?;
-                if set.is_empty() {
-                    reg.insert(*dest, HashSet::new());
-                } else if set.len() > 1 {
-                    return Err(SolveError::QueryForMultiplePages);
-                } else {
-                    let mut result_set: HashSet<Title> = HashSet::new();
-                    for t in set.iter() {
-                        let res_one = apisolver::get_embed_one(t, cs.ns.as_ref(), cs.redir.unwrap_or(RedirectFilterStrategy::All), cs.resolveredir.unwrap_or(false), cs.limit.unwrap_or(default_limit)).await?;
-                        result_set.extend(res_one);
-                    }
-                    reg.insert(*dest, result_set);
-                }
+                let result_set = cache.query_each(Generator::EmbeddedIn, cs, set, |t| {
+                    source.embeds(t, cs.ns.as_ref(), cs.redir.unwrap_or(RedirectFilterStrategy::All), cs.resolveredir.unwrap_or(false), cs.limit.unwrap_or(default_limit))
+                }).await?;
+                reg.insert(*dest, result_set);
+            },
+            Instruction::TemplatesOn { dest, op, cs } => {
+                let set = get_set_1(&reg, op)?;
+                let result_set = cache.query_each(Generator::TemplatesOn, cs, set, |t| {
+                    source.templates(t, cs.ns.as_ref(), cs.resolveredir.unwrap_or(false), cs.limit.unwrap_or(default_limit))
+                }).await?;
+                reg.insert(*dest, result_set);
+            },
+            Instruction::FileUsage { dest, op, cs } => {
+                let set = get_set_1(&reg, op)?;
+                let result_set = cache.query_each(Generator::FileUsage, cs, set, |t| {
+                    source.file_usage(t, cs.ns.as_ref(), cs.redir.unwrap_or(RedirectFilterStrategy::All), cs.resolveredir.unwrap_or(false), cs.limit.unwrap_or(default_limit))
+                }).await?;
+                reg.insert(*dest, result_set);
+            },
+            Instruction::RedirectsTo { dest, op, cs } => {
+                let set = get_set_1(&reg, op)?;
+                let result_set = cache.query_each(Generator::RedirectsTo, cs, set, |t| {
+                    source.redirects(t, cs.ns.as_ref(), cs.limit.unwrap_or(default_limit))
+                }).await?;
+                reg.insert(*dest, result_set);
             },
             Instruction::InCat { dest, op, cs } => {
                 let set = get_set_1(&reg, op)?;
-                if set.is_empty() {
-                    reg.insert(*dest, HashSet::new());
-                } else if set.len() > 1 {
-                    return Err(SolveError::QueryForMultiplePages);
-                } else {
-                    let sub_limit = cs.depth.unwrap_or(0);
-                    let mut result_set: HashSet<Title> = HashSet::new();
-                    for t in set.iter() {
-                        let res_one = apisolver::get_category_members_one(t, cs.ns.as_ref(), sub_limit, cs.resolveredir.unwrap_or(false), cs.limit.unwrap_or(default_limit)).await?;
-                        result_set.extend(res_one);
-                    }
-                    reg.insert(*dest, result_set);
-                }
+                let sub_limit = cs.depth.unwrap_or(0);
+                let result_set = cache.query_each(Generator::InCat, cs, set, |t| {
+                    source.category_members(t, cs.ns.as_ref(), sub_limit, cs.resolveredir.unwrap_or(false), cs.added_after, cs.added_before, cs.sort, cs.limit.unwrap_or(default_limit), cancel)
+                }).await?;
+                reg.insert(*dest, result_set);
             },
-            Instruction::Toggle { dest, op } => {
+            Instruction::Toggle { dest, op, .. } => {
                 let set = get_set_1(&reg, op)?;
-                let title_set: HashSet<Title> = set.iter().cloned().map(|title| title.into_toggle_talk()).collect();
+                // special and interwiki pages have no talk pages, so they are left as they are
+                let title_set: HashSet<Title> = set.iter().cloned().map(|title| if title.namespace_id() < 0 { title } else { title.into_toggle_talk() }).collect();
                 reg.insert(*dest, title_set);
             },
+            Instruction::ResolveRedirect { dest, op } => {
+                let set = get_set_1(&reg, op)?;
+                let title_set = source.resolve_redirects(set).await?;
+                reg.insert(*dest, title_set);
+            },
+            Instruction::LangLinks { dest, op, .. } => {
+                let set = get_set_1(&reg, op)?;
+                let result_set = cache.query_each(Generator::LangLinks, &SetConstraint::new(), set, |t| {
+                    source.lang_links(t)
+                }).await?;
+                reg.insert(*dest, result_set);
+            },
             Instruction::Prefix { dest, op, cs } => {
                 let set = get_set_1(&reg, op)?;
-                if set.is_empty() {
-                    reg.insert(*dest, HashSet::new());
-                } else if set.len() > 1 {
-                    return Err(SolveError::QueryForMultiplePages);
-                } else {
-                    let mut result_set: HashSet<Title> = HashSet::new();
-                    for t in set.iter() {
-                        let res_one = apisolver::get_prefix_index_one(t, cs.ns.as_ref(), cs.redir.unwrap_or(RedirectFilterStrategy::All), cs.limit.unwrap_or(default_limit)).await?;
-                        result_set.extend(res_one);
-                    }
-                    reg.insert(*dest, result_set);
+                let result_set = cache.query_each(Generator::Prefix, cs, set, |t| {
+                    source.prefix_index(t, cs.ns.as_ref(), cs.redir.unwrap_or(RedirectFilterStrategy::All), cs.protect.as_ref(), cs.limit.unwrap_or(default_limit))
+                }).await?;
+                reg.insert(*dest, result_set);
+            },
+            Instruction::Complement { dest, op, cs } => {
+                let set = get_set_1(&reg, op)?;
+                let mut ns_vec = Vec::from_iter(cs.ns.iter().flatten().copied());
+                ns_vec.sort_unstable();
+                event!(Level::WARN, namespaces = ?ns_vec, "taking the complement enumerates every page in the namespaces, this may take very long");
+                // same as `AllPages`
+                let min_size = cs.size_gt.map(|s| s + 1);
+                let max_size = cs.size_lt.map(|s| s.saturating_sub(1));
+                let mut title_set: HashSet<Title> = HashSet::new();
+                for ns in ns_vec {
+                    title_set.extend(source.all_pages(ns, cs.redir.unwrap_or(RedirectFilterStrategy::All), cs.protect.as_ref(), min_size, max_size, cs.limit.unwrap_or(default_limit)).await?);
+                }
+                title_set.retain(|t| !set.contains(t));
+                reg.insert(*dest, title_set);
+            },
+            Instruction::Limit { dest, op, count } => {
+                // sort by full title before truncating, so that the same titles are kept across runs
+                let set = get_set_1(&reg, op)?;
+                let mut title_vec: Vec<(String, &Title)> = Vec::with_capacity(set.len());
+                for t in set.iter() {
+                    let name = source.full_name(t).await?.unwrap_or_else(|| t.pretty().to_string());
+                    title_vec.push((name, t));
                 }
+                title_vec.sort_by(|(a_name, a), (b_name, b)| a_name.cmp(b_name).then_with(|| a.namespace_id().cmp(&b.namespace_id())));
+                let title_set: HashSet<Title> = title_vec.into_iter().take(*count).map(|(_, t)| t.clone()).collect();
+                reg.insert(*dest, title_set);
             },
             Instruction::Set { dest, titles, cs } => {
+                let mut title_set = source.normalize_titles(titles, cs.keep_fragment.unwrap_or(false)).await?;
+                if let Some(nss) = &cs.ns {
+                    title_set.retain(|title| nss.contains(&title.namespace_id()));
+                }
+                reg.insert(*dest, title_set);
+            },
+            Instruction::Search { dest, query, what, cs } => {
+                let title_set = source.search(query, cs.ns.as_ref(), *what, cs.resolveredir.unwrap_or(false), cs.limit.unwrap_or(default_limit)).await?;
+                reg.insert(*dest, title_set);
+            },
+            Instruction::ExtLink { dest, url, cs } => {
+                let title_set = source.ext_link_usage(url, cs.ns.as_ref(), cs.resolveredir.unwrap_or(false), cs.limit.unwrap_or(default_limit)).await?;
+                reg.insert(*dest, title_set);
+            },
+            Instruction::MissingParam { dest, template, param, cs } => {
+                event!(Level::DEBUG, template = template.as_str(), "scanning the wikitext of every page embedding the template, this may take long");
                 let mut title_set: HashSet<Title> = HashSet::new();
-                for t in titles {
-                    let title: Title = API_SERVICE.title_new_from_full(t).await?;
-                    if let Some(nss) = &cs.ns {
-                        if !nss.contains(&title.namespace_id()) {
-                            continue;
-                        }
-                    }
-                    title_set.insert(title);
+                for t in source.normalize_titles(std::slice::from_ref(template), false).await? {
+                    title_set.extend(source.embeds_missing_param(&t, param, cs.ns.as_ref(), cs.limit.unwrap_or(default_limit)).await?);
+                }
+                reg.insert(*dest, title_set);
+            },
+            Instruction::WithProp { dest, prop, cs } => {
+                let title_set = source.pages_with_prop(prop, cs.ns.as_ref(), cs.resolveredir.unwrap_or(false), cs.limit.unwrap_or(default_limit)).await?;
+                reg.insert(*dest, title_set);
+            },
+            Instruction::AllPages { dest, ns, cs } => {
+                if *ns == 0 {
+                    event!(Level::WARN, "enumerating every page in the main namespace, this may take very long");
                 }
+                // the size bounds are exclusive in constraints, but inclusive in the API
+                // passing them on only saves bandwidth, the post filter still checks the result
+                let min_size = cs.size_gt.map(|s| s + 1);
+                let max_size = cs.size_lt.map(|s| s.saturating_sub(1));
+                let mut title_set = source.all_pages(*ns, cs.redir.unwrap_or(RedirectFilterStrategy::All), cs.protect.as_ref(), min_size, max_size, cs.limit.unwrap_or(default_limit)).await?;
+                // redirects cannot be resolved together with an allpages generator
+                if cs.resolveredir.unwrap_or(false) {
+                    title_set = source.resolve_redirects(&title_set).await?;
+                }
+                reg.insert(*dest, title_set);
+            },
+            Instruction::UserContribs { dest, user, created, cs } => {
+                let title_set = source.user_contribs(user, cs.ns.as_ref(), *created, cs.resolveredir.unwrap_or(false), cs.limit.unwrap_or(default_limit)).await?;
                 reg.insert(*dest, title_set);
             },
+            Instruction::RecentChanges { dest, hours, kind, cs } => {
+                if *hours > apisolver::RECENT_CHANGES_MAX_AGE {
+                    event!(Level::WARN, hours, "recent changes are usually not kept this long, older changes are not listed");
+                }
+                let title_set = source.recent_changes(*hours, *kind, cs.ns.as_ref(), cs.redir.unwrap_or(RedirectFilterStrategy::All), cs.resolveredir.unwrap_or(false), cs.limit.unwrap_or(default_limit)).await?;
+                reg.insert(*dest, title_set);
+            },
+            Instruction::DoubleRedirects { dest, cs } => {
+                let mut title_set = source.double_redirects(cs.ns.as_ref(), cs.limit.unwrap_or(default_limit)).await?;
+                if cs.resolveredir.unwrap_or(false) {
+                    title_set = source.resolve_redirects(&title_set).await?;
+                }
+                reg.insert(*dest, title_set);
+            },
+            Instruction::BrokenRedirects { dest, cs } => {
+                let mut title_set = source.broken_redirects(cs.ns.as_ref(), cs.limit.unwrap_or(default_limit)).await?;
+                if cs.resolveredir.unwrap_or(false) {
+                    title_set = source.resolve_redirects(&title_set).await?;
+                }
+                reg.insert(*dest, title_set);
+            },
+            Instruction::ProtectedTitles { dest, level, cs } => {
+                let title_set = source.protected_titles(level.as_deref(), cs.ns.as_ref(), cs.limit.unwrap_or(default_limit)).await?;
+                reg.insert(*dest, title_set);
+            },
+            Instruction::Count { dest, op } => {
+                // the set is passed through as is, it is up to the caller to only report its size
+                let set = get_set_1(&reg, op)?;
+                reg.insert(*dest, set.clone());
+            },
             Instruction::Nop { dest, op } => {
                 let set = get_set_1(&reg, op)?;
                 let copiedset = set.clone();
                 reg.insert(*dest, copiedset);
             },
         }
+        // post filters work the same on every instruction, so they are applied here rather than in each arm
+        // foreign pages are dropped where they come in, i.e. by every generator, before they reach any set operation, unless asked for
+        if inst.is_generator() && !inst.get_constraint().and_then(|cs| cs.keep_foreign).unwrap_or(false) {
+            if let Some(set) = reg.get_mut(&inst.get_dest()) {
+                set.retain(filter::is_local);
+            }
+        }
+        if let Some(cs) = inst.get_constraint() {
+            if cs.has_post_filter() {
+                let dest = inst.get_dest();
+                let set = reg.remove(&dest).unwrap_or_default();
+                reg.insert(dest, filter::apply(source, cs, content_regexes.get(&dest).map(Vec::as_slice).unwrap_or_default(), set).await?);
+            }
+        }
+        let failures = source.take_incomplete();
+        if !failures.is_empty() {
+            event!(Level::WARN, instruction = ?inst, errors = ?failures, "query fails mid-way, go on with the results gathered so far");
+            incomplete = true;
+        }
+        if incomplete {
+            incomplete_regs.insert(inst.get_dest());
+        }
+        if let (Some(result_cache), false) = (result_cache, incomplete) {
+            if let (true, Some(fp), Some(set)) = (resultcache::is_cacheable(inst), fingerprints.get(&inst.get_dest()), reg.get(&inst.get_dest())) {
+                result_cache.save(fp, set);
+            }
+        }
+        let elapsed = started.elapsed();
+        let size = reg.get(&inst.get_dest()).map(|set| set.len()).unwrap_or(0);
+        event!(Level::DEBUG, reg = inst.get_dest(), kind = inst.kind(), size, elapsed_ms = elapsed.as_millis() as u64, "instruction done");
+        step_done(checkpoint, total, idx, inst, &reg, &incomplete_regs, Some(elapsed), &mut progress);
     }
 
     let result = get_set_1(&reg, &query.1)?;
+    if let Some(path) = checkpoint {
+        checkpoint::clear(path);
+    }
     Ok(result.clone())
 }
+
+/// Save the checkpoint and report the progress after the instruction at `idx` is done, taking `elapsed` if it was run.
+/// `incomplete` are the registers whose results are incomplete so far.
+#[allow(clippy::too_many_arguments)]
+fn step_done(checkpoint: Option<&Path>, total: usize, idx: usize, inst: &Instruction, reg: &Register, incomplete: &HashSet<RegID>, elapsed: Option<Duration>, progress: &mut Option<ProgressCallback<'_>>) {
+    if let Some(path) = checkpoint {
+        checkpoint::save(path, total, idx + 1, reg, incomplete);
+    }
+    if let Some(progress) = progress.as_mut() {
+        let size = reg.get(&inst.get_dest()).map(|set| set.len()).unwrap_or(0);
+        progress(&SolveProgress { step: idx + 1, total, instruction: inst, size, elapsed });
+    }
+}
@@ -0,0 +1,110 @@
+//! The result cache lets a solve reuse what an earlier solve has fetched from the wiki.
+//!
+//! Unlike a checkpoint, which belongs to one query and is removed once the query is solved,
+//! the result of every instruction that queries the wiki is kept in the cache directory until it expires.
+//! A result is looked up by the fingerprint of the instruction, which covers the instruction and everything below it,
+//! so the same subquery is shared by every query, and an edited subquery never picks up a stale result.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use md5::{Md5, Digest};
+use mediawiki::title::Title;
+use tracing::{event, Level};
+
+use crate::parser::{Query, ir::{Instruction, RegID}};
+use super::checkpoint::{titles_from_plain, titles_to_plain};
+
+/// A directory of instruction results, each valid for `ttl` after it is saved.
+pub struct ResultCache {
+    dir: PathBuf,
+    ttl: Duration,
+}
+
+impl ResultCache {
+    /// Open the cache in `dir`, and remove the results that have expired.
+    pub fn new(dir: &Path, ttl: Duration) -> Self {
+        let cache = ResultCache { dir: dir.to_owned(), ttl };
+        cache.prune();
+        cache
+    }
+
+    fn path(&self, fingerprint: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", fingerprint))
+    }
+
+    fn is_expired(&self, path: &Path) -> bool {
+        fs::metadata(path).and_then(|m| m.modified())
+            .map(|saved| SystemTime::now().duration_since(saved).map(|age| age > self.ttl).unwrap_or(false))
+            .unwrap_or(true)
+    }
+
+    /// Remove every expired result. Failure to remove one is not fatal, it is simply never used.
+    fn prune(&self) {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+        for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+            if path.extension().map(|ext| ext == "json").unwrap_or(false) && self.is_expired(&path) {
+                _ = fs::remove_file(&path);
+            }
+        }
+    }
+
+    /// Load the result saved under `fingerprint`. Returns `None` if there is none, or it has expired.
+    pub(crate) fn load(&self, fingerprint: &str) -> Option<HashSet<Title>> {
+        let path = self.path(fingerprint);
+        if self.is_expired(&path) {
+            return None;
+        }
+        let content = fs::read_to_string(&path).ok()?;
+        match serde_json::from_str(&content) {
+            Ok(titles) => Some(titles_from_plain(titles)),
+            Err(e) => {
+                event!(Level::WARN, error = ?e, path = ?path, "cannot parse cached result, ignore");
+                None
+            },
+        }
+    }
+
+    /// Save a result under `fingerprint`. Failure to save is logged but not fatal, the result is simply fetched again next time.
+    pub(crate) fn save(&self, fingerprint: &str, titles: &HashSet<Title>) {
+        let path = self.path(fingerprint);
+        let write_result = serde_json::to_string(&titles_to_plain(titles))
+            .map_err(std::io::Error::from)
+            .and_then(|content| {
+                fs::create_dir_all(&self.dir)?;
+                // Write to a temporary file first, so that a crash never leaves a half-written result
+                let tmp_path = path.with_extension("tmp");
+                fs::write(&tmp_path, content)?;
+                fs::rename(&tmp_path, &path)
+            });
+        if let Err(e) = write_result {
+            event!(Level::WARN, error = ?e, path = ?path, "cannot save result to cache");
+        }
+    }
+}
+
+/// Whether the result of an instruction is worth caching, i.e. the instruction queries the wiki.
+/// Set operations and the like are cheap to redo from their operands.
+pub(crate) fn is_cacheable(inst: &Instruction) -> bool {
+    inst.is_generator()
+}
+
+/// The fingerprint of every instruction in `query`, by `dest`.
+/// It covers the instruction without its registers, the fingerprints of its operands, and the default limit, which most instructions fall back to.
+pub(crate) fn fingerprints(query: &Query, default_limit: i64) -> HashMap<RegID, String> {
+    let mut fingerprints: HashMap<RegID, String> = HashMap::new();
+    for inst in query.0.iter() {
+        let mut hasher = Md5::new();
+        hasher.update(format!("{}\n{:?}", default_limit, inst.without_registers()));
+        for op in inst.get_operands() {
+            hasher.update(format!("\n{}", fingerprints.get(&op).map(|s| s.as_str()).unwrap_or("")));
+        }
+        fingerprints.insert(inst.get_dest(), hex::encode(hasher.finalize()));
+    }
+    fingerprints
+}
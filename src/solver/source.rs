@@ -0,0 +1,221 @@
+//! The solver never talks to the wiki directly, but through a `PageSource`.
+//!
+//! `ApiSource` is the only source for now, which queries the live wiki via an `APIService`.
+//! Any other implementation, e.g. an in-memory one, can be plugged into the solver
+//! to run queries without network access.
+
+use std::collections::{HashMap, HashSet};
+
+use futures::future::BoxFuture;
+use mediawiki::{api::NamespaceID, title::Title};
+use tokio_util::sync::CancellationToken;
+
+use crate::parser::ir::{DepthNum, RedirectFilterStrategy, ProtectionFilter, SearchWhat, CategorySort, RecentChangeType, Timestamp};
+use crate::apiservice::{APIService, APIServiceError};
+use super::{apisolver::{self, Incomplete}, error::SolveError};
+
+/// Information about a page, used by post filters.
+/// `protection` is only filled in if asked for.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PageInfo {
+    /// Page size in bytes.
+    pub size: u64,
+    /// When the page was last touched.
+    pub touched: Option<Timestamp>,
+    /// Protections on the page.
+    pub protection: Vec<PageProtection>,
+    /// Whether the page is a redirect.
+    pub redirect: bool,
+    /// The page ID.
+    pub page_id: u64,
+}
+
+/// A single protection on a page.
+#[derive(Debug, Clone)]
+pub(crate) struct PageProtection {
+    /// What the protection is about, e.g. `edit` or `move`.
+    pub prtype: String,
+    /// The protection level, e.g. `sysop`.
+    pub level: String,
+    /// When the protection expires. `None` means it never expires.
+    pub expiry: Option<Timestamp>,
+}
+
+/// The type of a file, used by the media type filter.
+#[derive(Debug, Clone)]
+pub(crate) struct FileMedia {
+    /// The media type, e.g. `BITMAP` or `VIDEO`.
+    pub media_type: String,
+    /// The MIME type, e.g. `image/png`.
+    pub mime: String,
+}
+
+/// A source of pages. Each method corresponds to one generator, and works on a single page, except `search`.
+/// Refer to the functions in `apisolver` for the meaning of each parameter.
+pub(crate) trait PageSource: Sync {
+    /// Pages linked from `title`.
+    fn links<'a>(&'a self, title: &'a Title, ns: Option<&'a HashSet<NamespaceID>>, follow_redir: bool, limit: i64) -> BoxFuture<'a, Result<HashSet<Title>, SolveError>>;
+    /// Pages linking to `title`.
+    fn backlinks<'a>(&'a self, title: &'a Title, ns: Option<&'a HashSet<NamespaceID>>, level_2: bool, redirect_strat: RedirectFilterStrategy, follow_redir: bool, limit: i64) -> BoxFuture<'a, Result<HashSet<Title>, SolveError>>;
+    /// Pages transcluding `title`.
+    fn embeds<'a>(&'a self, title: &'a Title, ns: Option<&'a HashSet<NamespaceID>>, redirect_strat: RedirectFilterStrategy, follow_redir: bool, limit: i64) -> BoxFuture<'a, Result<HashSet<Title>, SolveError>>;
+    /// Pages transcluded by `title`.
+    fn templates<'a>(&'a self, title: &'a Title, ns: Option<&'a HashSet<NamespaceID>>, follow_redir: bool, limit: i64) -> BoxFuture<'a, Result<HashSet<Title>, SolveError>>;
+    /// Pages using the file `title`.
+    fn file_usage<'a>(&'a self, title: &'a Title, ns: Option<&'a HashSet<NamespaceID>>, redirect_strat: RedirectFilterStrategy, follow_redir: bool, limit: i64) -> BoxFuture<'a, Result<HashSet<Title>, SolveError>>;
+    /// Redirects to `title`.
+    fn redirects<'a>(&'a self, title: &'a Title, ns: Option<&'a HashSet<NamespaceID>>, limit: i64) -> BoxFuture<'a, Result<HashSet<Title>, SolveError>>;
+    /// Members of the category `title`.
+    #[allow(clippy::too_many_arguments)]
+    fn category_members<'a>(&'a self, title: &'a Title, ns: Option<&'a HashSet<NamespaceID>>, depth: DepthNum, follow_redir: bool, added_after: Option<Timestamp>, added_before: Option<Timestamp>, sort: Option<CategorySort>, limit: i64, cancel: &'a CancellationToken) -> BoxFuture<'a, Result<HashSet<Title>, SolveError>>;
+    /// Pages whose title starts with `title`.
+    fn prefix_index<'a>(&'a self, title: &'a Title, ns: Option<&'a HashSet<NamespaceID>>, redirect_strat: RedirectFilterStrategy, protect: Option<&'a ProtectionFilter>, limit: i64) -> BoxFuture<'a, Result<HashSet<Title>, SolveError>>;
+    /// Pages with the page property `prop`.
+    fn pages_with_prop<'a>(&'a self, prop: &'a str, ns: Option<&'a HashSet<NamespaceID>>, follow_redir: bool, limit: i64) -> BoxFuture<'a, Result<HashSet<Title>, SolveError>>;
+    /// Every page in namespace `ns`.
+    fn all_pages<'a>(&'a self, ns: NamespaceID, redirect_strat: RedirectFilterStrategy, protect: Option<&'a ProtectionFilter>, min_size: Option<u64>, max_size: Option<u64>, limit: i64) -> BoxFuture<'a, Result<HashSet<Title>, SolveError>>;
+    /// Pages edited by `user`, or only the ones created by `user` if `created` is set.
+    fn user_contribs<'a>(&'a self, user: &'a str, ns: Option<&'a HashSet<NamespaceID>>, created: bool, follow_redir: bool, limit: i64) -> BoxFuture<'a, Result<HashSet<Title>, SolveError>>;
+    /// Pages changed in the last `hours` hours, by changes of type `kind`, or of any type if `None`.
+    fn recent_changes<'a>(&'a self, hours: u64, kind: Option<RecentChangeType>, ns: Option<&'a HashSet<NamespaceID>>, redirect_strat: RedirectFilterStrategy, follow_redir: bool, limit: i64) -> BoxFuture<'a, Result<HashSet<Title>, SolveError>>;
+    /// Redirects whose target is a redirect too.
+    fn double_redirects<'a>(&'a self, ns: Option<&'a HashSet<NamespaceID>>, limit: i64) -> BoxFuture<'a, Result<HashSet<Title>, SolveError>>;
+    /// Redirects whose target does not exist.
+    fn broken_redirects<'a>(&'a self, ns: Option<&'a HashSet<NamespaceID>>, limit: i64) -> BoxFuture<'a, Result<HashSet<Title>, SolveError>>;
+    /// Titles protected from creation, at `level` if given.
+    fn protected_titles<'a>(&'a self, level: Option<&'a str>, ns: Option<&'a HashSet<NamespaceID>>, limit: i64) -> BoxFuture<'a, Result<HashSet<Title>, SolveError>>;
+    /// Pages with an external link matching `url`.
+    fn ext_link_usage<'a>(&'a self, url: &'a str, ns: Option<&'a HashSet<NamespaceID>>, follow_redir: bool, limit: i64) -> BoxFuture<'a, Result<HashSet<Title>, SolveError>>;
+    /// Pages embedding `template` that leave `param` out, or empty, in a call of it.
+    fn embeds_missing_param<'a>(&'a self, template: &'a Title, param: &'a str, ns: Option<&'a HashSet<NamespaceID>>, limit: i64) -> BoxFuture<'a, Result<HashSet<Title>, SolveError>>;
+    /// Pages matching the search `query`.
+    fn search<'a>(&'a self, query: &'a str, ns: Option<&'a HashSet<NamespaceID>>, what: SearchWhat, follow_redir: bool, limit: i64) -> BoxFuture<'a, Result<HashSet<Title>, SolveError>>;
+    /// Pages on other wikis that `title` links to via interlanguage links.
+    fn lang_links<'a>(&'a self, title: &'a Title) -> BoxFuture<'a, Result<HashSet<Title>, SolveError>>;
+    /// Replace each redirect in `titles` with its target.
+    fn resolve_redirects<'a>(&'a self, titles: &'a HashSet<Title>) -> BoxFuture<'a, Result<HashSet<Title>, SolveError>>;
+    /// The pages in `titles` whose latest wikitext passes `keep`. Missing pages are left out.
+    /// This downloads the wikitext of every page, so it is only meant for sets already narrowed down.
+    fn filter_by_content<'a>(&'a self, titles: &'a [Title], keep: &'a (dyn Fn(&str) -> bool + Sync)) -> BoxFuture<'a, Result<HashSet<Title>, SolveError>>;
+    /// Information about each page in `titles`, along with the `inprop` properties. Missing pages are left out.
+    fn page_info<'a>(&'a self, titles: &'a [Title], inprop: &'a [&'a str]) -> BoxFuture<'a, Result<HashMap<Title, PageInfo>, SolveError>>;
+    /// The type of each file in `titles`. Pages other than files, and files that were never uploaded, are left out.
+    fn media_info<'a>(&'a self, titles: &'a HashSet<Title>) -> BoxFuture<'a, Result<HashMap<Title, FileMedia>, SolveError>>;
+    /// The full title of `title`, with its namespace, or `None` if the namespace is unknown.
+    fn full_name<'a>(&'a self, title: &'a Title) -> BoxFuture<'a, Result<Option<String>, SolveError>>;
+    /// Turn the titles written in a query into `Title`s, stripping their fragments unless `keep_fragment` is set.
+    fn normalize_titles<'a>(&'a self, titles: &'a [String], keep_fragment: bool) -> BoxFuture<'a, Result<HashSet<Title>, SolveError>>;
+    /// The failures of queries that ended early since the last call, leaving their results incomplete.
+    fn take_incomplete(&self) -> Vec<APIServiceError>;
+}
+
+/// The live wiki, queried through an `APIService`.
+/// With `Incomplete`, continued queries that fail mid-way keep what was gathered so far, see `apisolver::get_continued`.
+pub(crate) struct ApiSource<'s>(pub &'s APIService, pub Option<Incomplete>);
+
+impl PageSource for ApiSource<'_> {
+    fn links<'a>(&'a self, title: &'a Title, ns: Option<&'a HashSet<NamespaceID>>, follow_redir: bool, limit: i64) -> BoxFuture<'a, Result<HashSet<Title>, SolveError>> {
+        Box::pin(apisolver::get_links_one(self.0, title, ns, follow_redir, limit, self.1.as_ref()))
+    }
+
+    fn backlinks<'a>(&'a self, title: &'a Title, ns: Option<&'a HashSet<NamespaceID>>, level_2: bool, redirect_strat: RedirectFilterStrategy, follow_redir: bool, limit: i64) -> BoxFuture<'a, Result<HashSet<Title>, SolveError>> {
+        Box::pin(apisolver::get_backlinks_one(self.0, title, ns, level_2, redirect_strat, follow_redir, limit, self.1.as_ref()))
+    }
+
+    fn embeds<'a>(&'a self, title: &'a Title, ns: Option<&'a HashSet<NamespaceID>>, redirect_strat: RedirectFilterStrategy, follow_redir: bool, limit: i64) -> BoxFuture<'a, Result<HashSet<Title>, SolveError>> {
+        Box::pin(apisolver::get_embed_one(self.0, title, ns, redirect_strat, follow_redir, limit, self.1.as_ref()))
+    }
+
+    fn templates<'a>(&'a self, title: &'a Title, ns: Option<&'a HashSet<NamespaceID>>, follow_redir: bool, limit: i64) -> BoxFuture<'a, Result<HashSet<Title>, SolveError>> {
+        Box::pin(apisolver::get_templates_one(self.0, title, ns, follow_redir, limit, self.1.as_ref()))
+    }
+
+    fn file_usage<'a>(&'a self, title: &'a Title, ns: Option<&'a HashSet<NamespaceID>>, redirect_strat: RedirectFilterStrategy, follow_redir: bool, limit: i64) -> BoxFuture<'a, Result<HashSet<Title>, SolveError>> {
+        Box::pin(apisolver::get_image_usage_one(self.0, title, ns, redirect_strat, follow_redir, limit, self.1.as_ref()))
+    }
+
+    fn redirects<'a>(&'a self, title: &'a Title, ns: Option<&'a HashSet<NamespaceID>>, limit: i64) -> BoxFuture<'a, Result<HashSet<Title>, SolveError>> {
+        Box::pin(apisolver::get_redirects_one(self.0, title, ns, limit, self.1.as_ref()))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn category_members<'a>(&'a self, title: &'a Title, ns: Option<&'a HashSet<NamespaceID>>, depth: DepthNum, follow_redir: bool, added_after: Option<Timestamp>, added_before: Option<Timestamp>, sort: Option<CategorySort>, limit: i64, cancel: &'a CancellationToken) -> BoxFuture<'a, Result<HashSet<Title>, SolveError>> {
+        Box::pin(apisolver::get_category_members_one(self.0, title, ns, depth, follow_redir, added_after, added_before, sort, limit, self.1.as_ref(), cancel))
+    }
+
+    fn prefix_index<'a>(&'a self, title: &'a Title, ns: Option<&'a HashSet<NamespaceID>>, redirect_strat: RedirectFilterStrategy, protect: Option<&'a ProtectionFilter>, limit: i64) -> BoxFuture<'a, Result<HashSet<Title>, SolveError>> {
+        Box::pin(apisolver::get_prefix_index_one(self.0, title, ns, redirect_strat, protect, limit, self.1.as_ref()))
+    }
+
+    fn pages_with_prop<'a>(&'a self, prop: &'a str, ns: Option<&'a HashSet<NamespaceID>>, follow_redir: bool, limit: i64) -> BoxFuture<'a, Result<HashSet<Title>, SolveError>> {
+        Box::pin(apisolver::get_pages_with_prop(self.0, prop, ns, follow_redir, limit, self.1.as_ref()))
+    }
+
+    fn all_pages<'a>(&'a self, ns: NamespaceID, redirect_strat: RedirectFilterStrategy, protect: Option<&'a ProtectionFilter>, min_size: Option<u64>, max_size: Option<u64>, limit: i64) -> BoxFuture<'a, Result<HashSet<Title>, SolveError>> {
+        Box::pin(apisolver::get_all_pages(self.0, ns, redirect_strat, protect, min_size, max_size, limit, self.1.as_ref()))
+    }
+
+    fn user_contribs<'a>(&'a self, user: &'a str, ns: Option<&'a HashSet<NamespaceID>>, created: bool, follow_redir: bool, limit: i64) -> BoxFuture<'a, Result<HashSet<Title>, SolveError>> {
+        Box::pin(apisolver::get_user_contributions(self.0, user, ns, created, follow_redir, limit, self.1.as_ref()))
+    }
+
+    fn recent_changes<'a>(&'a self, hours: u64, kind: Option<RecentChangeType>, ns: Option<&'a HashSet<NamespaceID>>, redirect_strat: RedirectFilterStrategy, follow_redir: bool, limit: i64) -> BoxFuture<'a, Result<HashSet<Title>, SolveError>> {
+        Box::pin(apisolver::get_recent_changes(self.0, hours, kind, ns, redirect_strat, follow_redir, limit, self.1.as_ref()))
+    }
+
+    fn double_redirects<'a>(&'a self, ns: Option<&'a HashSet<NamespaceID>>, limit: i64) -> BoxFuture<'a, Result<HashSet<Title>, SolveError>> {
+        Box::pin(apisolver::get_double_redirects(self.0, ns, limit, self.1.as_ref()))
+    }
+
+    fn broken_redirects<'a>(&'a self, ns: Option<&'a HashSet<NamespaceID>>, limit: i64) -> BoxFuture<'a, Result<HashSet<Title>, SolveError>> {
+        Box::pin(apisolver::get_broken_redirects(self.0, ns, limit, self.1.as_ref()))
+    }
+
+    fn ext_link_usage<'a>(&'a self, url: &'a str, ns: Option<&'a HashSet<NamespaceID>>, follow_redir: bool, limit: i64) -> BoxFuture<'a, Result<HashSet<Title>, SolveError>> {
+        Box::pin(apisolver::get_external_link_usage(self.0, url, ns, follow_redir, limit, self.1.as_ref()))
+    }
+
+    fn protected_titles<'a>(&'a self, level: Option<&'a str>, ns: Option<&'a HashSet<NamespaceID>>, limit: i64) -> BoxFuture<'a, Result<HashSet<Title>, SolveError>> {
+        Box::pin(apisolver::get_protected_titles(self.0, level, ns, limit, self.1.as_ref()))
+    }
+
+    fn embeds_missing_param<'a>(&'a self, template: &'a Title, param: &'a str, ns: Option<&'a HashSet<NamespaceID>>, limit: i64) -> BoxFuture<'a, Result<HashSet<Title>, SolveError>> {
+        Box::pin(apisolver::get_embed_with_param_check(self.0, template, param, ns, limit, self.1.as_ref()))
+    }
+
+    fn search<'a>(&'a self, query: &'a str, ns: Option<&'a HashSet<NamespaceID>>, what: SearchWhat, follow_redir: bool, limit: i64) -> BoxFuture<'a, Result<HashSet<Title>, SolveError>> {
+        Box::pin(apisolver::get_search(self.0, query, ns, what, follow_redir, limit, self.1.as_ref()))
+    }
+
+    fn lang_links<'a>(&'a self, title: &'a Title) -> BoxFuture<'a, Result<HashSet<Title>, SolveError>> {
+        Box::pin(apisolver::get_langlinks_one(self.0, title))
+    }
+
+    fn resolve_redirects<'a>(&'a self, titles: &'a HashSet<Title>) -> BoxFuture<'a, Result<HashSet<Title>, SolveError>> {
+        Box::pin(apisolver::resolve_redirects(self.0, titles))
+    }
+
+    fn page_info<'a>(&'a self, titles: &'a [Title], inprop: &'a [&'a str]) -> BoxFuture<'a, Result<HashMap<Title, PageInfo>, SolveError>> {
+        Box::pin(apisolver::fetch_page_info(self.0, titles, inprop))
+    }
+
+    fn full_name<'a>(&'a self, title: &'a Title) -> BoxFuture<'a, Result<Option<String>, SolveError>> {
+        Box::pin(async move { Ok(self.0.full_pretty(title).await?) })
+    }
+
+    fn filter_by_content<'a>(&'a self, titles: &'a [Title], keep: &'a (dyn Fn(&str) -> bool + Sync)) -> BoxFuture<'a, Result<HashSet<Title>, SolveError>> {
+        Box::pin(apisolver::filter_by_content(self.0, titles, keep))
+    }
+
+    fn media_info<'a>(&'a self, titles: &'a HashSet<Title>) -> BoxFuture<'a, Result<HashMap<Title, FileMedia>, SolveError>> {
+        Box::pin(apisolver::get_media_info(self.0, titles))
+    }
+
+    fn normalize_titles<'a>(&'a self, titles: &'a [String], keep_fragment: bool) -> BoxFuture<'a, Result<HashSet<Title>, SolveError>> {
+        Box::pin(apisolver::normalize_titles(self.0, titles, keep_fragment))
+    }
+
+    fn take_incomplete(&self) -> Vec<APIServiceError> {
+        self.1.as_ref().map(|partial| partial.take()).unwrap_or_default()
+    }
+}
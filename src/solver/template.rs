@@ -0,0 +1,131 @@
+//! Finding the calls of a template in wikitext, and the values passed to them.
+//!
+//! This is not a wikitext parser. A call is whatever sits between a pair of `{{` and `}}`,
+//! and its parameters are split at the `|` outside of nested calls and links.
+//! That is good enough for the usual infobox, but templates built by other templates or parser functions are not seen.
+
+use std::collections::HashSet;
+
+/// Normalizes a template name as written in a call, so that names written differently can be compared.
+/// Underscores become spaces, runs of spaces are collapsed, the namespace prefix is lower-cased,
+/// and the first letter of the rest is upper-cased.
+pub(crate) fn normalize_name(name: &str) -> String {
+    let name = name.replace('_', " ").split_whitespace().collect::<Vec<&str>>().join(" ");
+    let (prefix, rest) = match name.split_once(':') {
+        Some((prefix, rest)) => (Some(prefix.trim().to_lowercase()), rest.trim()),
+        None => (None, name.as_str()),
+    };
+    let mut chars = rest.chars();
+    let rest: String = chars.next().map(|c| c.to_uppercase().chain(chars).collect()).unwrap_or_default();
+    match prefix {
+        Some(prefix) => format!("{}:{}", prefix, rest),
+        None => rest,
+    }
+}
+
+/// Removes `<!-- -->` comments. An unclosed comment runs to the end of the text.
+fn strip_comments(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("<!--") {
+        out.push_str(&rest[..start]);
+        rest = match rest[start..].find("-->") {
+            Some(end) => &rest[start + end + 3..],
+            None => "",
+        };
+    }
+    out.push_str(rest);
+    out
+}
+
+/// The contents of the outermost `{{...}}` in `text`, without the braces.
+fn outer_calls(text: &str) -> Vec<&str> {
+    let bytes = text.as_bytes();
+    let mut calls: Vec<&str> = Vec::new();
+    let mut depth: usize = 0;
+    let mut start: usize = 0;
+    let mut i: usize = 0;
+    while i + 1 < bytes.len() {
+        if bytes[i] == b'{' && bytes[i + 1] == b'{' {
+            if depth == 0 {
+                start = i + 2;
+            }
+            depth += 1;
+            i += 2;
+        } else if bytes[i] == b'}' && bytes[i + 1] == b'}' && depth > 0 {
+            depth -= 1;
+            if depth == 0 {
+                calls.push(&text[start..i]);
+            }
+            i += 2;
+        } else {
+            i += 1;
+        }
+    }
+    calls
+}
+
+/// Splits `text` at every `sep` outside of nested calls and links.
+fn split_top_level(text: &str, sep: u8) -> Vec<&str> {
+    let bytes = text.as_bytes();
+    let mut parts: Vec<&str> = Vec::new();
+    let mut depth: usize = 0;
+    let mut start: usize = 0;
+    let mut i: usize = 0;
+    while i < bytes.len() {
+        let pair = bytes.get(i + 1).map(|&next| (bytes[i], next));
+        if matches!(pair, Some((b'{', b'{')) | Some((b'[', b'['))) {
+            depth += 1;
+            i += 2;
+        } else if matches!(pair, Some((b'}', b'}')) | Some((b']', b']'))) && depth > 0 {
+            depth -= 1;
+            i += 2;
+        } else {
+            if bytes[i] == sep && depth == 0 {
+                parts.push(&text[start..i]);
+                start = i + 1;
+            }
+            i += 1;
+        }
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+/// The value passed to `param` in the call `call`, if any. As in MediaWiki, the last one wins if it is passed more than once.
+/// `param` is either a name, or the position of an unnamed parameter, e.g. `1`.
+fn param_value<'a>(call: &'a str, param: &str) -> Option<&'a str> {
+    let mut value: Option<&str> = None;
+    let mut position: usize = 0;
+    for arg in split_top_level(call, b'|').into_iter().skip(1) {
+        let named = split_top_level(arg, b'=');
+        if named.len() > 1 {
+            if named[0].trim() == param {
+                value = Some(arg[named[0].len() + 1..].trim());
+            }
+        } else {
+            position += 1;
+            if position.to_string() == param {
+                // unnamed parameters keep their whitespace in MediaWiki, but blank is still blank
+                value = Some(arg.trim());
+            }
+        }
+    }
+    value
+}
+
+/// Whether any call in `text` of a template named one of `names` has no value, or an empty one, for `param`.
+/// `names` must be normalized by `normalize_name`. Calls nested in other calls are looked at too.
+pub(crate) fn lacks_param(text: &str, names: &HashSet<String>, param: &str) -> bool {
+    let text = strip_comments(text);
+    let param = param.trim();
+    let mut stack: Vec<&str> = outer_calls(&text);
+    while let Some(call) = stack.pop() {
+        let name = split_top_level(call, b'|')[0];
+        if names.contains(&normalize_name(name)) && param_value(call, param).map(|v| v.is_empty()).unwrap_or(true) {
+            return true;
+        }
+        stack.extend(outer_calls(call));
+    }
+    false
+}
@@ -1,12 +1,92 @@
 use super::error::SolveError;
 
+use crate::apiservice::APIServiceError;
 use crate::parser::ir::RegID;
 
 use std::collections::HashSet;
-use mediawiki::title::Title;
+use std::future::Future;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use futures::{stream, StreamExt};
+use mediawiki::{media_wiki_error::MediaWikiError, title::Title};
+use tracing::{event, Level};
 
 use super::Register;
 
+/// Maximum number of API requests one instruction may have in flight at the same time.
+pub(crate) const MAX_CONCURRENT_REQUESTS: usize = 8;
+
+/// How a failed API request is retried.
+/// The `n`-th retry waits `base_delay * 2^(n-1)`, plus a random jitter of up to the same amount.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+}
+
+/// The retry policy for the API requests made by the solver.
+pub(crate) const API_RETRY: RetryPolicy = RetryPolicy { max_retries: 3, base_delay: Duration::from_millis(500) };
+
+/// Whether a failed request is worth another try.
+/// Network failures, timeouts, unparsable responses (usually an HTML error page from a 5xx response), and a few server error codes are transient.
+/// Any other server error, such as `badtoken` or `permissiondenied`, would fail again.
+fn is_retryable(e: &APIServiceError) -> bool {
+    match e {
+        APIServiceError::Client(MediaWikiError::Reqwest(_)) |
+        APIServiceError::Client(MediaWikiError::Serde(_)) |
+        APIServiceError::Timeout(_) => true,
+        APIServiceError::Server(errobj) => {
+            let code = errobj["code"].as_str().unwrap_or("");
+            matches!(code, "readonly" | "maxlag" | "ratelimited") || code.starts_with("internal_api_error")
+        },
+        _ => false,
+    }
+}
+
+fn jitter(max: Duration) -> Duration {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos()).unwrap_or(0);
+    max.mul_f64(f64::from(nanos % 1000) / 1000.0)
+}
+
+/// Send a request by calling `request`, and send it again with exponential backoff if it fails with a retryable error.
+pub(crate) async fn retry<T, F, Fut>(policy: RetryPolicy, mut request: F) -> Result<T, APIServiceError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, APIServiceError>>,
+{
+    let mut attempt: u32 = 0;
+    loop {
+        match request().await {
+            Err(e) if attempt < policy.max_retries && is_retryable(&e) => {
+                let delay = policy.base_delay * 2u32.pow(attempt);
+                attempt += 1;
+                event!(Level::WARN, error = ?e, attempt, max_retries = policy.max_retries, "API request fails, retry");
+                tokio::time::sleep(delay + jitter(delay)).await;
+            },
+            res => return res,
+        }
+    }
+}
+
+async fn keyed<K, Fut: Future>(key: K, fut: Fut) -> (K, Fut::Output) {
+    (key, fut.await)
+}
+
+/// Drive the per-title queries in `queries`, with at most `MAX_CONCURRENT_REQUESTS` running concurrently.
+/// Each result is returned along with its key, in no particular order.
+/// Any error aborts the whole operation.
+pub(crate) async fn query_each<K, Fut>(queries: Vec<(K, Fut)>) -> Result<Vec<(K, HashSet<Title>)>, SolveError>
+where
+    Fut: Future<Output = Result<HashSet<Title>, SolveError>>,
+{
+    let queries: Vec<_> = queries.into_iter().map(|(key, fut)| keyed(key, fut)).collect();
+    let mut results = stream::iter(queries).buffer_unordered(MAX_CONCURRENT_REQUESTS);
+    let mut result_vec = Vec::new();
+    while let Some((key, res_one)) = results.next().await {
+        result_vec.push((key, res_one?));
+    }
+    Ok(result_vec)
+}
+
 pub(crate) fn get_set_1<'a>(reg: &'a Register, reg_id: &'a RegID) -> Result<&'a HashSet<Title>, SolveError> {
     let set = reg.get(reg_id);
     if let Some(s) = set {
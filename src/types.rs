@@ -17,17 +17,26 @@ impl ToString for APIAssertType {
 }
 
 #[derive(PartialEq, Eq, Clone, Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct LoginCredential {
     pub username: String,
     pub password: String,
 }
 
-#[derive(PartialEq, Eq, Clone, Debug, serde::Deserialize)]
+#[derive(PartialEq, Clone, Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct SiteProfile {
     pub api: String,
     pub db: Option<String>,
     pub login: String,
     pub assert: Option<APIAssertType>,
     pub botflag: bool,
+    pub maxlag: Option<u64>,
+    pub ratelimit: Option<f64>,
+    pub requesttimeout: Option<u64>,
+    #[serde(default)]
+    pub readonly: bool,
+    pub useragent: Option<String>,
+    pub contact: Option<String>,
     pub config: String,
 }